@@ -0,0 +1,232 @@
+//! Allocations of the steps of a home screen request that go through the
+//! children of its hubs: merging the hubs of a second server, styling every
+//! hub and filtering the items of the container. Each step runs the way it
+//! did when `children()` returned a clone of the list ("before"), and through
+//! the borrowing accessors ("after").
+//!
+//! Run with `cargo bench --bench children`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use serde_json::{json, Value};
+
+use replex::models::{ChildKind, MediaContainer, MetaData};
+use replex::transforms::merge_server_hubs;
+
+// Collection hubs on the home screen and items per hub, with `count=24`.
+const HUBS: usize = 12;
+const ITEMS: usize = 24;
+
+const ITERATIONS: u32 = 200;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[derive(Default)]
+struct Usage {
+    allocations: usize,
+    bytes: usize,
+    time: Duration,
+}
+
+// Runs `step` on a copy of `container` `ITERATIONS` times, counting only what
+// `step` itself allocates.
+fn measure(container: &MediaContainer, step: impl Fn(&mut MediaContainer)) -> Usage {
+    let mut usage = Usage::default();
+
+    for _ in 0..ITERATIONS {
+        let mut container = container.clone();
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let bytes = BYTES.load(Ordering::Relaxed);
+        let start = Instant::now();
+
+        step(&mut container);
+
+        usage.time += start.elapsed();
+        usage.allocations += ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        usage.bytes += BYTES.load(Ordering::Relaxed) - bytes;
+        drop(container);
+    }
+
+    usage.allocations /= ITERATIONS as usize;
+    usage.bytes /= ITERATIONS as usize;
+    usage.time /= ITERATIONS;
+    usage
+}
+
+// Hubs like those of `/hubs/promoted` of a server, made of the collection hub
+// and items in `tests/mock`.
+fn hubs(server: &str) -> Vec<MetaData> {
+    let fixture: Value =
+        serde_json::from_str(include_str!("../tests/mock/in/hubs_promoted_6_7.json")).unwrap();
+    let template = &fixture["MediaContainer"]["Hub"][1];
+    let items = template["Metadata"].as_array().unwrap();
+
+    (0..HUBS)
+        .map(|h| {
+            let mut hub = template.clone();
+            hub["title"] = json!(format!("Collection {}", h));
+            hub["key"] = json!(format!("/library/collections/{}{}/children", server, h));
+            hub["size"] = json!(ITEMS);
+            hub["Metadata"] = (0..ITEMS)
+                .map(|i| {
+                    let mut item = items[i % items.len()].clone();
+                    item["ratingKey"] = json!(format!("{}{}", h, i));
+                    item
+                })
+                .collect();
+            let mut hub: MetaData = serde_json::from_value(hub).unwrap();
+            hub.element = ChildKind::Hub;
+            hub
+        })
+        .collect()
+}
+
+mod before {
+    use super::*;
+
+    // `merge_server_hubs`, which cloned the children of both hubs to merge
+    // them.
+    pub fn merge(container: &mut MediaContainer, hubs: Vec<MetaData>) {
+        for hub in hubs {
+            match container
+                .iter_children_mut()
+                .find(|existing| existing.title == hub.title && existing.is_collection_hub())
+            {
+                Some(existing) => {
+                    let all_children = existing
+                        .children()
+                        .to_vec()
+                        .into_iter()
+                        .interleave(hub.children().to_vec())
+                        .collect();
+                    existing.set_children(all_children);
+                }
+                None => container.children_mut().push(hub),
+            }
+        }
+    }
+
+    // `HubStyleTransform`, which styled a clone of the children.
+    pub fn style(container: &mut MediaContainer) {
+        for hub in container.iter_children_mut() {
+            let children = hub.children().to_vec();
+            hub.set_children(children);
+        }
+    }
+
+    // `TransformBuilder::apply_to`, which removed the items that were filtered
+    // out one at a time and counted them on a clone.
+    pub fn filter(container: &mut MediaContainer) {
+        let mut i = 0;
+        while i < container.children_mut().len() {
+            if container.children_mut()[i].title.is_empty() {
+                container.children_mut().remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        container.size = Some(container.children().to_vec().len() as i64);
+    }
+}
+
+mod after {
+    use super::*;
+
+    // Also merges the keys of the hubs, which the other doesn't.
+    pub fn merge(container: &mut MediaContainer, hubs: Vec<MetaData>) {
+        merge_server_hubs(container, hubs, "4k");
+    }
+
+    pub fn style(container: &mut MediaContainer) {
+        for hub in container.iter_children_mut() {
+            let children = hub.take_children();
+            hub.set_children(children);
+        }
+    }
+
+    pub fn filter(container: &mut MediaContainer) {
+        let kept: Vec<MetaData> = container
+            .take_children()
+            .into_iter()
+            .filter(|item| !item.title.is_empty())
+            .collect();
+        *container.children_mut() = kept;
+        container.size = Some(container.children().len() as i64);
+    }
+}
+
+fn report(step: &str, before: Usage, after: Usage) {
+    println!(
+        "{:<8} {:>12} {:>12} {:>14} {:>14} {:>12?} {:>12?}",
+        step,
+        before.allocations,
+        after.allocations,
+        before.bytes,
+        after.bytes,
+        before.time,
+        after.time,
+    );
+}
+
+fn main() {
+    let mut container = MediaContainer::default();
+    *container.children_mut() = hubs("host");
+    let other = hubs("4k");
+
+    let mut merged = container.clone();
+    after::merge(&mut merged, other.clone());
+
+    println!(
+        "{} hubs of {} items from 2 servers, mean of {} runs",
+        HUBS, ITEMS, ITERATIONS
+    );
+    println!(
+        "{:<8} {:>12} {:>12} {:>14} {:>14} {:>12} {:>12}",
+        "step", "allocs old", "allocs new", "bytes old", "bytes new", "time old", "time new"
+    );
+
+    // Both count the copy of the hubs of the other server they take.
+    report(
+        "merge",
+        measure(&container, |c| before::merge(c, other.clone())),
+        measure(&container, |c| after::merge(c, other.clone())),
+    );
+    report(
+        "style",
+        measure(&merged, before::style),
+        measure(&merged, after::style),
+    );
+    report(
+        "filter",
+        measure(&merged, before::filter),
+        measure(&merged, after::filter),
+    );
+}
@@ -1,55 +1,288 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use bincode::{config, Decode, Encode};
-use moka::future::Cache as MokaCache;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use futures_util::future::{BoxFuture, Shared};
+use futures_util::{Future, FutureExt};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
-use std::sync::Arc;
-use std::time::Duration;
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use crate::config::Config;
+use crate::config::{Cache as CacheConfig, CacheStorage, Config};
 
-// Define the maximum capacity for the global cache.
-const MAX_CAPACITY: usize = 100_000;
+mod disk;
+mod memory;
 
+pub use disk::DiskBackend;
+pub use memory::MemoryBackend;
+
+/// Kinds of entries that can be given their own TTL in `cache.ttls`. A
+/// cache key starts with its family, followed by a colon.
+pub const KEY_FAMILIES: [&str; 5] = [
+    "collection",
+    "collection_children",
+    "hero_art",
+    "metadata_children",
+    "section_collections",
+];
+
+// Fraction of the TTL after which an entry is due for a refresh.
+const REFRESH_AFTER: f64 = 0.75;
+
+// Encoded entries larger than this are compressed when `cache.compression`
+// is enabled. Smaller ones rarely shrink enough to be worth it.
+const COMPRESS_ABOVE: usize = 8 * 1024;
+
+/// The cache shared by all requests. It is built from the `cache` settings at
+/// startup and kept across reloads, so its entries survive them; changes to
+/// the settings it is built from are logged as requiring a restart.
 pub static CACHE_MANAGER: Lazy<Arc<CacheManager>> = Lazy::new(|| {
     let config = Config::load();
+    let max_size = config.cache.max_size * 1024 * 1024;
 
-    // Create a new CacheManager instance with the specified capacity.
-    Arc::new(CacheManager::new(MAX_CAPACITY, config.cache.ttl))
+    let backend: Box<dyn CacheBackend> = match config.cache.storage {
+        CacheStorage::Memory => Box::new(MemoryBackend::new(max_size)),
+        CacheStorage::Disk => {
+            let disk = &config.cache.disk;
+            match DiskBackend::open(&disk.path, disk.max_size * 1024 * 1024) {
+                Ok(backend) => Box::new(backend),
+                Err(e) => {
+                    tracing::error!(
+                        error = %e,
+                        path = %disk.path,
+                        "Failed to open disk cache, falling back to memory"
+                    );
+                    Box::new(MemoryBackend::new(max_size))
+                }
+            }
+        }
+    };
+
+    Arc::new(CacheManager::new(backend, &config.cache))
 });
 
-// A wrapper around the Moka cache to provide async support and serialization.
+/// Who a cached value can be shared with. Values that don't depend on the
+/// user are stored once instead of once for every user and device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheScope {
+    /// The same for everyone, like artwork from the Plex metadata provider.
+    Global,
+    /// The same for every user of a Plex server, like the title and labels
+    /// of a collection. Plex filters what it returns by what the user may
+    /// see, so a shared value must not be what decides whether a user gets
+    /// to see something; the children of a collection are per user.
+    Server,
+    /// Specific to a user and device, like children filtered by watch state
+    /// or the collections a user has access to.
+    User,
+}
+
+/// Selects entries by their key.
+pub type KeyFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Storage for encoded cache entries.
+#[async_trait]
+pub trait CacheBackend: Debug + Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Arc<CacheEntry>>>;
+
+    async fn insert(&self, key: &str, entry: Arc<CacheEntry>) -> Result<()>;
+
+    async fn remove(&self, key: &str) -> Result<()>;
+
+    /// Removes every entry with a key that `filter` returns true for.
+    async fn remove_if(&self, filter: KeyFilter) -> Result<()>;
+
+    /// Returns the key and size in bytes of every entry.
+    async fn entries(&self) -> Result<Vec<(String, u64)>>;
+
+    async fn clear(&self) -> Result<()>;
+}
+
+// A fetch that concurrent callers for the same key wait on together. The
+// result is type erased so flights for different types share one map.
+type Flight = Shared<
+    BoxFuture<'static, Result<Arc<dyn Any + Send + Sync>, Arc<anyhow::Error>>>,
+>;
+
+#[derive(Default)]
+struct InFlight(Mutex<HashMap<String, Flight>>);
+
+impl Debug for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.0.lock().unwrap().keys().cloned().collect();
+        f.debug_tuple("InFlight").field(&keys).finish()
+    }
+}
+
+// Removes a flight from `InFlight` once its fetch is done, however it ends.
+struct FlightGuard {
+    in_flight: Arc<InFlight>,
+    key: String,
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut flights) = self.in_flight.0.lock() {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+/// Marks the refresh of a key as done when dropped, see
+/// `CacheManager::start_refresh`.
+#[derive(Debug)]
+pub struct RefreshGuard {
+    cache: Arc<CacheManager>,
+    key: String,
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        if let Ok(mut refreshing) = self.cache.refreshing.lock() {
+            refreshing.remove(&self.key);
+        }
+    }
+}
+
+/// An encoded value together with the metadata needed to expire it.
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub data: Vec<u8>,
+    // Whether `data` is deflate compressed.
+    pub compressed: bool,
+    pub inserted_at: SystemTime,
+    pub ttl: Duration,
+}
+
+impl CacheEntry {
+    fn age(&self) -> Duration {
+        // A clock that went backwards makes the entry brand new.
+        self.inserted_at.elapsed().unwrap_or_default()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.age() >= self.ttl
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.age() >= self.ttl.mul_f64(REFRESH_AFTER)
+    }
+}
+
+// Serializes values and tracks their age on top of a `CacheBackend`.
 #[derive(Debug)]
 pub struct CacheManager {
-    inner: MokaCache<String, Arc<Vec<u8>>>,
+    backend: Box<dyn CacheBackend>,
+    ttl: Duration,
+    // TTLs by key family, overriding `ttl`.
+    ttls: HashMap<String, Duration>,
+    compression: bool,
+    // Keys that are being refreshed in the background.
+    refreshing: Mutex<HashSet<String>>,
+    // Fetches that are running, by key.
+    in_flight: Arc<InFlight>,
+    // Lookups since startup, by key family.
+    counters: Mutex<HashMap<String, Counters>>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    hits: u64,
+    misses: u64,
+}
+
+/// Usage of the cache for a single key family.
+#[derive(Debug, Default, Serialize)]
+pub struct FamilyStats {
+    pub family: String,
+    pub entries: u64,
+    pub bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    // Hits out of all lookups, `None` before the first lookup.
+    pub hit_ratio: Option<f64>,
+}
+
+/// A value read from the cache.
+#[derive(Debug)]
+pub struct Cached<T> {
+    pub value: T,
+    // Whether the entry is close to expiring and should be refreshed.
+    pub stale: bool,
 }
 
 impl CacheManager {
-    /// Constructs a new `CacheManager` with a specified capacity and global TTL.
-    pub fn new(max_capacity: usize, global_ttl: u64) -> Self {
-        let cache = MokaCache::builder()
-            .max_capacity(max_capacity.try_into().unwrap())
-            .time_to_live(Duration::from_secs(global_ttl))
-            .build();
-        Self { inner: cache }
+    /// Constructs a new `CacheManager` on top of `backend`, with the TTLs and
+    /// compression set in `config`.
+    pub fn new(backend: Box<dyn CacheBackend>, config: &CacheConfig) -> Self {
+        Self {
+            backend,
+            ttl: Duration::from_secs(config.ttl),
+            ttls: config
+                .ttls
+                .iter()
+                .map(|(family, ttl)| (family.clone(), Duration::from_secs(*ttl)))
+                .collect(),
+            compression: config.compression,
+            refreshing: Mutex::default(),
+            in_flight: Arc::default(),
+            counters: Mutex::default(),
+        }
+    }
+
+    /// TTL of the entry at `key`, depending on its family.
+    pub fn ttl_for(&self, key: &str) -> Duration {
+        self.ttls.get(family(key)).copied().unwrap_or(self.ttl)
     }
 
     /// Retrieves a value from the cache, deserializing it into the desired type.
     pub async fn get<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: for<'de> Deserialize<'de> + Serialize + Debug + Decode,
+    {
+        Ok(self.get_cached(key).await?.map(|cached| cached.value))
+    }
+
+    /// Same as `get`, but also reports whether the entry is due for a
+    /// refresh.
+    pub async fn get_cached<T>(&self, key: &str) -> Result<Option<Cached<T>>>
     where
         T: for<'de> Deserialize<'de> + Serialize + Debug + Decode,
     {
         let config = config::standard();
 
-        if let Some(arc_data) = self.inner.get(key) {
-            let (decoded, _len): (T, usize) =
-                bincode::decode_from_slice(&arc_data[..], config)?;
+        let entry = match self.backend.get(key).await? {
+            Some(entry) if entry.is_expired() => {
+                self.backend.remove(key).await?;
+                None
+            }
+            entry => entry,
+        };
+        self.count(key, entry.is_some());
+
+        let Some(entry) = entry else {
+            return Ok(None);
+        };
 
-            Ok(Some(decoded))
+        let (decoded, _len): (T, usize) = if entry.compressed {
+            let mut data = vec![];
+            DeflateDecoder::new(&entry.data[..]).read_to_end(&mut data)?;
+            bincode::decode_from_slice(&data[..], config)?
         } else {
-            Ok(None)
-        }
+            bincode::decode_from_slice(&entry.data[..], config)?
+        };
+
+        Ok(Some(Cached {
+            value: decoded,
+            stale: entry.is_stale(),
+        }))
     }
 
     /// Inserts a value into the cache, serializing it from the provided type.
@@ -61,21 +294,311 @@ impl CacheManager {
 
         let encoded: Vec<u8> = bincode::encode_to_vec(value, config).unwrap();
 
-        // let bytes = bincode::serialize(value)?;
+        let (data, compressed) = match self.compress(&encoded)? {
+            Some(compressed) => (compressed, true),
+            None => (encoded, false),
+        };
+
+        let entry = CacheEntry {
+            data,
+            compressed,
+            inserted_at: SystemTime::now(),
+            ttl: self.ttl_for(key),
+        };
+        self.backend.insert(key, Arc::new(entry)).await
+    }
+
+    // Compresses `data` if compression is enabled, the data is large enough
+    // and it actually gets smaller.
+    fn compress(&self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        if !self.compression || data.len() <= COMPRESS_ABOVE {
+            return Ok(None);
+        }
+
+        let mut encoder = DeflateEncoder::new(vec![], Compression::fast());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
 
-        self.inner.insert(key.to_owned(), Arc::new(encoded)).await;
-        Ok(())
+        Ok((compressed.len() < data.len()).then_some(compressed))
+    }
+
+    /// Runs the future returned by `fetcher`, unless one is already running
+    /// for `key`. Concurrent callers then all get the result of that single
+    /// run, or the same error.
+    pub async fn single_flight<T, F, Fut>(
+        &self,
+        key: &str,
+        fetcher: F,
+    ) -> Result<T, Arc<anyhow::Error>>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let flight = {
+            let mut flights = self.in_flight.0.lock().unwrap();
+
+            match flights.get(key) {
+                Some(flight) => flight.clone(),
+                None => {
+                    let fetch = fetcher();
+                    let in_flight = self.in_flight.clone();
+                    let owned_key = key.to_owned();
+
+                    let flight = async move {
+                        // Later callers start a new fetch, or find the result
+                        // in the cache if the fetch stored it there. Also when
+                        // the fetch panics or is dropped, so the key isn't
+                        // stuck with it.
+                        let _done = FlightGuard {
+                            in_flight,
+                            key: owned_key,
+                        };
+
+                        fetch
+                            .await
+                            .map(|value| Arc::new(value) as Arc<dyn Any + Send + Sync>)
+                            .map_err(Arc::new)
+                    }
+                    .boxed()
+                    .shared();
+
+                    flights.insert(key.to_owned(), flight.clone());
+                    flight
+                }
+            }
+        };
+
+        let value = flight.await?;
+        value.downcast_ref::<T>().cloned().ok_or_else(|| {
+            Arc::new(anyhow::anyhow!("Fetch for {} returned another type", key))
+        })
+    }
+
+    /// Marks `key` as being refreshed until the returned guard is dropped.
+    /// Returns `None` when a refresh for the key is already running.
+    pub fn start_refresh(self: &Arc<Self>, key: &str) -> Option<RefreshGuard> {
+        if !self.refreshing.lock().unwrap().insert(key.to_owned()) {
+            return None;
+        }
+
+        Some(RefreshGuard {
+            cache: self.clone(),
+            key: key.to_owned(),
+        })
     }
 
     /// Deletes a value from the cache by its key.
     pub async fn delete(&self, key: &str) -> Result<()> {
-        self.inner.invalidate(key).await;
-        Ok(())
+        self.backend.remove(key).await
+    }
+
+    /// Deletes every value with a key that starts with `prefix`, e.g. all
+    /// entries of a family with `hero_art:`.
+    pub async fn delete_prefix(&self, prefix: &str) -> Result<()> {
+        let prefix = prefix.to_owned();
+        self.backend
+            .remove_if(Arc::new(move |key| key.starts_with(&prefix)))
+            .await
+    }
+
+    /// Deletes every value with a key that `filter` returns true for.
+    pub async fn delete_if(&self, filter: KeyFilter) -> Result<()> {
+        self.backend.remove_if(filter).await
+    }
+
+    /// Deletes every value cached for the user with `token`. Values of
+    /// `CacheScope::Global` and `CacheScope::Server` are kept.
+    pub async fn delete_token(&self, token: &str) -> Result<()> {
+        // Keys end in `:<token>-<client identifier>`, see
+        // `PlexClient::generate_cache_key`.
+        let pattern = format!(":{}-", token);
+        self.backend
+            .remove_if(Arc::new(move |key| key.contains(&pattern)))
+            .await
+    }
+
+    /// Returns the tokens of the users that have values cached for the
+    /// client with `client_identifier`, which is usually one.
+    pub async fn tokens_of_client(&self, client_identifier: &str) -> Result<HashSet<String>> {
+        // Keys end in `:<token>-<client identifier>`, and `@<server>` for
+        // the other servers, see `PlexClient::generate_cache_key`.
+        let suffix = format!("-{}", client_identifier);
+        let tokens = self
+            .backend
+            .entries()
+            .await?
+            .into_iter()
+            .filter_map(|(key, _)| {
+                let (_, user) = key.rsplit_once(':')?;
+                let user = user.split_once('@').map_or(user, |(user, _)| user);
+                user.strip_suffix(&suffix).map(ToOwned::to_owned)
+            })
+            .collect();
+        Ok(tokens)
+    }
+
+    /// Returns the number of entries, their size and the hit ratio for
+    /// every key family that has entries or was looked up.
+    pub async fn stats(&self) -> Result<Vec<FamilyStats>> {
+        let mut stats: HashMap<String, FamilyStats> = HashMap::new();
+
+        for (key, bytes) in self.backend.entries().await? {
+            let family = family(&key);
+            let entry = stats.entry(family.to_owned()).or_default();
+            entry.entries += 1;
+            entry.bytes += bytes;
+        }
+
+        for (family, counters) in self.counters.lock().unwrap().iter() {
+            let entry = stats.entry(family.clone()).or_default();
+            entry.hits = counters.hits;
+            entry.misses = counters.misses;
+
+            let lookups = counters.hits + counters.misses;
+            entry.hit_ratio = (lookups > 0).then(|| counters.hits as f64 / lookups as f64);
+        }
+
+        let mut stats: Vec<FamilyStats> = stats
+            .into_iter()
+            .map(|(family, stats)| FamilyStats { family, ..stats })
+            .collect();
+        stats.sort_by(|a, b| a.family.cmp(&b.family));
+
+        Ok(stats)
+    }
+
+    fn count(&self, key: &str, hit: bool) {
+        let mut counters = self.counters.lock().unwrap();
+        let counters = counters.entry(family(key).to_owned()).or_default();
+
+        if hit {
+            counters.hits += 1;
+        } else {
+            counters.misses += 1;
+        }
     }
 
     /// Clears all entries from the cache.
     pub async fn clear(&self) -> Result<()> {
-        self.inner.invalidate_all();
-        Ok(())
+        self.backend.clear().await
+    }
+}
+
+// The family of a key is everything before the first colon.
+fn family(key: &str) -> &str {
+    key.split(':').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn manager() -> Arc<CacheManager> {
+        let config: CacheConfig = serde_json::from_str("{}").unwrap();
+        Arc::new(CacheManager::new(
+            Box::new(MemoryBackend::new(1024 * 1024)),
+            &config,
+        ))
+    }
+
+    #[tokio::test]
+    async fn single_flight_runs_concurrent_fetches_once() {
+        let cache = manager();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let fetches = (0..5).map(|_| {
+            let runs = runs.clone();
+            cache.single_flight("key", move || async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(42)
+            })
+        });
+
+        for result in futures::future::join_all(fetches).await {
+            assert_eq!(result.unwrap(), 42);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn single_flight_shares_errors() {
+        let cache = manager();
+
+        let (first, second) = tokio::join!(
+            cache.single_flight::<i32, _, _>("key", || async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Err(anyhow::anyhow!("upstream down"))
+            }),
+            cache.single_flight::<i32, _, _>("key", || async { Ok(1) }),
+        );
+
+        assert_eq!(first.unwrap_err().to_string(), "upstream down");
+        assert_eq!(second.unwrap_err().to_string(), "upstream down");
+    }
+
+    #[tokio::test]
+    async fn single_flight_fetches_again_once_done() {
+        let cache = manager();
+
+        let first = cache.single_flight("key", || async { Ok(1) }).await;
+        let second = cache.single_flight("key", || async { Ok(2) }).await;
+
+        assert_eq!(first.unwrap(), 1);
+        assert_eq!(second.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn single_flight_recovers_from_a_panicking_fetch() {
+        let cache = manager();
+
+        let panicking = {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                cache
+                    .single_flight::<i32, _, _>("key", || async { panic!("fetch panicked") })
+                    .await
+            })
+        };
+        assert!(panicking.await.unwrap_err().is_panic());
+
+        let result = cache.single_flight("key", || async { Ok(3) }).await;
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_token_keeps_shared_entries() {
+        let cache = manager();
+        for key in [
+            "hero_art:global",
+            "collection:1:server",
+            "collection:1:server@4k",
+            "collection_children:1,0,10:secret-client",
+            "collection_children:1,0,10:other-client",
+        ] {
+            cache.insert(key, &1).await.unwrap();
+        }
+
+        cache.delete_token("secret").await.unwrap();
+
+        assert_eq!(cache.get::<i32>("collection:1:server").await.unwrap(), Some(1));
+        assert_eq!(cache.get::<i32>("collection:1:server@4k").await.unwrap(), Some(1));
+        assert_eq!(cache.get::<i32>("hero_art:global").await.unwrap(), Some(1));
+        assert_eq!(cache.get::<i32>("collection_children:1,0,10:secret-client").await.unwrap(), None);
+        assert_eq!(cache.get::<i32>("collection_children:1,0,10:other-client").await.unwrap(), Some(1));
+    }
+
+    #[test]
+    fn refresh_guard_releases_the_key() {
+        let cache = manager();
+
+        let refreshing = cache.start_refresh("key").unwrap();
+        assert!(cache.start_refresh("key").is_none());
+
+        drop(refreshing);
+        assert!(cache.start_refresh("key").is_some());
     }
 }
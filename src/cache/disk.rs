@@ -0,0 +1,398 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use bincode::{config, Decode, Encode};
+use data_encoding::HEXLOWER;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{CacheBackend, CacheEntry, KeyFilter};
+
+// Extension of complete entries. Files that are still being written end in
+// `.tmp` and are renamed once done.
+const EXTENSION: &str = "bin";
+const TMP_EXTENSION: &str = "tmp";
+
+/// Keeps entries as files in a directory, so they survive restarts.
+///
+/// The total size of the files is kept under `max_size` bytes by removing the
+/// oldest entries first.
+#[derive(Debug)]
+pub struct DiskBackend {
+    dir: PathBuf,
+    max_size: u64,
+    index: Mutex<Index>,
+    // Keeps temporary file names unique when a key is written concurrently.
+    writes: AtomicU64,
+}
+
+// The files in the cache directory, by name.
+#[derive(Debug, Default)]
+struct Index {
+    files: HashMap<String, IndexEntry>,
+    size: u64,
+}
+
+#[derive(Debug)]
+struct IndexEntry {
+    key: String,
+    size: u64,
+    written_at: SystemTime,
+}
+
+// What is stored in a file. The key is kept to detect hash collisions.
+#[derive(Encode, Decode)]
+struct DiskEntry {
+    key: String,
+    compressed: bool,
+    // Milliseconds since the Unix epoch.
+    inserted_at: u64,
+    // Milliseconds.
+    ttl: u64,
+    data: Vec<u8>,
+}
+
+impl DiskBackend {
+    /// Opens the cache in `dir`, creating it if needed, and indexes the
+    /// entries left by a previous run.
+    pub fn open(dir: impl AsRef<Path>, max_size: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut index = Index::default();
+        for file in fs::read_dir(&dir)? {
+            let path = file?.path();
+
+            match path.extension().and_then(|e| e.to_str()) {
+                Some(EXTENSION) => {}
+                // Left over from a write that was interrupted.
+                Some(TMP_EXTENSION) => {
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let (Some(name), Ok(metadata), Ok(key)) = (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.metadata(),
+                read_key(&path),
+            ) else {
+                continue;
+            };
+
+            index.add(
+                name.to_owned(),
+                IndexEntry {
+                    key,
+                    size: metadata.len(),
+                    written_at: metadata.modified()?,
+                },
+            );
+        }
+
+        tracing::info!(
+            entries = index.files.len(),
+            size = index.size,
+            path = %dir.display(),
+            "Opened disk cache"
+        );
+
+        for name in index.evict(max_size) {
+            let _ = fs::remove_file(dir.join(name).with_extension(EXTENSION));
+        }
+
+        Ok(Self {
+            dir,
+            max_size,
+            index: Mutex::new(index),
+            writes: AtomicU64::new(0),
+        })
+    }
+
+    fn file_name(key: &str) -> String {
+        HEXLOWER.encode(&Sha256::digest(key.as_bytes()))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name).with_extension(EXTENSION)
+    }
+
+    async fn remove_files(&self, names: Vec<String>) -> Result<()> {
+        for name in names {
+            match tokio::fs::remove_file(self.path(&name)).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CacheBackend for DiskBackend {
+    async fn get(&self, key: &str) -> Result<Option<Arc<CacheEntry>>> {
+        let name = Self::file_name(key);
+        if !self.index.lock().unwrap().files.contains_key(&name) {
+            return Ok(None);
+        }
+
+        let bytes = match tokio::fs::read(self.path(&name)).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                self.index.lock().unwrap().remove(&name);
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let (entry, _len): (DiskEntry, usize) =
+            bincode::decode_from_slice(&bytes, config::standard())?;
+        if entry.key != key {
+            return Ok(None);
+        }
+
+        Ok(Some(Arc::new(CacheEntry {
+            data: entry.data,
+            compressed: entry.compressed,
+            inserted_at: UNIX_EPOCH + Duration::from_millis(entry.inserted_at),
+            ttl: Duration::from_millis(entry.ttl),
+        })))
+    }
+
+    async fn insert(&self, key: &str, entry: Arc<CacheEntry>) -> Result<()> {
+        let inserted_at = entry.inserted_at.duration_since(UNIX_EPOCH)?;
+        let encoded = bincode::encode_to_vec(
+            DiskEntry {
+                key: key.to_owned(),
+                compressed: entry.compressed,
+                inserted_at: inserted_at.as_millis() as u64,
+                ttl: entry.ttl.as_millis() as u64,
+                data: entry.data.clone(),
+            },
+            config::standard(),
+        )?;
+
+        let name = Self::file_name(key);
+        let write = self.writes.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = self
+            .dir
+            .join(format!("{}.{}", name, write))
+            .with_extension(TMP_EXTENSION);
+
+        let written = match tokio::fs::write(&tmp_path, &encoded).await {
+            Ok(()) => tokio::fs::rename(&tmp_path, self.path(&name)).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(e.into());
+        }
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.add(
+                name,
+                IndexEntry {
+                    key: key.to_owned(),
+                    size: encoded.len() as u64,
+                    written_at: SystemTime::now(),
+                },
+            );
+            index.evict(self.max_size)
+        };
+
+        self.remove_files(evicted).await
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        let name = Self::file_name(key);
+        self.index.lock().unwrap().remove(&name);
+        self.remove_files(vec![name]).await
+    }
+
+    async fn remove_if(&self, filter: KeyFilter) -> Result<()> {
+        let names = {
+            let mut index = self.index.lock().unwrap();
+            let names: Vec<String> = index
+                .files
+                .iter()
+                .filter(|(_, entry)| filter(&entry.key))
+                .map(|(name, _)| name.clone())
+                .collect();
+
+            for name in &names {
+                index.remove(name);
+            }
+            names
+        };
+
+        self.remove_files(names).await
+    }
+
+    async fn entries(&self) -> Result<Vec<(String, u64)>> {
+        let index = self.index.lock().unwrap();
+        Ok(index
+            .files
+            .values()
+            .map(|entry| (entry.key.clone(), entry.size))
+            .collect())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let names = {
+            let mut index = self.index.lock().unwrap();
+            let names = index.files.drain().map(|(name, _)| name).collect();
+            index.size = 0;
+            names
+        };
+
+        self.remove_files(names).await
+    }
+}
+
+// Reads only the key at the start of an entry file, so indexing doesn't
+// have to load the data.
+fn read_key(path: &Path) -> Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(bincode::decode_from_std_read(&mut reader, config::standard())?)
+}
+
+impl Index {
+    fn add(&mut self, name: String, entry: IndexEntry) {
+        self.size += entry.size;
+        if let Some(previous) = self.files.insert(name, entry) {
+            self.size -= previous.size;
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some(entry) = self.files.remove(name) {
+            self.size -= entry.size;
+        }
+    }
+
+    // Drops the oldest entries until the total size fits in `max_size`, and
+    // returns their names so the files can be removed.
+    fn evict(&mut self, max_size: u64) -> Vec<String> {
+        if self.size <= max_size {
+            return vec![];
+        }
+
+        let mut by_age: Vec<(String, SystemTime)> = self
+            .files
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.written_at))
+            .collect();
+        by_age.sort_by_key(|(_, written_at)| *written_at);
+
+        let mut evicted = vec![];
+        for (name, _) in by_age {
+            if self.size <= max_size {
+                break;
+            }
+            self.remove(&name);
+            evicted.push(name);
+        }
+
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::cache::CacheManager;
+    use crate::config::Cache as CacheConfig;
+
+    use super::*;
+
+    fn entry(data: &[u8]) -> Arc<CacheEntry> {
+        Arc::new(CacheEntry {
+            data: data.to_vec(),
+            compressed: false,
+            inserted_at: SystemTime::now(),
+            ttl: Duration::from_secs(60),
+        })
+    }
+
+    fn files(dir: &TempDir) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|file| file.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn entries_survive_reopen() {
+        let dir = TempDir::new().unwrap();
+        let cache = DiskBackend::open(dir.path(), 1024 * 1024).unwrap();
+        cache.insert("collection:1", entry(b"one")).await.unwrap();
+        let size = cache.entries().await.unwrap()[0].1;
+        drop(cache);
+
+        let cache = DiskBackend::open(dir.path(), 1024 * 1024).unwrap();
+        assert_eq!(
+            cache.entries().await.unwrap(),
+            [("collection:1".to_string(), size)]
+        );
+        let entry = cache.get("collection:1").await.unwrap().unwrap();
+        assert_eq!(entry.data, b"one");
+        assert_eq!(entry.ttl, Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn evicts_oldest_entries_past_max_size() {
+        let dir = TempDir::new().unwrap();
+        let cache = DiskBackend::open(dir.path(), 2500).unwrap();
+
+        for key in ["a", "b", "c"] {
+            cache.insert(key, entry(&[0; 1000])).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mut keys: Vec<String> =
+            cache.entries().await.unwrap().into_iter().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(keys, ["b", "c"]);
+        assert!(cache.get("a").await.unwrap().is_none());
+        assert_eq!(files(&dir).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_removed_on_get() {
+        let dir = TempDir::new().unwrap();
+        let config: CacheConfig = serde_json::from_str(r#"{"ttl": 0}"#).unwrap();
+        let backend = DiskBackend::open(dir.path(), 1024 * 1024).unwrap();
+        let cache = CacheManager::new(Box::new(backend), &config);
+
+        cache.insert("collection:1", &1u32).await.unwrap();
+        assert_eq!(files(&dir).len(), 1);
+
+        assert_eq!(cache.get::<u32>("collection:1").await.unwrap(), None);
+        assert!(files(&dir).is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_write_leaves_no_tmp_file() {
+        let dir = TempDir::new().unwrap();
+        let cache = DiskBackend::open(dir.path(), 1024 * 1024).unwrap();
+
+        // A directory where the entry goes makes the rename fail.
+        let path = cache.path(&DiskBackend::file_name("collection:1"));
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("file"), b"").unwrap();
+
+        assert!(cache.insert("collection:1", entry(b"one")).await.is_err());
+        assert_eq!(files(&dir), [path.file_name().unwrap().to_string_lossy()]);
+        assert!(cache.entries().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use moka::future::Cache as MokaCache;
+use moka::Expiry;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{CacheBackend, CacheEntry, KeyFilter};
+
+/// Keeps entries in memory, they are lost on restart.
+#[derive(Debug)]
+pub struct MemoryBackend {
+    inner: MokaCache<String, Arc<CacheEntry>>,
+}
+
+impl MemoryBackend {
+    /// Constructs a new `MemoryBackend` holding at most `max_size` bytes of
+    /// keys and entries.
+    pub fn new(max_size: u64) -> Self {
+        let cache = MokaCache::builder()
+            .max_capacity(max_size)
+            .weigher(|key: &String, entry: &Arc<CacheEntry>| {
+                (key.len() + entry.data.len()).try_into().unwrap_or(u32::MAX)
+            })
+            .expire_after(EntryExpiry)
+            .support_invalidation_closures()
+            .build();
+        Self { inner: cache }
+    }
+}
+
+// Expires entries after their own TTL.
+struct EntryExpiry;
+
+impl Expiry<String, Arc<CacheEntry>> for EntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        entry: &Arc<CacheEntry>,
+        _current_time: Instant,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &Arc<CacheEntry>,
+        _current_time: Instant,
+        _current_duration: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+#[async_trait]
+impl CacheBackend for MemoryBackend {
+    async fn get(&self, key: &str) -> Result<Option<Arc<CacheEntry>>> {
+        Ok(self.inner.get(key))
+    }
+
+    async fn insert(&self, key: &str, entry: Arc<CacheEntry>) -> Result<()> {
+        self.inner.insert(key.to_owned(), entry).await;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        self.inner.invalidate(key).await;
+        Ok(())
+    }
+
+    async fn remove_if(&self, filter: KeyFilter) -> Result<()> {
+        self.inner.invalidate_entries_if(move |key, _| filter(key))?;
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<(String, u64)>> {
+        Ok(self
+            .inner
+            .iter()
+            .map(|(key, entry)| (key.to_string(), entry.data.len() as u64))
+            .collect())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.inner.invalidate_all();
+        Ok(())
+    }
+}
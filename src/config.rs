@@ -1,25 +1,76 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use arc_swap::ArcSwap;
 use figment::util::bool_from_str_or_int;
-use figment::{
-    providers::{Env, Format, Yaml},
-    Figment,
-};
+use figment::providers::Serialized;
 use nestify::nest;
-use once_cell::sync::Lazy;
-use serde::{self, Deserialize};
+use once_cell::sync::OnceCell;
+use salvo::Request;
+use serde::{self, Deserialize, Serialize};
 
 use crate::deserializers::{
     deserialize_comma_separated, deserialize_host,
     vec_from_comma_separated_or_list,
 };
+use crate::plex::models::PlexContext;
+
+#[cfg(test)]
+mod fixtures;
+mod hub_rule;
+mod pipeline;
+mod profile;
+mod server;
+mod source;
+mod validation;
+mod watcher;
+
+pub use hub_rule::{HubMatch, HubRule, HubTarget, TitlePattern};
+pub use pipeline::PipelineStep;
+pub use profile::{Profile, ProfileMatch, ProfileTarget};
+pub use server::Server;
+pub use source::config_path;
+pub use validation::{check_host, Issue, Report, Severity};
+pub use watcher::watch;
+
+/// Default location of the config file, relative to the working directory.
+/// See `config_path` for how to use another file.
+pub const CONFIG_PATH: &str = "config/config.yml";
+
+static INSTANCE: OnceCell<ArcSwap<Config>> = OnceCell::new();
 
 nest! {
-#[derive(Debug, PartialEq, Deserialize)]*
+#[derive(Debug, PartialEq, Deserialize, Serialize)]*
 pub struct Config {
     #[serde(deserialize_with = "deserialize_host")]
     pub host: String,
 
+    // Plex servers to fall back to, in order, while `host` is down.
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub standby_hosts: Option<Vec<String>>,
+
+    // Seconds between checks of whether the hosts are up.
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval: u64,
+
+    // Other Plex servers, whose collection hubs are merged into the ones of
+    // `host` on the home screen and library pages.
+    #[serde(default)]
+    pub servers: Vec<Server>,
+
     pub port: Option<u64>,
 
+    pub rust_log: Option<String>,
+
+    // Token of the server owner, used to listen to library and playback
+    // notifications.
+    pub plex_token: Option<String>,
+
+    // Bearer token for the `/replex/admin` endpoints, which are disabled
+    // without it.
+    pub admin_token: Option<String>,
+
     pub better_on_deck: pub struct OnDeck {
         #[serde(default, deserialize_with = "bool_from_str_or_int")]
         pub enabled: bool,
@@ -34,6 +85,48 @@ pub struct Config {
         pub ttl: u64,
         #[serde(default = "as_true", deserialize_with = "bool_from_str_or_int")]
         pub auto_refresh: bool,
+        // TTLs by key family, e.g. `hero_art`, overriding `ttl`.
+        #[serde(default)]
+        pub ttls: BTreeMap<String, u64>,
+        // Size of the memory cache, in megabytes.
+        #[serde(default = "default_cache_size")]
+        pub max_size: u64,
+        #[serde(default, deserialize_with = "bool_from_str_or_int")]
+        pub compression: bool,
+        #[serde(default)]
+        pub storage: CacheStorage,
+        #[serde(default)]
+        pub disk: pub struct DiskCache {
+            #[serde(default = "default_disk_cache_path")]
+            pub path: String,
+            // In megabytes.
+            #[serde(default = "default_disk_cache_size")]
+            pub max_size: u64,
+        },
+    },
+
+    // Requests from Replex to Plex. Proxied requests are passed through as
+    // they are.
+    #[serde(default)]
+    pub http: pub struct Http {
+        // In seconds.
+        #[serde(default = "default_http_timeout")]
+        pub timeout: u64,
+        // How often GET requests that failed with a connection error or a
+        // server error are retried.
+        #[serde(default = "default_http_retries")]
+        pub retries: u32,
+        // Bounds of the delay before a retry, in milliseconds. The delay
+        // doubles with every retry, and a random part of it is used.
+        #[serde(default = "default_http_retry_min_delay")]
+        pub retry_min_delay: u64,
+        #[serde(default = "default_http_retry_max_delay")]
+        pub retry_max_delay: u64,
+        // How many items of a response are transformed at once. Some
+        // transforms, like hero rows and excluding watched items, make a
+        // request to Plex per hub.
+        #[serde(default = "default_http_concurrency")]
+        pub concurrency: usize,
     },
 
     pub exclude_watched: pub struct ExcludeWatched {
@@ -55,6 +148,10 @@ pub struct Config {
     #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
     pub priority_hubs: Option<Vec<String>>,
 
+    // Changes to the hubs that match, see `HubRule`.
+    #[serde(default)]
+    pub hub_rules: Vec<HubRule>,
+
     #[serde(default, deserialize_with = "bool_from_str_or_int")]
     pub disable_user_state: bool,
 
@@ -79,32 +176,400 @@ pub struct Config {
     #[serde(default, deserialize_with = "deserialize_comma_separated")]
     pub force_direct_play_for: Option<Vec<String>>,
 
+    // Not used anymore, see `scripts`.
     pub test_script: Option<String>,
+
+    // Rhai scripts that change the responses of some routes, by path. Scripts
+    // are stopped after `max_operations` operations or `timeout`
+    // milliseconds.
+    #[serde(default)]
+    pub scripts: pub struct Scripts {
+        pub promoted_hubs: Option<String>,
+        pub section_hubs: Option<String>,
+        pub collection_children: Option<String>,
+        #[serde(default = "default_script_max_operations")]
+        pub max_operations: u64,
+        #[serde(default = "default_script_timeout")]
+        pub timeout: u64,
+    },
+
+    // The transforms and filters of each route, in order, see `PipelineStep`.
+    // Hub rules, plugins and scripts run after these.
+    #[serde(default)]
+    pub pipelines: pub struct Pipelines {
+        #[serde(default = "pipeline::default_promoted_hubs")]
+        pub promoted_hubs: Vec<PipelineStep>,
+        #[serde(default = "pipeline::default_section_hubs")]
+        pub section_hubs: Vec<PipelineStep>,
+        #[serde(default = "pipeline::default_collection_children")]
+        pub collection_children: Vec<PipelineStep>,
+        // Items of a hub, behind the keys that `hub_key` gives hubs.
+        #[serde(default = "pipeline::default_hub_items")]
+        pub hub_items: Vec<PipelineStep>,
+    },
+
+    // WebAssembly plugins, see `plugins`. Every `.wasm` file in `directory`
+    // is a plugin. A call to a plugin is stopped when it runs out of `fuel`,
+    // about one per instruction, or uses more than `max_memory` megabytes.
+    #[serde(default)]
+    pub plugins: pub struct Plugins {
+        pub directory: Option<String>,
+        #[serde(default = "default_plugin_fuel")]
+        pub fuel: u64,
+        #[serde(default = "default_plugin_max_memory")]
+        pub max_memory: usize,
+    },
+
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+
+    // The global config with each profile's settings layered on top, in the
+    // same order as `profiles`.
+    #[serde(skip)]
+    profile_configs: Vec<Arc<Config>>,
 }}
 
 impl Config {
-    fn figment() -> Figment {
-        Figment::new()
-            .merge(Yaml::file("config/config.yml"))
-            .merge(Env::prefixed("REPLEX_"))
+    /// Returns a snapshot of the current configuration.
+    ///
+    /// The snapshot stays the same for as long as it is held, even if the
+    /// config is reloaded in the meantime. Call `load` again to pick up
+    /// changes.
+    pub fn load() -> Arc<Self> {
+        INSTANCE
+            .get_or_init(|| {
+                let config = Self::read()
+                    .expect("Configuration should be correctly set up");
+                ArcSwap::from_pointee(config)
+            })
+            .load_full()
+    }
+
+    /// Same as `load`, but returns an error instead of panicking when the
+    /// configuration is invalid. The warnings found are returned instead of
+    /// logged, as this runs before logging is set up, which depends on the
+    /// config.
+    pub fn try_load() -> Result<(Arc<Self>, Vec<Issue>)> {
+        let (config, report) = Self::check(&config_path());
+        let config = Self::valid(config, &report)?;
+
+        let config = INSTANCE
+            .get_or_init(|| ArcSwap::from_pointee(config))
+            .load_full();
+        Ok((config, report.warnings().cloned().collect()))
+    }
+
+    /// Reads and validates the configuration from disk and the environment,
+    /// and swaps it in as the current configuration. The previous config
+    /// stays active if the new one is invalid.
+    pub fn reload() -> Result<()> {
+        let config = Self::read()?;
+
+        match INSTANCE.get() {
+            Some(instance) => instance.store(Arc::new(config)),
+            None => {
+                let _ = INSTANCE.set(ArcSwap::from_pointee(config));
+            }
+        }
+        tracing::info!("Configuration reloaded");
+
+        Ok(())
+    }
+
+    /// Returns the configuration for the client behind a request, with the
+    /// settings of the first matching profile applied.
+    pub fn for_request(req: &Request) -> Arc<Self> {
+        Self::load().resolve(&ProfileTarget::from(req))
+    }
+
+    /// Same as `for_request`, for an already extracted `PlexContext`.
+    pub fn for_context(context: &PlexContext) -> Arc<Self> {
+        Self::load().resolve(&ProfileTarget::from(context))
+    }
+
+    /// Returns the `plugins.directory` of this config and of its profiles,
+    /// without duplicates.
+    pub fn plugin_directories(&self) -> Vec<&str> {
+        let mut directories: Vec<&str> = Vec::new();
+        let configs = std::iter::once(self).chain(self.profile_configs.iter().map(|c| &**c));
+        for directory in configs.filter_map(|config| config.plugins.directory.as_deref()) {
+            if !directories.contains(&directory) {
+                directories.push(directory);
+            }
+        }
+        directories
+    }
+
+    /// Returns the server in `servers` called `name`.
+    pub fn server(&self, name: &str) -> Option<&Server> {
+        self.servers.iter().find(|server| server.name == name)
+    }
+
+    fn resolve(self: Arc<Self>, target: &ProfileTarget) -> Arc<Self> {
+        self.profiles
+            .iter()
+            .position(|profile| profile.matcher.matches(target))
+            .map(|index| self.profile_configs[index].clone())
+            .unwrap_or(self)
+    }
+
+    /// Extracts the configuration at `path`, merged with the files it includes
+    /// and the environment, and validates it. The config is `None` when it
+    /// couldn't be extracted at all.
+    pub fn check(path: &str) -> (Option<Self>, Report) {
+        let figment = match source::figment(path) {
+            Ok(figment) => figment,
+            Err(e) => return (None, validation::from_error(e)),
+        };
+
+        let mut config: Config = match figment.extract() {
+            Ok(config) => config,
+            Err(e) => return (None, validation::from_error(e)),
+        };
+
+        let mut report = Report::default();
+        let mut profile_configs = Vec::with_capacity(config.profiles.len());
+
+        for (index, profile) in config.profiles.iter().enumerate() {
+            let extracted: Result<Config, _> = figment
+                .clone()
+                .merge(Serialized::globals(&profile.settings))
+                .extract();
+
+            match extracted {
+                Ok(mut resolved) => {
+                    resolved.profiles.clear();
+                    profile_configs.push(Arc::new(resolved));
+                }
+                Err(e) => {
+                    let mut errors = validation::from_error(e);
+                    for issue in &mut errors.issues {
+                        issue.key = issue.key.as_ref().map(|key| {
+                            format!("profiles.{}.settings.{}", index, key)
+                        });
+                    }
+                    report.issues.append(&mut errors.issues);
+                }
+            }
+        }
+
+        if !report.has_errors() {
+            config.profile_configs = profile_configs;
+            report = validation::validate(&figment, &config);
+        }
+
+        (Some(config), report)
+    }
+
+    /// The config as YAML, with the values of secrets like `plex_token`
+    /// masked, also in `servers` and in the settings of `profiles`.
+    pub fn to_redacted_yaml(&self) -> Result<String> {
+        let mut value = serde_yaml::to_value(self)?;
+        redact(&mut value);
+        Ok(serde_yaml::to_string(&value)?)
+    }
+
+    fn read() -> Result<Self> {
+        let (config, report) = Self::check(&config_path());
+
+        for warning in report.warnings() {
+            tracing::warn!("{}", warning);
+        }
+
+        Self::valid(config, &report)
     }
 
-    pub fn load() -> &'static Self {
-        static INSTANCE: Lazy<Config> = Lazy::new(|| {
-            let config: Config = Config::figment()
-                .extract()
-                .expect("Configuration should be correctly set up");
+    // The config of a `check`, or its errors.
+    fn valid(config: Option<Self>, report: &Report) -> Result<Self> {
+        match config {
+            Some(config) if !report.has_errors() => Ok(config),
+            _ => {
+                let errors: Vec<String> =
+                    report.errors().map(ToString::to_string).collect();
+                anyhow::bail!("Invalid configuration:\n{}", errors.join("\n"))
+            }
+        }
+    }
+}
+
+/// Keys whose values are secrets, at any depth of the config.
+const SECRET_KEYS: [&str; 3] = ["admin_token", "plex_token", "token"];
+
+fn redact(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                let is_secret = key.as_str().is_some_and(|key| SECRET_KEYS.contains(&key));
+                if is_secret && !value.is_null() {
+                    *value = serde_yaml::Value::from("<redacted>");
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_yaml::Value::Sequence(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Where cached Plex responses are kept.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStorage {
+    #[default]
+    Memory,
+    // Survives restarts, see `cache.disk` for its settings.
+    Disk,
+}
+
+impl Default for DiskCache {
+    fn default() -> Self {
+        Self {
+            path: default_disk_cache_path(),
+            max_size: default_disk_cache_size(),
+        }
+    }
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            timeout: default_http_timeout(),
+            retries: default_http_retries(),
+            retry_min_delay: default_http_retry_min_delay(),
+            retry_max_delay: default_http_retry_max_delay(),
+            concurrency: default_http_concurrency(),
+        }
+    }
+}
 
-            config
-        });
-        &INSTANCE
+impl Default for Scripts {
+    fn default() -> Self {
+        Self {
+            promoted_hubs: None,
+            section_hubs: None,
+            collection_children: None,
+            max_operations: default_script_max_operations(),
+            timeout: default_script_timeout(),
+        }
     }
 }
 
+impl Default for Pipelines {
+    fn default() -> Self {
+        Self {
+            promoted_hubs: pipeline::default_promoted_hubs(),
+            section_hubs: pipeline::default_section_hubs(),
+            collection_children: pipeline::default_collection_children(),
+            hub_items: pipeline::default_hub_items(),
+        }
+    }
+}
+
+impl Pipelines {
+    /// The pipeline of each route, by its name.
+    pub fn routes(&self) -> [(&'static str, &[PipelineStep]); 4] {
+        [
+            ("promoted_hubs", &self.promoted_hubs),
+            ("section_hubs", &self.section_hubs),
+            ("collection_children", &self.collection_children),
+            ("hub_items", &self.hub_items),
+        ]
+    }
+}
+
+impl Default for Plugins {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            fuel: default_plugin_fuel(),
+            max_memory: default_plugin_max_memory(),
+        }
+    }
+}
+
+fn default_health_check_interval() -> u64 {
+    10
+}
+
 fn default_cache_ttl() -> u64 {
     30 * 60
 }
 
+fn default_cache_size() -> u64 {
+    256
+}
+
+fn default_disk_cache_path() -> String {
+    "config/cache".to_string()
+}
+
+fn default_disk_cache_size() -> u64 {
+    512
+}
+
+fn default_http_timeout() -> u64 {
+    30
+}
+
+fn default_http_retries() -> u32 {
+    2
+}
+
+fn default_http_retry_min_delay() -> u64 {
+    100
+}
+
+fn default_http_retry_max_delay() -> u64 {
+    2000
+}
+
+fn default_http_concurrency() -> usize {
+    8
+}
+
+fn default_script_max_operations() -> u64 {
+    1_000_000
+}
+
+fn default_script_timeout() -> u64 {
+    500
+}
+
+fn default_plugin_fuel() -> u64 {
+    100_000_000
+}
+
+fn default_plugin_max_memory() -> usize {
+    64
+}
+
 fn as_true() -> bool {
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::plex::testing;
+
+    #[test]
+    fn redacted_yaml_masks_every_token() {
+        let config = testing::config(json!({
+            "plex_token": "owner-secret",
+            "admin_token": "admin-secret",
+            "servers": [{ "name": "4k", "host": "http://4k:32400", "token": "server-secret" }],
+            "profiles": [{
+                "match": { "token": ["user-secret"] },
+                "settings": { "plex_token": "profile-secret" },
+            }],
+        }));
+
+        let yaml = config.to_redacted_yaml().unwrap();
+
+        assert!(!yaml.contains("secret"), "{}", yaml);
+        assert_eq!(yaml.matches("<redacted>").count(), 5);
+        assert!(yaml.contains("http://4k:32400"));
+    }
+}
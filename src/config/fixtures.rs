@@ -0,0 +1,51 @@
+//! Config files for tests, written to a directory of their own.
+
+use std::fs;
+use std::path::PathBuf;
+
+use tempfile::TempDir;
+
+use super::{Config, Report};
+
+/// The settings that have no default, for the top of a config file.
+pub(crate) const REQUIRED: &str = "host: http://plex.test:32400
+better_on_deck: {}
+cache: {}
+exclude_watched: {}
+redirect_streams: {}
+";
+
+pub(crate) struct Fixtures {
+    dir: TempDir,
+}
+
+impl Fixtures {
+    pub(crate) fn new() -> Self {
+        Self {
+            dir: TempDir::new().unwrap(),
+        }
+    }
+
+    /// Writes `contents` to the file `name`, and returns its path.
+    pub(crate) fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Keeps the files after the fixtures are dropped, for paths that may
+    /// still be read.
+    pub(crate) fn keep(self) -> PathBuf {
+        self.dir.into_path()
+    }
+
+    /// Checks the config file `name`.
+    pub(crate) fn check(&self, name: &str) -> (Option<Config>, Report) {
+        Config::check(self.dir.path().join(name).to_str().unwrap())
+    }
+}
+
+/// The errors of `report`, as they are printed.
+pub(crate) fn errors(report: &Report) -> Vec<String> {
+    report.errors().map(ToString::to_string).collect()
+}
@@ -0,0 +1,235 @@
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use figment::util::bool_from_str_or_int;
+
+use crate::deserializers::vec_from_comma_separated_or_list;
+use crate::models::Style;
+
+/// Changes to the hubs a rule matches, applied by `HubRulesTransform`. When
+/// several rules match a hub, they are all applied, and for settings that
+/// more than one of them has, the last rule wins.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct HubRule {
+    #[serde(default, rename = "match")]
+    pub matcher: HubMatch,
+
+    /// Removes the hub.
+    #[serde(default, deserialize_with = "bool_from_str_or_int")]
+    pub hide: bool,
+
+    /// New title of the hub.
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// Shows the hub as a hero or shelf row, in place of what `hero_rows`
+    /// and the `REPLEXHERO` label say.
+    #[serde(default)]
+    pub style: Option<Style>,
+
+    /// Keeps the first items of the hub only.
+    #[serde(default)]
+    pub limit: Option<usize>,
+
+    /// Moves the hub to this place, 1 being the top.
+    #[serde(default)]
+    pub position: Option<usize>,
+
+    /// Removes watched items from the hub.
+    #[serde(default, deserialize_with = "bool_from_str_or_int")]
+    pub exclude_watched: bool,
+}
+
+/// Hubs a rule applies to. Every property that is set has to match, and a
+/// property matches when any of its values does.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct HubMatch {
+    /// Parts of the hub identifier, like in `hero_rows`.
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub hub_identifier: Option<Vec<String>>,
+
+    /// Hub contexts. A context also matches the ones that start with it, so
+    /// `hub.custom.collection` matches every collection hub.
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub context: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub title: Option<TitlePattern>,
+
+    /// Ids of the libraries the hub belongs to.
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub section: Option<Vec<i64>>,
+
+    /// Labels of the collection of a collection hub, ignoring case.
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub collection_label: Option<Vec<String>>,
+}
+
+/// The properties of a single hub, as used for rule matching. Collection
+/// labels are checked separately, as they have to be fetched from Plex.
+#[derive(Debug, Default)]
+pub struct HubTarget<'a> {
+    pub hub_identifier: Option<&'a str>,
+    pub context: Option<&'a str>,
+    pub title: &'a str,
+    pub section: Option<i64>,
+}
+
+impl HubRule {
+    /// Whether the rule changes anything about the hubs it matches.
+    pub fn has_actions(&self) -> bool {
+        self.hide
+            || self.title.is_some()
+            || self.style.is_some()
+            || self.limit.is_some()
+            || self.position.is_some()
+            || self.exclude_watched
+    }
+}
+
+impl HubMatch {
+    /// Whether `target` matches everything but the collection labels.
+    pub fn matches(&self, target: &HubTarget) -> bool {
+        // Like profiles, a rule without criteria doesn't apply to anything.
+        if self.is_empty() {
+            return false;
+        }
+
+        let matches_identifier = self.hub_identifier.as_ref().is_none_or(|ids| {
+            target.hub_identifier.is_some_and(|hub_id| {
+                ids.iter().any(|id| !id.is_empty() && hub_id.contains(id.as_str()))
+            })
+        });
+        let matches_context = self.context.as_ref().is_none_or(|contexts| {
+            target.context.is_some_and(|context| {
+                contexts.iter().any(|c| context.starts_with(c.as_str()))
+            })
+        });
+        let matches_title = self
+            .title
+            .as_ref()
+            .is_none_or(|pattern| pattern.0.is_match(target.title));
+        let matches_section = self.section.as_ref().is_none_or(|sections| {
+            target.section.is_some_and(|section| sections.contains(&section))
+        });
+
+        matches_identifier && matches_context && matches_title && matches_section
+    }
+
+    /// Whether the labels of a collection match. Without `collection_label`
+    /// every collection does.
+    pub fn matches_labels<'a>(&self, labels: impl IntoIterator<Item = &'a str>) -> bool {
+        let Some(expected) = &self.collection_label else {
+            return true;
+        };
+        labels
+            .into_iter()
+            .any(|label| expected.iter().any(|e| e.eq_ignore_ascii_case(label)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hub_identifier.is_none()
+            && self.context.is_none()
+            && self.title.is_none()
+            && self.section.is_none()
+            && self.collection_label.is_none()
+    }
+}
+
+/// A regex that hub titles are matched against.
+#[derive(Debug, Clone)]
+pub struct TitlePattern(pub Regex);
+
+impl PartialEq for TitlePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for TitlePattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TitlePattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(TitlePattern)
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(yaml: &str) -> HubMatch {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn target(hub_identifier: &str) -> HubTarget<'_> {
+        HubTarget {
+            hub_identifier: Some(hub_identifier),
+            context: Some("hub.custom.collection"),
+            title: "Recently Added Movies",
+            section: Some(6),
+        }
+    }
+
+    #[test]
+    fn empty_matches_nothing() {
+        assert!(!HubMatch::default().matches(&target("home.movies.recent.6")));
+    }
+
+    #[test]
+    fn any_value_of_a_property_matches() {
+        let matcher = matcher("hub_identifier: home.continue, movies.recent");
+
+        assert!(matcher.matches(&target("home.movies.recent.6")));
+        assert!(!matcher.matches(&target("home.television.recent.2")));
+    }
+
+    #[test]
+    fn every_property_has_to_match() {
+        let matching = matcher("{ hub_identifier: recent, section: [6], title: '^Recently' }");
+        let other_section = matcher("{ hub_identifier: recent, section: [7], title: '^Recently' }");
+
+        assert!(matching.matches(&target("home.movies.recent.6")));
+        assert!(!other_section.matches(&target("home.movies.recent.6")));
+    }
+
+    #[test]
+    fn contexts_match_by_prefix() {
+        assert!(matcher("context: hub.custom").matches(&target("custom.collection.6.1")));
+        assert!(!matcher("context: hub.movie").matches(&target("custom.collection.6.1")));
+    }
+
+    #[test]
+    fn missing_properties_of_the_hub_dont_match() {
+        let target = HubTarget {
+            title: "Continue Watching",
+            ..HubTarget::default()
+        };
+
+        assert!(!matcher("section: 6").matches(&target));
+        assert!(!matcher("hub_identifier: continue").matches(&target));
+        assert!(matcher("title: Continue").matches(&target));
+    }
+
+    #[test]
+    fn labels_match_ignoring_case() {
+        let matcher = matcher("collection_label: Pinned");
+
+        assert!(matcher.matches_labels(["other", "PINNED"]));
+        assert!(!matcher.matches_labels(["other"]));
+        assert!(HubMatch::default().matches_labels([]));
+    }
+}
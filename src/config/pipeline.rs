@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A transform or filter in the pipeline of a route, by its name in
+/// `transforms::registry`. Written as just the name, or as a map with the
+/// `name`, `enabled` and the parameters of the step:
+///
+/// ```yaml
+/// - hub_key
+/// - name: hub_style
+///   is_home: true
+/// - name: exclude_watched
+///   enabled: false
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "StepDefinition")]
+pub struct PipelineStep {
+    pub name: String,
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub params: Map<String, Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StepDefinition {
+    Name(String),
+    Step {
+        name: String,
+        #[serde(default = "as_true")]
+        enabled: bool,
+        #[serde(flatten)]
+        params: Map<String, Value>,
+    },
+}
+
+impl From<StepDefinition> for PipelineStep {
+    fn from(definition: StepDefinition) -> Self {
+        match definition {
+            StepDefinition::Name(name) => PipelineStep::named(&name),
+            StepDefinition::Step {
+                name,
+                enabled,
+                params,
+            } => PipelineStep {
+                name,
+                enabled,
+                params,
+            },
+        }
+    }
+}
+
+impl PipelineStep {
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            enabled: true,
+            params: Map::new(),
+        }
+    }
+
+    pub fn with_param(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(name.to_owned(), value.into());
+        self
+    }
+}
+
+fn as_true() -> bool {
+    true
+}
+
+pub(super) fn default_promoted_hubs() -> Vec<PipelineStep> {
+    vec![
+        PipelineStep::named("section_directory"),
+        PipelineStep::named("exclude_watched"),
+        PipelineStep::named("supplement_hub"),
+        PipelineStep::named("hub_mix"),
+        PipelineStep::named("reorder_hubs"),
+        PipelineStep::named("hub_style").with_param("is_home", true),
+        PipelineStep::named("hub_key"),
+        PipelineStep::named("merge_servers"),
+        PipelineStep::named("hub_rules"),
+        PipelineStep::named("plugins"),
+        PipelineStep::named("script"),
+        PipelineStep::named("user_state"),
+    ]
+}
+
+pub(super) fn default_section_hubs() -> Vec<PipelineStep> {
+    vec![
+        PipelineStep::named("section_directory"),
+        PipelineStep::named("hide_in_progress"),
+        PipelineStep::named("exclude_watched"),
+        PipelineStep::named("supplement_hub"),
+        PipelineStep::named("reorder_hubs"),
+        PipelineStep::named("hub_style").with_param("is_home", false),
+        PipelineStep::named("hub_key"),
+        PipelineStep::named("merge_servers"),
+        PipelineStep::named("hub_rules"),
+        PipelineStep::named("plugins"),
+        PipelineStep::named("script"),
+        PipelineStep::named("user_state"),
+    ]
+}
+
+pub(super) fn default_collection_children() -> Vec<PipelineStep> {
+    vec![
+        PipelineStep::named("section_mix"),
+        PipelineStep::named("collection_style"),
+        PipelineStep::named("merge_servers"),
+        PipelineStep::named("plugins"),
+        PipelineStep::named("script"),
+        PipelineStep::named("user_state"),
+    ]
+}
+
+pub(super) fn default_hub_items() -> Vec<PipelineStep> {
+    vec![
+        PipelineStep::named("media_style"),
+        PipelineStep::named("user_state"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::fixtures::{errors, Fixtures, REQUIRED};
+    use crate::config::{Config, Report};
+
+    fn check(yaml: &str) -> (Option<Config>, Report) {
+        let fixtures = Fixtures::new();
+        fixtures.write("config.yml", &format!("{}{}", REQUIRED, yaml));
+        fixtures.check("config.yml")
+    }
+
+    fn names(steps: &[super::PipelineStep]) -> Vec<&str> {
+        steps.iter().map(|step| step.name.as_str()).collect()
+    }
+
+    #[test]
+    fn default_pipelines_match_the_chains_they_replace() {
+        let (config, report) = check("");
+        assert!(errors(&report).is_empty(), "{}", report);
+        let pipelines = config.unwrap().pipelines;
+
+        assert_eq!(
+            names(&pipelines.promoted_hubs),
+            [
+                "section_directory",
+                "exclude_watched",
+                "supplement_hub",
+                "hub_mix",
+                "reorder_hubs",
+                "hub_style",
+                "hub_key",
+                "merge_servers",
+                "hub_rules",
+                "plugins",
+                "script",
+                "user_state",
+            ]
+        );
+        assert_eq!(
+            names(&pipelines.section_hubs),
+            [
+                "section_directory",
+                "hide_in_progress",
+                "exclude_watched",
+                "supplement_hub",
+                "reorder_hubs",
+                "hub_style",
+                "hub_key",
+                "merge_servers",
+                "hub_rules",
+                "plugins",
+                "script",
+                "user_state",
+            ]
+        );
+        assert_eq!(
+            names(&pipelines.collection_children),
+            [
+                "section_mix",
+                "collection_style",
+                "merge_servers",
+                "plugins",
+                "script",
+                "user_state",
+            ]
+        );
+        assert_eq!(names(&pipelines.hub_items), ["media_style", "user_state"]);
+
+        let is_home = |steps: &[super::PipelineStep]| {
+            steps
+                .iter()
+                .find(|step| step.name == "hub_style")
+                .map(|step| step.params["is_home"].clone())
+        };
+        assert_eq!(is_home(&pipelines.promoted_hubs), Some(true.into()));
+        assert_eq!(is_home(&pipelines.section_hubs), Some(false.into()));
+    }
+
+    #[test]
+    fn steps_are_read_by_name_or_as_maps() {
+        let (config, report) = check(
+            "pipelines:
+  section_hubs:
+    - hub_key
+    - name: hub_style
+      is_home: true
+    - name: exclude_watched
+      enabled: false
+",
+        );
+        assert!(errors(&report).is_empty(), "{}", report);
+
+        let steps = config.unwrap().pipelines.section_hubs;
+        assert_eq!(names(&steps), ["hub_key", "hub_style", "exclude_watched"]);
+        assert_eq!(steps[1].params["is_home"], true);
+        assert!(steps[0].enabled && !steps[2].enabled);
+    }
+
+    #[test]
+    fn unknown_steps_are_rejected() {
+        let (_, report) = check(
+            "pipelines:
+  promoted_hubs: [hub_key, hub_styl]
+",
+        );
+
+        let errors = errors(&report);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("`pipelines.promoted_hubs.1`"));
+        assert!(errors[0].contains("unknown transform \"hub_styl\""));
+    }
+
+    #[test]
+    fn unknown_params_are_rejected() {
+        let (_, report) = check(
+            "pipelines:
+  section_hubs:
+    - name: hub_style
+      is_hom: true
+",
+        );
+
+        let errors = errors(&report);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("`pipelines.section_hubs.0`"));
+        assert!(errors[0].contains("unknown parameter `is_hom`"));
+    }
+
+    #[test]
+    fn route_steps_are_rejected_elsewhere() {
+        let (_, report) = check(
+            "pipelines:
+  hub_items: [media_style, script]
+",
+        );
+
+        let errors = errors(&report);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("`pipelines.hub_items.1`"));
+    }
+}
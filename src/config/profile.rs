@@ -0,0 +1,129 @@
+use figment::value::Dict;
+use salvo::Request;
+use serde::{Deserialize, Serialize};
+
+use crate::deserializers::vec_from_comma_separated_or_list;
+use crate::models::Platform;
+use crate::plex::models::PlexContext;
+
+/// A set of config overrides that applies to the clients it matches.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default, rename = "match")]
+    pub matcher: ProfileMatch,
+
+    /// Settings layered on top of the global config, using the same keys.
+    #[serde(default)]
+    pub settings: Dict,
+}
+
+/// Client properties a profile applies to. Every property that is set has
+/// to match, and a property matches when any of its values does.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ProfileMatch {
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub token: Option<Vec<String>>,
+
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub client_identifier: Option<Vec<String>>,
+
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub platform: Option<Vec<String>>,
+
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub product: Option<Vec<String>>,
+
+    #[serde(default, deserialize_with = "vec_from_comma_separated_or_list")]
+    pub device_name: Option<Vec<String>>,
+}
+
+/// The client properties of a single request, as used for profile matching.
+#[derive(Debug, Default)]
+pub struct ProfileTarget {
+    pub token: Option<String>,
+    pub client_identifier: Option<String>,
+    pub platform: Option<String>,
+    pub product: Option<String>,
+    pub device_name: Option<String>,
+}
+
+impl ProfileMatch {
+    pub fn matches(&self, target: &ProfileTarget) -> bool {
+        // A profile without any criteria would apply to everyone, which is
+        // what the global settings are for.
+        if self.is_empty() {
+            return false;
+        }
+
+        // Tokens and identifiers are compared exactly, descriptive fields
+        // ignore case.
+        matches_value(&self.token, &target.token, false)
+            && matches_value(&self.client_identifier, &target.client_identifier, false)
+            && matches_value(&self.platform, &target.platform, true)
+            && matches_value(&self.product, &target.product, true)
+            && matches_value(&self.device_name, &target.device_name, true)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.token.is_none()
+            && self.client_identifier.is_none()
+            && self.platform.is_none()
+            && self.product.is_none()
+            && self.device_name.is_none()
+    }
+}
+
+fn matches_value(
+    expected: &Option<Vec<String>>,
+    actual: &Option<String>,
+    ignore_case: bool,
+) -> bool {
+    let Some(expected) = expected else {
+        return true;
+    };
+    let Some(actual) = actual else {
+        return false;
+    };
+
+    expected.iter().any(|value| {
+        if ignore_case {
+            value.eq_ignore_ascii_case(actual)
+        } else {
+            value == actual
+        }
+    })
+}
+
+impl From<&PlexContext> for ProfileTarget {
+    fn from(context: &PlexContext) -> Self {
+        Self {
+            token: context.token.clone(),
+            client_identifier: context.client_identifier.clone(),
+            platform: Some(context.platform.to_string()),
+            product: context.product.clone(),
+            device_name: context.device_name.clone(),
+        }
+    }
+}
+
+impl From<&Request> for ProfileTarget {
+    fn from(req: &Request) -> Self {
+        // Plex clients send these either as headers or as query parameters.
+        let value = |name: &str| -> Option<String> {
+            req.header::<String>(name)
+                .or_else(|| req.query::<String>(name))
+        };
+
+        Self {
+            token: value("X-Plex-Token"),
+            client_identifier: value("X-Plex-Client-Identifier"),
+            platform: value("X-Plex-Platform")
+                .map(|platform| platform.parse::<Platform>().unwrap_or_default().to_string()),
+            product: value("X-Plex-Product"),
+            device_name: value("X-Plex-Device-Name"),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::deserializers::deserialize_host;
+
+/// Another Plex server whose hubs are merged into the ones of `host`.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Server {
+    /// Identifies the server in the keys that Replex hands out to clients,
+    /// so it should be short and stay the same.
+    pub name: String,
+
+    #[serde(deserialize_with = "deserialize_host")]
+    pub host: String,
+
+    /// Token of the owner of this server, for its notifications, and for
+    /// the hubs and collections that Replex merges when `share_owner_view`
+    /// is set. Requests of clients otherwise keep their own token.
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// Merges the hubs and collections of this server as the owner sees
+    /// them, for every client that `host` accepts, including restricted
+    /// and managed users.
+    #[serde(default)]
+    pub share_owner_view: bool,
+
+    /// Libraries of this server, by the id of the library on `host` whose
+    /// hubs they are merged into.
+    #[serde(default)]
+    pub sections: BTreeMap<String, u32>,
+}
+
+impl Server {
+    /// Returns the id of the library on this server that is merged into
+    /// library `id` of `host`.
+    pub fn section_for(&self, id: &str) -> Option<String> {
+        self.sections.get(id).map(ToString::to_string)
+    }
+
+    /// Returns the token that merged hubs and collections are fetched with
+    /// instead of the one of the client, if any.
+    pub fn shared_token(&self) -> Option<&String> {
+        self.token.as_ref().filter(|_| self.share_owner_view)
+    }
+}
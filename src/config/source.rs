@@ -0,0 +1,395 @@
+// Providers have to return `figment::Error`, large as it is.
+#![allow(clippy::result_large_err)]
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use figment::providers::{Env, Format, Json, Serialized, Toml, Yaml};
+use figment::value::{Dict, Map, Value};
+use figment::{Error, Figment, Metadata, Profile, Provider};
+
+use super::CONFIG_PATH;
+
+/// Returns the path of the config file, taken from the `--config` argument,
+/// the `REPLEX_CONFIG` environment variable or `CONFIG_PATH`, in that order.
+pub fn config_path() -> String {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            if let Some(path) = args.next() {
+                return path;
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return path.to_string();
+        }
+    }
+
+    env::var("REPLEX_CONFIG").unwrap_or_else(|_| CONFIG_PATH.to_string())
+}
+
+/// Builds the figment for the config file at `path`: the files it includes,
+/// the file itself, and the `REPLEX_` environment variables on top.
+pub(super) fn figment(path: &str) -> Result<Figment, Error> {
+    let mut figment = Figment::new();
+    for file in files(path)? {
+        figment = figment.merge(ConfigFile { path: file });
+    }
+
+    Ok(figment
+        .merge(
+            Env::prefixed("REPLEX_")
+                .ignore(&["CONFIG"])
+                .filter(|key| !key.as_str().to_ascii_lowercase().ends_with("_file"))
+                .split("__"),
+        )
+        .merge(EnvSecretFiles))
+}
+
+/// Returns the config file at `path` and every file it includes, with
+/// included files before the files including them so they can be
+/// overridden.
+pub(super) fn files(path: &str) -> Result<Vec<PathBuf>, Error> {
+    let path = PathBuf::from(path);
+
+    // The default config file is optional, everything can be set through
+    // the environment instead.
+    if path == Path::new(CONFIG_PATH) && !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut files = vec![];
+    collect_files(path, &mut vec![], &mut files)?;
+    Ok(files)
+}
+
+/// Returns the secret files that the `*_file` keys of the config files in
+/// `files` and the `REPLEX_*_FILE` environment variables point to.
+pub(super) fn secret_files(files: &[PathBuf]) -> Vec<PathBuf> {
+    let mut secrets = vec![];
+    for file in files {
+        if let Ok(dict) = (ConfigFile { path: file.clone() }).read() {
+            collect_secret_files(&dict, &mut secrets);
+        }
+    }
+
+    for (key, path) in Env::prefixed("REPLEX_").iter() {
+        if key.as_str().ends_with("_file") {
+            secrets.push(PathBuf::from(path));
+        }
+    }
+
+    secrets
+}
+
+fn collect_secret_files(dict: &Dict, secrets: &mut Vec<PathBuf>) {
+    for (key, value) in dict {
+        match value {
+            Value::String(_, file) if key.len() > "_file".len() && key.ends_with("_file") => {
+                secrets.push(PathBuf::from(file));
+            }
+            Value::Dict(_, dict) => collect_secret_files(dict, secrets),
+            Value::Array(_, values) => {
+                for value in values {
+                    if let Value::Dict(_, dict) = value {
+                        collect_secret_files(dict, secrets);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_files(
+    path: PathBuf,
+    chain: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    if chain.contains(&path) {
+        return Err(format!("{} includes itself", path.display()).into());
+    }
+
+    if files.contains(&path) {
+        return Ok(());
+    }
+
+    let includes = ConfigFile { path: path.clone() }.includes()?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    chain.push(path);
+    for include in includes {
+        collect_files(dir.join(include), chain, files)?;
+    }
+    let path = chain.pop().expect("path was just pushed");
+
+    files.push(path);
+    Ok(())
+}
+
+/// A single config file, parsed according to its extension. YAML is used
+/// for anything that isn't `.toml` or `.json`.
+///
+/// Keys ending in `_file` are replaced by the contents of the file they point
+/// to, without the suffix. `host_file: /run/secrets/plex_host` sets `host`.
+struct ConfigFile {
+    path: PathBuf,
+}
+
+impl ConfigFile {
+    fn format(&self) -> &'static str {
+        match self.path.extension().and_then(OsStr::to_str) {
+            Some("toml") => Toml::NAME,
+            Some("json") => Json::NAME,
+            _ => Yaml::NAME,
+        }
+    }
+
+    fn read(&self) -> Result<Dict, Error> {
+        if !self.path.is_file() {
+            return Err(format!("{} does not exist", self.path.display()).into());
+        }
+
+        let mut data = match self.format() {
+            Toml::NAME => Toml::file_exact(&self.path).data(),
+            Json::NAME => Json::file_exact(&self.path).data(),
+            _ => Yaml::file_exact(&self.path).data(),
+        }?;
+
+        Ok(data.remove(&Profile::Default).unwrap_or_default())
+    }
+
+    /// Paths listed under the `include` key, relative to this file.
+    fn includes(&self) -> Result<Vec<String>, Error> {
+        let includes = match self.read()?.remove("include") {
+            None => vec![],
+            Some(Value::String(_, path)) => vec![path],
+            Some(Value::Array(_, paths)) => paths
+                .into_iter()
+                .map(|path| path.into_string())
+                .collect::<Option<_>>()
+                .ok_or_else(|| include_error(&self.path))?,
+            Some(_) => return Err(include_error(&self.path)),
+        };
+
+        Ok(includes)
+    }
+}
+
+impl Provider for ConfigFile {
+    fn metadata(&self) -> Metadata {
+        Metadata::from(format!("{} file", self.format()), self.path.as_path())
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, Error> {
+        let mut dict = self.read()?;
+        dict.remove("include");
+        read_secret_files(&mut dict, "")?;
+
+        Ok(Profile::Default.collect(dict))
+    }
+}
+
+fn include_error(path: &Path) -> Error {
+    Error::from(format!(
+        "`include` in {} should be a path or a list of paths",
+        path.display()
+    ))
+    .with_path("include")
+}
+
+/// Sets a key for every `REPLEX_<KEY>_FILE` environment variable to the
+/// contents of the file it points to. Use `__` to set nested keys, like
+/// `REPLEX_CACHE__TTL_FILE`.
+struct EnvSecretFiles;
+
+impl Provider for EnvSecretFiles {
+    fn metadata(&self) -> Metadata {
+        Metadata::named("`REPLEX_*_FILE` environment variable(s)")
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, Error> {
+        let mut secrets = Figment::new();
+        for (key, path) in Env::prefixed("REPLEX_").split("__").iter() {
+            let Some(key) = key.as_str().strip_suffix("_file") else {
+                continue;
+            };
+
+            let value = read_secret(&path).map_err(|e| e.with_path(key))?;
+            secrets = secrets.merge(Serialized::global(key, value));
+        }
+
+        Ok(Profile::Default.collect(secrets.extract()?))
+    }
+}
+
+/// Replaces every `<key>_file` entry in `dict` and the dictionaries nested in
+/// it with `<key>`, set to the contents of the file.
+fn read_secret_files(dict: &mut Dict, prefix: &str) -> Result<(), Error> {
+    let keys: Vec<String> = dict
+        .keys()
+        .filter(|key| key.len() > "_file".len() && key.ends_with("_file"))
+        .cloned()
+        .collect();
+
+    for key in keys {
+        let path = format!("{}{}", prefix, key);
+        let file = match dict.remove(&key) {
+            Some(Value::String(_, file)) => file,
+            _ => return Err(Error::from("should be the path of a file").with_path(&path)),
+        };
+
+        let value = read_secret(&file).map_err(|e| e.with_path(&path))?;
+        let key = key.strip_suffix("_file").expect("key ends with _file");
+        dict.insert(key.to_string(), value);
+    }
+
+    for (key, value) in dict.iter_mut() {
+        let prefix = format!("{}{}.", prefix, key);
+        match value {
+            Value::Dict(_, dict) => read_secret_files(dict, &prefix)?,
+            Value::Array(_, values) => {
+                for (index, value) in values.iter_mut().enumerate() {
+                    if let Value::Dict(_, dict) = value {
+                        read_secret_files(dict, &format!("{}{}.", prefix, index))?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Values are parsed the same way as environment variables, and the trailing
+// newline most secret files end with is dropped.
+fn read_secret(path: &str) -> Result<Value, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::from(format!("failed to read {}: {}", path, e)))?;
+
+    Ok(contents.trim_end().parse().expect("parsing a value is infallible"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::fixtures::{errors, Fixtures, REQUIRED};
+    use crate::config::Config;
+
+    use super::*;
+
+    #[test]
+    fn including_files_override_included_ones() {
+        let fixtures = Fixtures::new();
+        let base = fixtures.write("base.toml", "plex_token = \"base\"\n[cache]\nttl = 60\n");
+        let json = fixtures.write("server.json", r#"{ "plex_token": "server" }"#);
+        let config = fixtures.write(
+            "config.yml",
+            &format!("include: [base.toml, server.json]\n{}", REQUIRED),
+        );
+
+        assert_eq!(
+            files(config.to_str().unwrap()).unwrap(),
+            [base, json, config]
+        );
+
+        let (config, report) = fixtures.check("config.yml");
+        assert!(errors(&report).is_empty(), "{}", report);
+        let config = config.unwrap();
+        assert_eq!(config.plex_token.as_deref(), Some("server"));
+        assert_eq!(config.cache.ttl, 60);
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let fixtures = Fixtures::new();
+        fixtures.write("a.yml", "include: b.yml\n");
+        fixtures.write("b.yml", "include: a.yml\n");
+        fixtures.write("config.yml", &format!("include: a.yml\n{}", REQUIRED));
+
+        let (config, report) = fixtures.check("config.yml");
+
+        assert!(config.is_none());
+        let errors = errors(&report);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].ends_with("a.yml includes itself"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn file_keys_are_read_from_their_file() {
+        let fixtures = Fixtures::new();
+        let token = fixtures.write("token", "secret\n");
+        let ttl = fixtures.write("ttl", "120\n");
+        fixtures.write(
+            "config.yml",
+            &format!(
+                "plex_token_file: {}\n{}",
+                token.display(),
+                REQUIRED.replace("cache: {}", &format!("cache:\n  ttl_file: {}", ttl.display()))
+            ),
+        );
+
+        let (config, report) = fixtures.check("config.yml");
+
+        assert!(errors(&report).is_empty(), "{}", report);
+        let config = config.unwrap();
+        assert_eq!(config.plex_token.as_deref(), Some("secret"));
+        assert_eq!(config.cache.ttl, 120);
+    }
+
+    #[test]
+    fn secret_files_are_found_in_nested_keys() {
+        let fixtures = Fixtures::new();
+        let config = fixtures.write(
+            "config.yml",
+            &format!(
+                "plex_token_file: /run/secrets/plex_token\nservers:\n  - name: 4k\n    token_file: /run/secrets/4k\n{}",
+                REQUIRED.replace("cache: {}", "cache:\n  ttl_file: /run/secrets/ttl")
+            ),
+        );
+
+        // Other tests may set `REPLEX_*_FILE` variables at the same time.
+        let secrets = secret_files(&[config]);
+
+        for expected in ["/run/secrets/plex_token", "/run/secrets/4k", "/run/secrets/ttl"] {
+            assert!(secrets.contains(&PathBuf::from(expected)), "{:?}", secrets);
+        }
+    }
+
+    #[test]
+    fn missing_secret_files_are_errors() {
+        let fixtures = Fixtures::new();
+        let config = fixtures.write(
+            "config.yml",
+            &REQUIRED.replace("cache: {}", "cache:\n  ttl_file: /nonexistent/ttl"),
+        );
+
+        let (config_value, report) = fixtures.check("config.yml");
+
+        assert!(config_value.is_none());
+        let errors = errors(&report);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains(&config.display().to_string()), "{}", errors[0]);
+        assert!(errors[0].contains("`cache.ttl_file`"), "{}", errors[0]);
+        assert!(errors[0].contains("failed to read /nonexistent/ttl"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn env_file_variables_are_read_from_their_file() {
+        // Other tests check configs at the same time and see the variable
+        // too, so the file is kept and only sets a value none of them reads.
+        let fixtures = Fixtures::new();
+        fixtures.write("config.yml", REQUIRED);
+        let dir = fixtures.keep();
+        let secret = dir.join("admin_token");
+        fs::write(&secret, "admin\n").unwrap();
+
+        env::set_var("REPLEX_ADMIN_TOKEN_FILE", &secret);
+        let (config, report) = Config::check(dir.join("config.yml").to_str().unwrap());
+        env::remove_var("REPLEX_ADMIN_TOKEN_FILE");
+
+        assert!(errors(&report).is_empty(), "{}", report);
+        assert_eq!(config.unwrap().admin_token.as_deref(), Some("admin"));
+    }
+}
@@ -0,0 +1,834 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use figment::{providers::Serialized, value::Value, Figment, Source};
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use toml::Spanned;
+use url::Url;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+use super::{CacheStorage, Config};
+use crate::cache::KEY_FAMILIES;
+use crate::scripting;
+use crate::transforms::registry::{self, StepContext};
+use crate::transforms::ScriptHook;
+
+// Values accepted by `video_transcode_fallback_for` and `force_direct_play_for`,
+// as reported in the `videoResolution` field by Plex.
+const VIDEO_RESOLUTIONS: [&str; 6] = ["4k", "1080", "720", "576", "480", "sd"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in the configuration.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    pub key: Option<String>,
+    // Where the offending value was set, e.g. `config/config.yml:12`.
+    pub location: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error")?,
+            Severity::Warning => write!(f, "warning")?,
+        }
+
+        if let Some(location) = &self.location {
+            write!(f, " [{}]", location)?;
+        }
+
+        if let Some(key) = &self.key {
+            write!(f, " `{}`", key)?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// All problems found while validating a configuration.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+
+    fn error(&mut self, key: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Error, key, message);
+    }
+
+    fn warning(&mut self, key: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Warning, key, message);
+    }
+
+    fn push(&mut self, severity: Severity, key: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(Issue {
+            severity,
+            key: Some(key.into()),
+            location: None,
+            message: message.into(),
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates a configuration against the sources it was extracted from.
+///
+/// Reports keys that don't map to a setting, values outside of their
+/// accepted range and malformed hosts. Keys that are only set through the
+/// environment are reported as warnings, since unrelated `REPLEX_` variables
+/// are common in container setups.
+pub fn validate(figment: &Figment, config: &Config) -> Report {
+    let mut report = Report::default();
+
+    let global = ignored_keys(figment);
+    for key in &global {
+        if source_file(figment, key).is_some() {
+            report.error(key, "unknown key");
+        } else {
+            report.warning(
+                key.clone(),
+                format!(
+                    "unknown key, set through REPLEX_{}",
+                    key.replace('.', "__").to_uppercase()
+                ),
+            );
+        }
+    }
+
+    for (index, profile) in config.profiles.iter().enumerate() {
+        let prefix = format!("profiles.{}", index);
+
+        if profile.matcher == Default::default() {
+            report.warning(
+                format!("{}.match", prefix),
+                "profile has no match criteria and will never be applied",
+            );
+        }
+
+        // Settings are layered on the global config, so only report keys
+        // that the global config doesn't already report.
+        let layered = figment
+            .clone()
+            .merge(Serialized::globals(&profile.settings));
+        for key in ignored_keys(&layered) {
+            if !global.contains(&key) {
+                report.error(format!("{}.settings.{}", prefix, key), "unknown key");
+            }
+        }
+    }
+
+    let mut values = Report::default();
+    validate_values(config, &mut values);
+
+    // Profiles inherit the global values, only report what they change.
+    for (index, profile) in config.profile_configs.iter().enumerate() {
+        let mut profile_values = Report::default();
+        validate_values(profile, &mut profile_values);
+
+        for mut issue in profile_values.issues {
+            let inherited = values
+                .issues
+                .iter()
+                .any(|i| i.key == issue.key && i.message == issue.message);
+
+            if !inherited {
+                issue.key = issue
+                    .key
+                    .map(|key| format!("profiles.{}.settings.{}", index, key));
+                report.issues.push(issue);
+            }
+        }
+    }
+    report.issues.append(&mut values.issues);
+
+    let mut locations = KeyLocations::default();
+    for issue in &mut report.issues {
+        if let Some(key) = &issue.key {
+            issue.location = source_file(figment, key).map(|file| locations.locate(file, key));
+        }
+    }
+
+    report
+}
+
+/// Turns an extraction error into a report, with the location of every
+/// offending key where it can be found.
+pub fn from_error(error: figment::Error) -> Report {
+    let mut locations = KeyLocations::default();
+    let issues = error
+        .into_iter()
+        .map(|e| {
+            let key = (!e.path.is_empty()).then(|| e.path.join("."));
+            let location = match e.metadata.as_ref().and_then(|m| m.source.as_ref()) {
+                Some(Source::File(file)) => {
+                    Some(locations.locate(file, key.as_deref().unwrap_or_default()))
+                }
+                Some(source) => Some(source.to_string()),
+                None => e.metadata.as_ref().map(|m| m.name.to_string()),
+            };
+
+            Issue {
+                severity: Severity::Error,
+                key,
+                location,
+                message: e.kind.to_string(),
+            }
+        })
+        .collect();
+
+    Report { issues }
+}
+
+/// Checks whether the Plex server at `host` responds. Malformed hosts are
+/// skipped, as `validate` already reports those.
+pub async fn check_host(host: &str) -> Option<Issue> {
+    validate_host(host).ok()?;
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .ok()?;
+    let url = format!("{}/identity", host.trim_end_matches('/'));
+
+    let message = match client.get(&url).send().await {
+        Ok(res) if res.status().is_success() => return None,
+        Ok(res) => format!("{} responded with {}", url, res.status()),
+        Err(e) => format!("{} is unreachable: {}", url, e),
+    };
+
+    Some(Issue {
+        severity: Severity::Warning,
+        key: Some("host".to_string()),
+        location: None,
+        message,
+    })
+}
+
+fn validate_values(config: &Config, report: &mut Report) {
+    if let Err(message) = validate_host(&config.host) {
+        report.error("host", message);
+    }
+
+    for (index, host) in config.standby_hosts.iter().flatten().enumerate() {
+        if let Err(message) = validate_host(host) {
+            report.error(format!("standby_hosts.{}", index), message);
+        }
+    }
+
+    for (index, server) in config.servers.iter().enumerate() {
+        let valid_name = !server.name.is_empty()
+            && server
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            report.error(
+                format!("servers.{}.name", index),
+                "must only contain letters, digits, `-` and `_`",
+            );
+        } else if config.servers[..index].iter().any(|s| s.name == server.name) {
+            report.error(
+                format!("servers.{}.name", index),
+                format!("\"{}\" is used by another server", server.name),
+            );
+        }
+
+        if let Err(message) = validate_host(&server.host) {
+            report.error(format!("servers.{}.host", index), message);
+        }
+
+        if server.sections.is_empty() {
+            report.warning(
+                format!("servers.{}.sections", index),
+                "no libraries are merged without sections",
+            );
+        }
+    }
+
+    if config.health_check_interval == 0 {
+        report.error("health_check_interval", "must be greater than 0");
+    }
+
+    if let Some(host) = &config.redirect_streams.host {
+        if let Err(message) = validate_host(host) {
+            report.error("redirect_streams.host", message);
+        }
+    }
+
+    if let Some(port) = config.port {
+        if !(1..=65535).contains(&port) {
+            report.error("port", format!("{} is not a valid port", port));
+        }
+    }
+
+    if config.cache.ttl == 0 {
+        report.error("cache.ttl", "must be greater than 0");
+    }
+
+    for (family, ttl) in &config.cache.ttls {
+        let key = format!("cache.ttls.{}", family);
+        if !KEY_FAMILIES.contains(&family.as_str()) {
+            report.warning(
+                key.clone(),
+                format!("unknown family, expected one of {}", KEY_FAMILIES.join(", ")),
+            );
+        }
+        if *ttl == 0 {
+            report.error(key, "must be greater than 0");
+        }
+    }
+
+    for (index, rule) in config.hub_rules.iter().enumerate() {
+        if rule.matcher.is_empty() {
+            report.warning(
+                format!("hub_rules.{}.match", index),
+                "rule has no match criteria and will never be applied",
+            );
+        } else if !rule.has_actions() {
+            report.warning(format!("hub_rules.{}", index), "rule doesn't change anything");
+        }
+        if rule.position == Some(0) {
+            report.error(
+                format!("hub_rules.{}.position", index),
+                "must be greater than 0, 1 is the top",
+            );
+        }
+    }
+
+    if config.test_script.is_some() {
+        report.warning("test_script", "not used anymore, see `scripts`");
+    }
+
+    for (name, script) in [
+        ("promoted_hubs", &config.scripts.promoted_hubs),
+        ("section_hubs", &config.scripts.section_hubs),
+        ("collection_children", &config.scripts.collection_children),
+    ] {
+        if let Some(Err(e)) = script.as_deref().map(scripting::check) {
+            report.error(format!("scripts.{}", name), e.to_string());
+        }
+    }
+
+    if config.scripts.max_operations == 0 {
+        report.error("scripts.max_operations", "must be greater than 0");
+    }
+
+    if config.scripts.timeout == 0 {
+        report.error("scripts.timeout", "must be greater than 0");
+    }
+
+    for (name, steps) in config.pipelines.routes() {
+        let context = StepContext {
+            hook: ScriptHook::from_name(name),
+            ..StepContext::default()
+        };
+        for (index, step) in steps.iter().enumerate() {
+            if let Err(e) = registry::build(step, &context) {
+                report.error(format!("pipelines.{}.{}", name, index), e.to_string());
+            }
+        }
+    }
+
+    if let Some(directory) = &config.plugins.directory {
+        if !Path::new(directory).is_dir() {
+            report.error("plugins.directory", format!("\"{}\" is not a directory", directory));
+        }
+    }
+
+    if config.plugins.fuel == 0 {
+        report.error("plugins.fuel", "must be greater than 0");
+    }
+
+    if config.plugins.max_memory == 0 {
+        report.error("plugins.max_memory", "must be greater than 0");
+    }
+
+    if config.cache.max_size == 0 {
+        report.error("cache.max_size", "must be greater than 0");
+    }
+
+    if config.cache.storage == CacheStorage::Disk && config.cache.disk.max_size == 0 {
+        report.error("cache.disk.max_size", "must be greater than 0");
+    }
+
+    if config.http.timeout == 0 {
+        report.error("http.timeout", "must be greater than 0");
+    }
+
+    if config.http.concurrency == 0 {
+        report.error("http.concurrency", "must be greater than 0");
+    }
+
+    if config.http.retry_min_delay > config.http.retry_max_delay {
+        report.error(
+            "http.retry_min_delay",
+            "must not be greater than `http.retry_max_delay`",
+        );
+    }
+
+    for (name, resolutions) in [
+        (
+            "video_transcode_fallback_for",
+            &config.video_transcode_fallback_for,
+        ),
+        ("force_direct_play_for", &config.force_direct_play_for),
+    ] {
+        for resolution in resolutions.iter().flatten() {
+            if !VIDEO_RESOLUTIONS.contains(&resolution.to_lowercase().as_str()) {
+                report.error(
+                    name,
+                    format!(
+                        "unknown resolution \"{}\", expected one of {}",
+                        resolution,
+                        VIDEO_RESOLUTIONS.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    let on_deck = &config.better_on_deck;
+    if on_deck.enabled && on_deck.in_progress.is_none() && on_deck.next_up.is_none() {
+        report.warning(
+            "better_on_deck",
+            "enabled without an in_progress or next_up collection",
+        );
+    }
+}
+
+fn validate_host(host: &str) -> Result<(), String> {
+    if host.is_empty() {
+        return Err("host is not set".to_string());
+    }
+
+    let url = Url::parse(host).map_err(|e| format!("\"{}\" is not a valid URL: {}", host, e))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "\"{}\" should start with http:// or https://",
+            host
+        ));
+    }
+
+    if url.host_str().is_none() {
+        return Err(format!("\"{}\" is missing a hostname", host));
+    }
+
+    Ok(())
+}
+
+/// Returns every key in the merged sources that doesn't map to a setting.
+fn ignored_keys(figment: &Figment) -> Vec<String> {
+    let Ok(value) = figment.extract::<Value>() else {
+        return vec![];
+    };
+
+    let mut ignored = vec![];
+    let _: Result<Config, _> = serde_ignored::deserialize(&value, |path| {
+        // Drop the markers serde_ignored adds for `Option` fields.
+        ignored.push(path.to_string().replace("?.", ""));
+    });
+
+    ignored
+}
+
+/// Returns the file the value at `key` was read from, or `None` when it was
+/// set through the environment.
+fn source_file<'a>(figment: &'a Figment, key: &str) -> Option<&'a Path> {
+    // Keys inside of lists can't be looked up, use the list they are in.
+    let segments: Vec<&str> = key.split('.').collect();
+    let metadata = (1..=segments.len())
+        .rev()
+        .find_map(|len| figment.find_metadata(&segments[..len].join(".")))?;
+
+    match &metadata.source {
+        Some(Source::File(file)) => Some(file),
+        _ => None,
+    }
+}
+
+/// Locations of keys across all the files a config was read from.
+#[derive(Default)]
+struct KeyLocations {
+    files: HashMap<PathBuf, KeyLines>,
+}
+
+impl KeyLocations {
+    // `file:line` if the key can be found in the file, `file` otherwise.
+    fn locate(&mut self, file: &Path, key: &str) -> String {
+        let lines = self
+            .files
+            .entry(file.to_path_buf())
+            .or_insert_with(|| KeyLines::read(file));
+
+        match lines.get(key) {
+            Some(line) => format!("{}:{}", file.display(), line),
+            None => file.display().to_string(),
+        }
+    }
+}
+
+/// Line numbers of every key in a YAML, JSON or TOML file, keyed by their
+/// dotted path.
+#[derive(Default)]
+struct KeyLines {
+    lines: HashMap<String, usize>,
+}
+
+impl KeyLines {
+    fn read(path: &Path) -> Self {
+        let Ok(source) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+
+        // A file that doesn't parse is reported by the extraction itself.
+        if path.extension().and_then(OsStr::to_str) == Some("toml") {
+            let mut lines = HashMap::new();
+            if let Ok(table) = toml::from_str::<TomlKeys>(&source) {
+                table.collect("", &source, &mut lines);
+            }
+            return Self { lines };
+        }
+
+        let mut receiver = KeyLinesReceiver::default();
+        let _ = Parser::new_from_str(&source).load(&mut receiver, false);
+
+        Self {
+            lines: receiver.lines,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<usize> {
+        self.lines.get(key).copied()
+    }
+}
+
+enum Frame {
+    Map { path: String, key: Option<String> },
+    Seq { path: String, index: usize },
+}
+
+#[derive(Default)]
+struct KeyLinesReceiver {
+    stack: Vec<Frame>,
+    lines: HashMap<String, usize>,
+}
+
+impl KeyLinesReceiver {
+    // Path of the value that is about to start in the current container.
+    fn child_path(&self) -> String {
+        let join = |path: &str, segment: &str| {
+            if path.is_empty() {
+                segment.to_string()
+            } else {
+                format!("{}.{}", path, segment)
+            }
+        };
+
+        match self.stack.last() {
+            Some(Frame::Map { path, key }) => join(path, key.as_deref().unwrap_or_default()),
+            Some(Frame::Seq { path, index }) => join(path, &index.to_string()),
+            None => String::new(),
+        }
+    }
+
+    // Marks the value in the current container as complete.
+    fn end_value(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::Map { key, .. }) => *key = None,
+            Some(Frame::Seq { index, .. }) => *index += 1,
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for KeyLinesReceiver {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Map {
+                    key: key @ None, ..
+                }) = self.stack.last_mut()
+                {
+                    *key = Some(value);
+                    let path = self.child_path();
+                    self.lines.insert(path, mark.line());
+                } else {
+                    self.end_value();
+                }
+            }
+            Event::Alias(_) => self.end_value(),
+            Event::MappingStart(..) => {
+                let path = self.child_path();
+                self.stack.push(Frame::Map { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.child_path();
+                self.stack.push(Frame::Seq { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+                self.end_value();
+            }
+            _ => {}
+        }
+    }
+}
+
+// The keys of a TOML value, with where they are in the file. Only tables
+// and arrays of them have keys.
+enum TomlKeys {
+    Table(Vec<(Spanned<String>, TomlKeys)>),
+    Array(Vec<TomlKeys>),
+    Value,
+}
+
+impl TomlKeys {
+    // Adds the line of every key under `path` to `lines`.
+    fn collect(&self, path: &str, source: &str, lines: &mut HashMap<String, usize>) {
+        let join = |segment: &str| {
+            if path.is_empty() {
+                segment.to_string()
+            } else {
+                format!("{}.{}", path, segment)
+            }
+        };
+
+        match self {
+            TomlKeys::Table(entries) => {
+                for (key, value) in entries {
+                    let path = join(key.get_ref());
+                    let line = source[..key.span().start].matches('\n').count() + 1;
+                    lines.insert(path.clone(), line);
+                    value.collect(&path, source, lines);
+                }
+            }
+            TomlKeys::Array(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    value.collect(&join(&index.to_string()), source, lines);
+                }
+            }
+            TomlKeys::Value => {}
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TomlKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TomlKeysVisitor)
+    }
+}
+
+struct TomlKeysVisitor;
+
+impl<'de> Visitor<'de> for TomlKeysVisitor {
+    type Value = TomlKeys;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a TOML value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<TomlKeys, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key::<Spanned<String>>()? {
+            entries.push((key, map.next_value()?));
+        }
+        Ok(TomlKeys::Table(entries))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<TomlKeys, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(TomlKeys::Array(values))
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<TomlKeys, E> {
+        Ok(TomlKeys::Value)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<TomlKeys, E> {
+        Ok(TomlKeys::Value)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<TomlKeys, E> {
+        Ok(TomlKeys::Value)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<TomlKeys, E> {
+        Ok(TomlKeys::Value)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<TomlKeys, E> {
+        Ok(TomlKeys::Value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::fixtures::{errors, Fixtures, REQUIRED};
+
+    #[test]
+    fn unknown_keys_are_reported_at_their_line() {
+        let fixtures = Fixtures::new();
+        let path = fixtures.write(
+            "config.yml",
+            &format!("{}disabale_leaf_count: true\ntranscode_fallback_for: 4k\n", REQUIRED),
+        );
+
+        let (_, report) = fixtures.check("config.yml");
+
+        let mut errors = errors(&report);
+        errors.sort();
+        assert_eq!(
+            errors,
+            [
+                format!("error [{}:6] `disabale_leaf_count`: unknown key", path.display()),
+                format!("error [{}:7] `transcode_fallback_for`: unknown key", path.display()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_unknown_keys_are_reported_at_their_line() {
+        let fixtures = Fixtures::new();
+        let path = fixtures.write(
+            "config.yml",
+            &REQUIRED.replace("cache: {}", "cache:\n  ttl: 60\n  tll: 60"),
+        );
+
+        let (_, report) = fixtures.check("config.yml");
+
+        assert_eq!(
+            errors(&report),
+            [format!("error [{}:5] `cache.tll`: unknown key", path.display())]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_of_toml_files_are_reported_at_their_line() {
+        let fixtures = Fixtures::new();
+        let path = fixtures.write(
+            "config.toml",
+            r#"host = "http://plex.test:32400"
+better_on_deck = {}
+exclude_watched = {}
+redirect_streams = {}
+
+[cache]
+ttl = 60
+tll = 60
+
+[[servers]]
+name = "4k"
+host = "http://plex-4k:32400"
+sectoins = { 1 = 3 }
+"#,
+        );
+
+        let (_, report) = fixtures.check("config.toml");
+
+        let mut errors = errors(&report);
+        errors.sort();
+        assert_eq!(
+            errors,
+            [
+                format!("error [{}:13] `servers.0.sectoins`: unknown key", path.display()),
+                format!("error [{}:8] `cache.tll`: unknown key", path.display()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_of_included_files_are_reported_there() {
+        let fixtures = Fixtures::new();
+        let base = fixtures.write("base.yml", "redirect_streams: {}\nreplex_url: nope\n");
+        fixtures.write(
+            "config.yml",
+            &format!("include: base.yml\n{}", REQUIRED.replace("redirect_streams: {}\n", "")),
+        );
+
+        let (_, report) = fixtures.check("config.yml");
+
+        assert_eq!(
+            errors(&report),
+            [format!("error [{}:2] `replex_url`: unknown key", base.display())]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_of_profiles_are_reported() {
+        let fixtures = Fixtures::new();
+        fixtures.write(
+            "config.yml",
+            &format!(
+                "{}profiles:\n  - match: {{ platform: Android }}\n    settings:\n      hero_row: x\n",
+                REQUIRED
+            ),
+        );
+
+        let (_, report) = fixtures.check("config.yml");
+
+        let errors = errors(&report);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("`profiles.0.settings.hero_row`: unknown key"));
+    }
+
+    #[test]
+    fn values_out_of_range_are_reported_at_their_line() {
+        let fixtures = Fixtures::new();
+        let path = fixtures.write(
+            "config.yml",
+            &REQUIRED
+                .replace("http://plex.test:32400", "plex.test")
+                .replace("cache: {}", "cache:\n  max_size: 0"),
+        );
+
+        let (_, report) = fixtures.check("config.yml");
+
+        let errors = errors(&report);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors.contains(&format!(
+            "error [{}:4] `cache.max_size`: must be greater than 0",
+            path.display()
+        )));
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with(&format!("error [{}:1] `host`", path.display()))));
+    }
+}
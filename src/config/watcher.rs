@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tokio::signal::unix::{signal, SignalKind};
+
+use super::{config_path, source, Config};
+use crate::{http_client, plugins};
+
+// How often the config files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Spawns background tasks that reload the configuration whenever the config
+/// file, one of the files it includes or one of the secret files they point
+/// to changes on disk, or the process receives a SIGHUP. Plugins are loaded again along with the config, and
+/// when one of them changes.
+pub fn watch() {
+    tokio::spawn(watch_files(config_path()));
+    tokio::spawn(watch_sighup());
+    tokio::spawn(watch_plugins());
+}
+
+async fn watch_files(path: String) {
+    let mut last_modified = modified_at(&path);
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let modified = modified_at(&path);
+        if modified == last_modified {
+            continue;
+        }
+
+        last_modified = modified;
+        tracing::info!("Config or secret files of {} changed, reloading", path);
+        reload();
+    }
+}
+
+async fn watch_sighup() {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to listen for SIGHUP");
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        tracing::info!("Received SIGHUP, reloading config");
+        reload();
+    }
+}
+
+async fn watch_plugins() {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        refresh_plugins().await;
+    }
+}
+
+fn reload() {
+    let previous = Config::load();
+    if let Err(e) = Config::reload() {
+        tracing::error!(
+            error = %e,
+            "Invalid configuration, keeping the previous config"
+        );
+        return;
+    }
+
+    let config = Config::load();
+    if config.http != previous.http {
+        http_client::rebuild(&config.http);
+    }
+    for key in restart_required(&previous, &config) {
+        tracing::warn!("Changes to `{}` require a restart to take effect", key);
+    }
+
+    tokio::spawn(refresh_plugins());
+}
+
+// Settings that are only read at startup, and that changed between
+// `previous` and `config`.
+fn restart_required(previous: &Config, config: &Config) -> Vec<&'static str> {
+    let (old, new) = (&previous.cache, &config.cache);
+    [
+        ("port", previous.port != config.port),
+        ("rust_log", previous.rust_log != config.rust_log),
+        ("cache.ttl", old.ttl != new.ttl),
+        ("cache.ttls", old.ttls != new.ttls),
+        ("cache.max_size", old.max_size != new.max_size),
+        ("cache.compression", old.compression != new.compression),
+        ("cache.storage", old.storage != new.storage),
+        ("cache.disk", old.disk != new.disk),
+    ]
+    .into_iter()
+    .filter_map(|(key, changed)| changed.then_some(key))
+    .collect()
+}
+
+// Plugins are read and compiled on a thread of their own, as that blocks.
+// The global config on purpose, as its `plugin_directories` cover the
+// profiles too.
+async fn refresh_plugins() {
+    let config = Config::load();
+    if let Err(e) = tokio::task::spawn_blocking(move || plugins::refresh(&config)).await {
+        tracing::error!(error = %e, "Failed to load plugins");
+    }
+}
+
+// Modification times of the config file, everything it includes and the
+// secret files. Includes and secrets are looked up on every call, so files
+// added to `include` or new `*_file` keys are watched too.
+fn modified_at(path: &str) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files = source::files(path).unwrap_or_else(|_| vec![PathBuf::from(path)]);
+    files.extend(source::secret_files(&files));
+
+    files
+        .into_iter()
+        .map(|file| {
+            let modified = modified_time(&file);
+            (file, modified)
+        })
+        .collect()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::plex::testing;
+
+    #[test]
+    fn settings_read_at_startup_require_a_restart() {
+        let previous = testing::config(json!({ "cache": { "ttl": 60 } }));
+        let config = testing::config(json!({
+            "port": 3001,
+            "cache": { "ttl": 60, "enabled": true, "max_size": 1 },
+            "http": { "timeout": 1 },
+        }));
+
+        assert_eq!(restart_required(&previous, &config), ["port", "cache.max_size"]);
+    }
+}
@@ -0,0 +1,167 @@
+use data_encoding::HEXLOWER;
+use http::{HeaderMap, HeaderValue};
+use itertools::Itertools;
+use salvo::http::StatusError;
+use salvo::prelude::*;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::cache::CACHE_MANAGER;
+use crate::handlers::promoted_hubs;
+use crate::models::{ContentType, MediaContainer};
+use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
+use crate::plex::models::PlexContext;
+
+/// Reports the number of entries, their size and the hit ratio for every key
+/// family, and the totals over all families.
+#[handler]
+pub async fn cache_stats(res: &mut Response) -> Result<(), anyhow::Error> {
+    let families = CACHE_MANAGER.stats().await?;
+
+    let entries: u64 = families.iter().map(|f| f.entries).sum();
+    let bytes: u64 = families.iter().map(|f| f.bytes).sum();
+    let hits: u64 = families.iter().map(|f| f.hits).sum();
+    let misses: u64 = families.iter().map(|f| f.misses).sum();
+    let lookups = hits + misses;
+
+    res.render(Json(json!({
+        "entries": entries,
+        "bytes": bytes,
+        "hits": hits,
+        "misses": misses,
+        "hit_ratio": (lookups > 0).then(|| hits as f64 / lookups as f64),
+        "families": families,
+    })));
+    Ok(())
+}
+
+/// Purges the whole cache, the entries of one key family with `?family=`, or
+/// the entries of one user with `?token=`. The token is only reported by the
+/// start of its hash.
+#[handler]
+pub async fn purge_cache(
+    req: &mut Request,
+    res: &mut Response,
+) -> Result<(), anyhow::Error> {
+    let family = req.query::<String>("family");
+    let token = req.query::<String>("token");
+
+    let purged = match (family, token) {
+        (Some(_), Some(_)) => {
+            res.render(
+                StatusError::bad_request()
+                    .brief("Use either `family` or `token`, not both."),
+            );
+            return Ok(());
+        }
+        (Some(family), None) => {
+            CACHE_MANAGER.delete_prefix(&format!("{}:", family)).await?;
+            json!({ "family": family })
+        }
+        (None, Some(token)) => {
+            CACHE_MANAGER.delete_token(&token).await?;
+            json!({ "token": redact(&token) })
+        }
+        (None, None) => {
+            CACHE_MANAGER.clear().await?;
+            json!("all")
+        }
+    };
+
+    tracing::info!(purged = %purged, "Purged cache");
+    res.render(Json(json!({ "purged": purged })));
+    Ok(())
+}
+
+// Identifies a token in logs and responses without giving it away: the
+// start of its SHA-256 hash.
+fn redact(token: &str) -> String {
+    let hash = HEXLOWER.encode(&Sha256::digest(token.as_bytes()));
+    format!("sha256:{}", &hash[..12])
+}
+
+/// Fills the cache for the home screen of a user, given by `?token=`, by
+/// fetching and transforming it the way a request for it would.
+///
+/// Cache keys include the client, so `client_identifier` and `platform`
+/// should match the device that is going to use the cache. The home screen
+/// shows the libraries in `sections`, a comma separated list of ids, or every
+/// library of the server when left out.
+#[handler]
+pub async fn warm_cache(
+    req: &mut Request,
+    res: &mut Response,
+) -> Result<(), anyhow::Error> {
+    let Some(token) = req.query::<String>("token") else {
+        res.render(StatusError::bad_request().brief("Missing `token`."));
+        return Ok(());
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Some(client_identifier) = req.query::<String>("client_identifier") {
+        headers.insert(
+            "X-Plex-Client-Identifier",
+            HeaderValue::from_str(&client_identifier)?,
+        );
+    }
+
+    let mut params = PlexContext {
+        token: Some(token),
+        client_identifier: req.query::<String>("client_identifier"),
+        platform: req
+            .query::<String>("platform")
+            .and_then(|platform| platform.parse().ok())
+            .unwrap_or_default(),
+        ..PlexContext::default()
+    };
+    let plex_client = PlexClient::from_context(&params, &headers)?;
+
+    let sections: Vec<String> = match req.query::<String>("sections") {
+        Some(sections) => sections
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        None => section_ids(&plex_client).await?,
+    };
+    let Some(first) = sections.first() else {
+        res.render(StatusError::bad_request().brief("No libraries to warm up."));
+        return Ok(());
+    };
+
+    // The same parameters as the request of a client for its home screen.
+    params.content_directory_id = Some(vec![first.clone()]);
+    params.pinned_content_directory_id = Some(sections.clone());
+
+    let mut url = Url::parse(&format!("{}/hubs/promoted", plex_client.config.host))?;
+    url.query_pairs_mut()
+        .append_pair("pinnedContentDirectoryID", &sections.iter().join(","))
+        .extend_pairs(promoted_hubs::query_params(&params));
+
+    promoted_hubs::fetch_and_transform_upstream_data(
+        &url,
+        ContentType::Json,
+        &params,
+        &plex_client,
+    )
+    .await?;
+
+    tracing::info!(sections = ?sections, "Warmed up cache");
+    res.render(Json(json!({ "warmed": { "sections": sections } })));
+    Ok(())
+}
+
+// Ids of all libraries on the server that the user of `plex_client` has
+// access to.
+async fn section_ids(plex_client: &PlexClient) -> Result<Vec<String>, PlexError> {
+    let res = PlexError::check(plex_client.get("/library/sections").await?)?;
+    let mut container = MediaContainer::from_reqwest_response(res).await?;
+
+    Ok(container
+        .take_children()
+        .into_iter()
+        .filter_map(|directory| directory.key)
+        .collect())
+}
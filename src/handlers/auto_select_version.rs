@@ -6,14 +6,17 @@ use salvo::prelude::*;
 #[handler]
 pub async fn handler(req: &mut Request) {
     let params: PlexContext = req.extract().await.unwrap_or_default();
-    let plex_client = PlexClient::from_request(req, &params);
+    let Ok(plex_client) = PlexClient::from_request(req, &params) else {
+        tracing::debug!("Skipping auto select as the request has no token");
+        return;
+    };
 
-    if params.screen_resolution.is_empty() {
+    let Some(resolution) = params.screen_resolution.first() else {
         tracing::debug!(
             "Skipping auto select as no screen resolution specified"
         );
         return;
-    }
+    };
 
     if let Some(media_index) = req.queries().get("mediaIndex") {
         if media_index != "-1" {
@@ -27,13 +30,17 @@ pub async fn handler(req: &mut Request) {
     if let Some(path) = req.queries().get("path") {
         let item = match plex_client.get_item_by_key(path.to_string()).await {
             Ok(item) => item,
-            Err(_) => {
-                tracing::debug!("Failed to get item by path: {}", path);
+            Err(e) => {
+                tracing::debug!(error = %e, "Failed to get item by path: {}", path);
                 return;
             }
         };
 
-        let media = &item.metadata[0].media;
+        let Some(metadata) = item.children().first() else {
+            tracing::debug!("Skipping auto select as the item was not found");
+            return;
+        };
+        let media = &metadata.media;
         if media.len() <= 1 {
             tracing::debug!(
                 "Only one media version available, skipping auto select"
@@ -47,14 +54,12 @@ pub async fn handler(req: &mut Request) {
             .or_else(|| req.queries().get("maxVideoBitrate"))
             .and_then(|v| v.parse::<i64>().ok());
 
-        let device_density = params.screen_resolution[0].height
-            * params.screen_resolution[0].width;
+        let device_density = resolution.height * resolution.width;
         let sorted_media = media
             .iter()
-            .filter(|m| m.height.is_some() && m.width.is_some())
-            .min_by_key(|m| {
-                (device_density - (m.height.unwrap() * m.width.unwrap())).abs()
-            });
+            .filter_map(|m| Some((m, m.height? * m.width?)))
+            .min_by_key(|(_, density)| (device_density - density).abs())
+            .map(|(m, _)| m);
 
         if let Some(best_match) = sorted_media {
             let index = media
@@ -1,21 +1,23 @@
+use itertools::Itertools;
 use salvo::prelude::*;
 
 use crate::models::{MediaContainer, WrappedMediaContainer};
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
-use crate::transforms::{
-    CollectionStyleTransform, SectionMixTransform, TransformBuilder,
-};
+use crate::plex::servers;
+use crate::transforms::registry::{self, StepContext};
+use crate::transforms::{ScriptHook, TransformBuilder};
 use crate::utils::*;
 
 #[handler]
 pub async fn handler(
     req: &mut Request,
     res: &mut Response,
-) -> Result<(), anyhow::Error> {
+) -> Result<(), PlexError> {
     // Extract config and parameters
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
+    let plex_client = PlexClient::from_request(req, &params)?;
 
     // Perform upstream request and handle response
     match fetch_and_transform_upstream_data(req, &params, &plex_client).await {
@@ -33,16 +35,20 @@ async fn fetch_and_transform_upstream_data(
     req: &Request,
     params: &PlexContext,
     plex_client: &PlexClient,
-) -> anyhow::Result<WrappedMediaContainer> {
+) -> Result<WrappedMediaContainer, PlexError> {
     let content_type = get_content_type_from_headers(req.headers());
-    let collection_ids = req.param::<String>("ids").unwrap();
-    let collection_ids: Vec<i64> = collection_ids
-        .split(',')
-        .filter_map(|v| v.parse::<i64>().ok())
-        .collect();
+    let collection_ids = req
+        .param::<String>("ids")
+        .ok_or_else(|| PlexError::BadRequest("Missing collection ids".to_string()))?;
 
-    // Create a stubbed media container
-    let mut container = MediaContainer::default();
+    let split = registry::split_at_merge(&plex_client.config.pipelines.collection_children);
+
+    // Merged hubs can hold collections of several servers. Without
+    // `merge_servers` only the ones of the first server are shown.
+    let mut groups = servers::group_collection_ids(&collection_ids);
+    if !split.merge {
+        groups.truncate(1);
+    }
 
     let limit = params.container_size.unwrap_or(50);
     let offset = params.container_start.unwrap_or(0);
@@ -53,17 +59,69 @@ async fn fetch_and_transform_upstream_data(
         && !params.include_advanced
         && !params.exclude_all_leaves;
 
-    TransformBuilder::new(plex_client, params)
-        .with_transform(SectionMixTransform {
-            collection_ids: collection_ids.clone(),
+    let mut containers = Vec::with_capacity(groups.len());
+    for (server, collection_ids) in groups {
+        let plex_client = match &server {
+            Some(name) => {
+                let server = plex_client.config.server(name).ok_or_else(|| {
+                    PlexError::NotFound(format!("Server {}", name))
+                })?;
+                plex_client.for_server(server).await?
+            }
+            None => plex_client.clone(),
+        };
+
+        // Create a stubbed media container
+        let mut container = MediaContainer::default();
+
+        let context = StepContext {
+            collection_ids,
             offset,
             limit,
-        })
-        .with_transform(CollectionStyleTransform {
-            collection_ids: collection_ids.clone(),
             is_hub,
-        })
-        // .with_transform(HubKeyTransform)
+            hook: Some(ScriptHook::CollectionChildren),
+            ..StepContext::default()
+        };
+
+        TransformBuilder::new(&plex_client, params)
+            .with_pipeline(split.before, &context)
+            .apply_to(&mut container)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!(error = %e, "Failed to transform media container");
+            });
+
+        containers.push(container);
+    }
+
+    let mut containers = containers.into_iter();
+    let mut container = containers.next().unwrap_or_default();
+
+    // Children of the other servers are mixed in like the ones of the other
+    // collections.
+    for mut other in containers {
+        let total_size = container.total_size.unwrap_or_default()
+            + other.total_size.unwrap_or_default();
+        let children = container
+            .take_children()
+            .into_iter()
+            .interleave(other.take_children())
+            .collect();
+
+        container.set_children(children);
+        container.total_size = Some(total_size);
+    }
+
+    let context = StepContext {
+        offset,
+        limit,
+        is_hub,
+        hook: Some(ScriptHook::CollectionChildren),
+        ..StepContext::default()
+    };
+
+    TransformBuilder::new(plex_client, params)
+        .with_pipeline(split.after, &context)
         .apply_to(&mut container)
         .await
         .unwrap_or_else(|e| {
@@ -1,6 +1,11 @@
+use crate::config::Config;
 use crate::models::WrappedMediaContainer;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
-use crate::utils::{add_query_param_salvo, get_content_type_from_headers};
+use crate::plex::upstream::{self, SERVER_PREFIX};
+use crate::utils::{
+    add_query_param_salvo, get_content_type_from_headers, url_from_request,
+};
 
 use salvo::prelude::*;
 
@@ -26,27 +31,85 @@ pub async fn photo_request_handler(
     req: &mut Request,
     _depot: &mut Depot,
     _res: &mut Response,
-) -> Result<(), anyhow::Error> {
-    let params: PlexContext = req.extract().await.unwrap();
-    if params.size.is_some() && params.clone().size.unwrap().contains('-')
+) -> Result<(), PlexError> {
+    let params: PlexContext = req.extract().await?;
     // (catched things like (medlium-240, large-500),i dont think size paramater orks at all, but who knows
-    // && params.platform.is_some()
-    // && params.clone().platform.unwrap().to_lowercase() == "android"
-    {
-        let size: String = params
-            .clone()
-            .size
-            .unwrap()
-            .split('-')
-            .last()
-            .unwrap()
-            .parse()
-            .unwrap();
-
-        add_query_param_salvo(req, "height".to_string(), size.clone());
-        add_query_param_salvo(req, "width".to_string(), size.clone());
+    if let Some(size) = params.size.as_deref().filter(|size| size.contains('-')) {
+        let size: u32 = size
+            .rsplit('-')
+            .next()
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| PlexError::BadRequest(format!("Invalid size {:?}", size)))?;
+
+        add_query_param_salvo(req, "height".to_string(), size.to_string());
+        add_query_param_salvo(req, "width".to_string(), size.to_string());
         add_query_param_salvo(req, "quality".to_string(), "80".to_string());
     }
 
+    // Images of the other servers are transcoded by the server they're on.
+    if let Some((name, path)) = params
+        .url
+        .as_deref()
+        .and_then(|url| url.strip_prefix(SERVER_PREFIX))
+        .and_then(|rest| rest.split_once('/'))
+    {
+        let config = Config::for_request(req);
+        if let Some(server) = config.server(name) {
+            let mut url = url_from_request(req);
+            let query: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(key, value)| match key.as_ref() {
+                    "url" => (key.into_owned(), format!("/{}", path)),
+                    _ => (key.into_owned(), value.into_owned()),
+                })
+                .collect();
+            url.query_pairs_mut().clear().extend_pairs(query);
+
+            if let Ok(uri) = hyper::Uri::try_from(url.as_str()) {
+                req.set_uri(uri);
+            }
+            upstream::select(req, server);
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler]
+    async fn transcode(req: &mut Request, res: &mut Response) {
+        res.render(req.query::<String>("width").unwrap_or_default());
+    }
+
+    fn service() -> Service {
+        Service::new(
+            Router::with_path("/photo/transcode")
+                .hoop(photo_request_handler)
+                .goal(transcode),
+        )
+    }
+
+    #[tokio::test]
+    async fn sizes_set_the_dimensions() {
+        let mut res = TestClient::get("http://localhost/photo/transcode?size=large-500")
+            .send(&service())
+            .await;
+
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(res.take_string().await.unwrap(), "500");
+    }
+
+    #[tokio::test]
+    async fn bad_sizes_are_bad_requests() {
+        let res = TestClient::get("http://localhost/photo/transcode?size=large-huge")
+            .send(&service())
+            .await;
+
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+}
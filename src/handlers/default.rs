@@ -1,21 +1,29 @@
 use itertools::Itertools;
 use salvo::prelude::*;
 
-use crate::config::Config;
 use crate::models::{MediaContainer, Platform, Style, WrappedMediaContainer};
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
-use crate::transforms::{MediaStyleTransform, TransformBuilder};
+use crate::transforms::registry::StepContext;
+use crate::transforms::TransformBuilder;
 use crate::utils::*;
 
 #[handler]
 pub async fn handler(
     req: &mut Request,
     res: &mut Response,
-) -> Result<(), anyhow::Error> {
-    let config = Config::load();
+) -> Result<(), PlexError> {
+    // Checked first, as there is nothing to ask Plex without them.
+    let style = req
+        .param::<Style>("style")
+        .ok_or_else(|| PlexError::BadRequest("Unknown style".to_string()))?;
+    let path = req
+        .param::<String>("**rest")
+        .ok_or_else(|| PlexError::BadRequest("Missing path".to_string()))?;
+
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
+    let plex_client = PlexClient::from_request(req, &params)?;
     let content_type = get_content_type_from_headers(req.headers());
 
     if let (Some(pinned_content_directory_id), Some(content_directory_id)) = (
@@ -30,10 +38,10 @@ pub async fn handler(
     }
 
     // Consolidate query parameter modifications
-    adjust_query_params(req, &params, config);
+    adjust_query_params(req, &params);
 
     // Perform upstream request and handle response
-    match fetch_and_transform_upstream_data(req, &params, &plex_client).await {
+    match fetch_and_transform_upstream_data(req, &params, &plex_client, &path, style).await {
         Ok(response) => res.render(response),
         Err(e) => {
             tracing::error!(error = %e, "Failed to process upstream data");
@@ -44,11 +52,7 @@ pub async fn handler(
     Ok(())
 }
 
-fn adjust_query_params(
-    req: &mut Request,
-    params: &PlexContext,
-    _config: &Config,
-) {
+fn adjust_query_params(req: &mut Request, params: &PlexContext) {
     if let Some(pinned_id) = &params.pinned_content_directory_id {
         let pinned_ids = pinned_id.iter().join(",");
         add_query_param_salvo(
@@ -81,32 +85,28 @@ async fn fetch_and_transform_upstream_data(
     req: &Request,
     params: &PlexContext,
     plex_client: &PlexClient,
-) -> anyhow::Result<WrappedMediaContainer> {
+    path: &str,
+    style: Style,
+) -> Result<WrappedMediaContainer, PlexError> {
     let mut url = url_from_request(req);
-    let path = req.param::<String>("**rest").unwrap();
-    let style = req.param::<Style>("style").unwrap();
     let content_type = get_content_type_from_headers(req.headers());
 
-    url.set_path(&path);
+    url.set_path(path);
 
     // Fetch data from upstream.
-    let upstream_res = plex_client.get(url.as_ref()).await?;
-    let status = upstream_res.status();
-    if status != reqwest::StatusCode::OK {
-        tracing::error!(status = ?status, "Failed to get plex response");
-        return Err(anyhow::anyhow!(
-            "Upstream request failed with status: {}",
-            status
-        ));
-    }
+    let upstream_res = PlexError::check(plex_client.get(url.as_ref()).await?)?;
 
     // Deserialize the upstream response.
     let mut container =
         MediaContainer::from_reqwest_response(upstream_res).await?;
 
+    let context = StepContext {
+        style,
+        ..StepContext::default()
+    };
+
     TransformBuilder::new(plex_client, params)
-        .with_transform(MediaStyleTransform { style })
-        // .with_transform(UserStateTransform)
+        .with_pipeline(&plex_client.config.pipelines.hub_items, &context)
         .apply_to(&mut container)
         .await
         .unwrap_or_else(|e| {
@@ -117,3 +117,21 @@ async fn fetch_and_transform_upstream_data(
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use salvo::test::TestClient;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn unknown_styles_are_bad_requests() {
+        let service = Service::new(Router::with_path("/replex/<style>/<**rest>").get(handler));
+
+        let res = TestClient::get("http://localhost/replex/banner/hubs/items")
+            .send(&service)
+            .await;
+
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+}
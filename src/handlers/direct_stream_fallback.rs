@@ -3,6 +3,7 @@ use salvo::prelude::*;
 
 use crate::models::MediaContainer;
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
 use crate::utils::{add_query_param_salvo, url_from_request};
 
@@ -11,10 +12,10 @@ use crate::utils::{add_query_param_salvo, url_from_request};
 pub async fn handler(
     req: &mut Request,
     _res: &mut Response,
-) -> Result<(), anyhow::Error> {
+) -> Result<(), PlexError> {
     // Extract configuration and request parameters.
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
+    let plex_client = PlexClient::from_request(req, &params)?;
 
     // Check if "directPlay" is explicitly set to "1" (true).
     if req.queries().get("directPlay").unwrap_or(&"1".to_string()) != "1" {
@@ -46,11 +47,12 @@ pub async fn handler(
             );
             set_direct_stream(req);
         }
-        status => {
-            tracing::error!(status = ?status, "Failed to get plex response");
-            return Err(
-                salvo::http::StatusError::internal_server_error().into()
+        _ => {
+            tracing::error!(
+                status = ?upstream_res.status(),
+                "Failed to get plex response"
             );
+            return PlexError::check(upstream_res).map(|_| ());
         }
     };
 
@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
 use crate::utils::replace_query;
 
@@ -7,10 +8,10 @@ use salvo::prelude::*;
 
 /// Forces the maximum video quality based on various conditions.
 #[handler]
-pub async fn handler(req: &mut Request) -> Result<(), anyhow::Error> {
+pub async fn handler(req: &mut Request) -> Result<(), PlexError> {
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
-    let config = Config::load();
+    let plex_client = PlexClient::from_request(req, &params)?;
+    let config = Config::for_request(req);
     let mut queries = req.queries().clone();
 
     // If bitrate limitations are present, clear them and set quality to maximum.
@@ -60,11 +61,17 @@ pub async fn handler(req: &mut Request) -> Result<(), anyhow::Error> {
                 .get("mediaIndex")
                 .and_then(|index| index.parse::<usize>().ok())
                 .unwrap_or(0);
-            let media_item = &item.metadata[0].media[media_index];
+            let media_item = item
+                .children()
+                .first()
+                .and_then(|metadata| metadata.media.get(media_index));
 
             // Use iter().any() to check if the current resolution matches any of the forced resolutions.
-            if force_resos.iter().any(|reso| {
-                media_item.video_resolution.as_deref() == Some(reso.as_str())
+            // Items without that media are left to Plex.
+            if media_item.is_some_and(|media_item| {
+                force_resos.iter().any(|reso| {
+                    media_item.video_resolution.as_deref() == Some(reso.as_str())
+                })
             }) {
                 queries.insert("directPlay".to_string(), "1".to_string());
             }
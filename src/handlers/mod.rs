@@ -1,5 +1,6 @@
 mod common_handlers;
 
+mod admin_cache;
 mod auto_select_version;
 mod collection_children;
 mod default;
@@ -15,6 +16,7 @@ pub use common_handlers::{
     empty_media_container_handler, photo_request_handler, ping,
 };
 
+pub use admin_cache::{cache_stats, purge_cache, warm_cache};
 pub use auto_select_version::handler as auto_select_version_handler;
 pub use collection_children::handler as collection_children_handler;
 pub use default::handler as default_handler;
@@ -1,21 +1,21 @@
 use itertools::Itertools;
 use salvo::prelude::*;
+use url::Url;
 
-use crate::config::Config;
-use crate::models::{MediaContainer, Platform, WrappedMediaContainer};
+use crate::models::{ContentType, MediaContainer, Platform, WrappedMediaContainer};
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
-use crate::transforms::*;
+use crate::plex::servers::HubRoute;
 use crate::utils::*;
 
 #[handler]
 pub async fn handler(
     req: &mut Request,
     res: &mut Response,
-) -> Result<(), anyhow::Error> {
-    let config = Config::load();
+) -> Result<(), PlexError> {
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
+    let plex_client = PlexClient::from_request(req, &params)?;
     let content_type = get_content_type_from_headers(req.headers());
 
     // Plex makes a call for every library that is pinned to the home screen.
@@ -36,10 +36,12 @@ pub async fn handler(
     }
 
     // Consolidate query parameter modifications
-    adjust_query_params(req, &params, config);
+    adjust_query_params(req, &params);
+
+    let url = url_from_request(req);
 
     // Perform upstream request and handle response
-    match fetch_and_transform_upstream_data(req, &params, &plex_client).await {
+    match fetch_and_transform_upstream_data(&url, content_type, &params, &plex_client).await {
         Ok(response) => res.render(response),
         Err(e) => {
             tracing::error!(error = %e, "Failed to process upstream data");
@@ -50,10 +52,20 @@ pub async fn handler(
     Ok(())
 }
 
-fn adjust_query_params(req: &mut Request, params: &PlexContext, _config: &Config) {
+fn adjust_query_params(req: &mut Request, params: &PlexContext) {
+    for (name, value) in query_params(params) {
+        add_query_param_salvo(req, name.to_string(), value);
+    }
+}
+
+/// Query parameters that are added to the request for the hubs of the
+/// pinned libraries in `params`.
+pub fn query_params(params: &PlexContext) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+
     if let Some(pinned_id) = &params.pinned_content_directory_id {
         let pinned_ids = pinned_id.iter().join(",");
-        add_query_param_salvo(req, "contentDirectoryID".to_string(), pinned_ids);
+        query.push(("contentDirectoryID", pinned_ids));
     }
 
     let mut count = params.count.unwrap_or(25);
@@ -63,45 +75,55 @@ fn adjust_query_params(req: &mut Request, params: &PlexContext, _config: &Config
     }
 
     // Always include GUIDs for banners.
-    add_query_param_salvo(req, "includeGuids".to_string(), "1".to_string());
-    add_query_param_salvo(req, "count".to_string(), count.to_string());
+    query.push(("includeGuids", "1".to_string()));
+    query.push(("count", count.to_string()));
+    query
 }
 
-async fn fetch_and_transform_upstream_data(
-    req: &Request,
+/// Fetches the hubs at `url` and transforms them like a request for the
+/// home screen, `url` having the `query_params` of `params`.
+pub async fn fetch_and_transform_upstream_data(
+    url: &Url,
+    content_type: ContentType,
     params: &PlexContext,
     plex_client: &PlexClient,
-) -> anyhow::Result<WrappedMediaContainer> {
-    let url = url_from_request(req);
-    let content_type = get_content_type_from_headers(req.headers());
-
+) -> Result<WrappedMediaContainer, PlexError> {
     // Fetch data from upstream.
-    let upstream_res = plex_client.get(url.as_str()).await?;
-    let status = upstream_res.status();
-    if status != reqwest::StatusCode::OK {
-        tracing::error!(status = ?status, "Failed to get plex response");
-        return Err(anyhow::anyhow!(
-            "Upstream request failed with status: {}",
-            status
-        ));
-    }
+    let upstream_res = PlexError::check(plex_client.get(url.as_str()).await?)?;
 
-    // Deserialize the upstream response.
-    let mut container = MediaContainer::from_reqwest_response(upstream_res).await?;
-
-    TransformBuilder::new(plex_client, params)
-        .with_transform(SectionDirectoryTransform)
-        .with_transform(ExcludeWatchedTransform)
-        .with_transform(SupplementHubTransform)
-        .with_transform(HubMixTransform)
-        .with_transform(ReorderHubsTransform)
-        .with_transform(HubStyleTransform { is_home: true })
-        .with_transform(HubKeyTransform)
-        .apply_to(&mut container)
-        .await
-        .unwrap_or_else(|e| {
-            tracing::error!(error = %e, "Failed to transform media container");
-        });
+    // Deserialize the upstream response. For JSON the children of hubs that
+    // no transform needs are passed on as they came.
+    let mut container = match content_type {
+        ContentType::Json => {
+            MediaContainer::from_reqwest_response_keeping_children(upstream_res).await?
+        }
+        ContentType::Xml => MediaContainer::from_reqwest_response(upstream_res).await?,
+    };
+
+    let section_ids = params
+        .pinned_content_directory_id
+        .as_ref()
+        .or(params.content_directory_id.as_ref());
+
+    HubRoute::PromotedHubs
+        .transform(&mut container, url, params, plex_client, |server| {
+            // The hubs of the libraries on the server that are merged into
+            // the pinned libraries.
+            let ids = section_ids?
+                .iter()
+                .filter_map(|id| server.section_for(id))
+                .join(",");
+            if ids.is_empty() {
+                return None;
+            }
+
+            let params_for_server = vec![
+                ("contentDirectoryID", ids.clone()),
+                ("pinnedContentDirectoryID", ids),
+            ];
+            Some((url.path().to_owned(), params_for_server))
+        })
+        .await;
 
     let result = container.wrap(content_type);
 
@@ -1,8 +1,18 @@
+use salvo::http::header::{HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use salvo::http::Method;
 use salvo::prelude::*;
 use tokio::time::{timeout, Duration};
 
 use crate::config::Config;
+use crate::http_client::PROXY_CLIENT;
+use crate::models::{ContentType, MediaContainer};
+use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
+use crate::plex::models::PlexContext;
+use crate::plex::upstream;
 use crate::proxy::Proxy;
+use crate::transforms::{TransformBuilder, UserStateTransform};
+use crate::utils::get_content_type_from_headers;
 
 #[handler]
 pub async fn handler(
@@ -10,11 +20,21 @@ pub async fn handler(
     res: &mut Response,
     depot: &mut Depot,
     ctrl: &mut FlowCtrl,
-) {
-    let config = Config::load();
-    let host = config.host.clone();
+) -> Result<(), PlexError> {
+    let config = Config::for_request(req);
+    let host = upstream::host_for(req, &config);
 
-    let proxy = Proxy::new(host);
+    // Metadata of the other servers links to more of it, and the user state
+    // may have to be hidden, so then it can't be passed on as it is.
+    if (upstream::selected_server(req).is_some()
+        || UserStateTransform::is_enabled(&config))
+        && req.method() == Method::GET
+        && is_metadata_path(req.uri().path())
+    {
+        return transformed_metadata(req, res).await;
+    }
+
+    let proxy = Proxy::with_client(host, PROXY_CLIENT.clone());
     let timeout_duration = Duration::from_secs(60 * 200);
     let proxy_result = timeout(timeout_duration, async {
         proxy.handle(req, depot, res, ctrl).await
@@ -30,4 +50,100 @@ pub async fn handler(
             res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
+
+    Ok(())
+}
+
+async fn transformed_metadata(
+    req: &mut Request,
+    res: &mut Response,
+) -> Result<(), PlexError> {
+    let params: PlexContext = req.extract().await?;
+    let plex_client = PlexClient::from_request(req, &params)?;
+    let content_type = get_content_type_from_headers(req.headers());
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_default();
+
+    let upstream_res = plex_client.get(&path_and_query).await?;
+    copy_head(&upstream_res, res);
+
+    // Anything that isn't a media container, like errors, 304s, images or
+    // the BIF files of `indexes`, is passed on as it is.
+    let Some(upstream_type) = container_type(&upstream_res) else {
+        res.stream(upstream_res.bytes_stream());
+        return Ok(());
+    };
+
+    let bytes = upstream_res.bytes().await?;
+    if bytes.is_empty() {
+        return Ok(());
+    }
+
+    let mut container = MediaContainer::from_bytes(bytes, upstream_type).await?;
+
+    // Besides this, the keys of the other servers are rewritten.
+    TransformBuilder::new(&plex_client, &params)
+        .with_transform(UserStateTransform)
+        .apply_to(&mut container)
+        .await
+        .map_err(|e| PlexError::from(&e))?;
+
+    // The body is written again, in the format the client asked for.
+    for name in [CONTENT_TYPE, CONTENT_LENGTH] {
+        res.headers_mut().remove(name);
+    }
+    res.render(container.wrap(content_type));
+    Ok(())
+}
+
+// Format of the media container in `upstream_res`, if it holds one.
+fn container_type(upstream_res: &reqwest::Response) -> Option<ContentType> {
+    if upstream_res.status() != reqwest::StatusCode::OK {
+        return None;
+    }
+
+    match upstream_res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(t) if t.contains("json") => Some(ContentType::Json),
+        Some(t) if t.contains("xml") => Some(ContentType::Xml),
+        _ => None,
+    }
+}
+
+// Copies the status and headers of `upstream_res`, like the
+// `X-Plex-Container-*` ones, except those of the connection to Plex.
+fn copy_head(upstream_res: &reqwest::Response, res: &mut Response) {
+    const HOP_BY_HOP: [&str; 3] = ["connection", "keep-alive", "transfer-encoding"];
+
+    if let Ok(status) = StatusCode::from_u16(upstream_res.status().as_u16()) {
+        res.status_code(status);
+    }
+
+    for (name, value) in upstream_res.headers() {
+        if HOP_BY_HOP.contains(&name.as_str()) {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_str().as_bytes()),
+            HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            res.headers_mut().append(name, value);
+        }
+    }
+}
+
+// Paths that return a media container, rather than an image or media.
+fn is_metadata_path(path: &str) -> bool {
+    const IMAGES: [&str; 7] = ["thumb", "art", "banner", "clearLogo", "theme", "composite", "file"];
+
+    ["/library/metadata", "/library/collections", "/library/sections", "/hubs"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+        && !path.split('/').any(|segment| IMAGES.contains(&segment))
 }
@@ -1,30 +1,23 @@
 use salvo::prelude::*;
 
-use crate::config::Config;
-use crate::models::{MediaContainer, Platform, WrappedMediaContainer};
+use crate::models::{ContentType, MediaContainer, Platform, WrappedMediaContainer};
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
-use crate::transforms::{
-    ExcludeWatchedTransform, HideInProgressTransform, HubKeyTransform,
-    ReorderHubsTransform, SectionDirectoryTransform, SupplementHubTransform,
-    TransformBuilder,
-};
+use crate::plex::servers::HubRoute;
 use crate::utils::*;
 
-use crate::transforms::HubStyleTransform;
-
 #[handler]
 pub async fn handler(
     req: &mut Request,
     res: &mut Response,
-) -> Result<(), anyhow::Error> {
-    // Extract config and parameters
-    let config = Config::load();
+) -> Result<(), PlexError> {
+    // Extract parameters
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
+    let plex_client = PlexClient::from_request(req, &params)?;
 
     // Consolidate query parameter modifications
-    adjust_query_params(req, &params, config);
+    adjust_query_params(req, &params);
 
     // Perform upstream request and handle response
     match fetch_and_transform_upstream_data(req, &params, &plex_client).await {
@@ -38,7 +31,7 @@ pub async fn handler(
     Ok(())
 }
 
-fn adjust_query_params(req: &mut Request, params: &PlexContext, _config: &Config) {
+fn adjust_query_params(req: &mut Request, params: &PlexContext) {
     let mut count = params.count.unwrap_or(25);
     if params.platform == Platform::Android {
         // Android doesn't do pagination so we to fetch more items.
@@ -54,37 +47,31 @@ async fn fetch_and_transform_upstream_data(
     req: &Request,
     params: &PlexContext,
     plex_client: &PlexClient,
-) -> anyhow::Result<WrappedMediaContainer> {
+) -> Result<WrappedMediaContainer, PlexError> {
     let url = url_from_request(req);
     let content_type = get_content_type_from_headers(req.headers());
 
     // Fetch data from upstream.
-    let upstream_res = plex_client.get(url.as_str()).await?;
-    let status = upstream_res.status();
-    if status != reqwest::StatusCode::OK {
-        tracing::error!(status = ?status, "Failed to get plex response");
-        return Err(anyhow::anyhow!(
-            "Upstream request failed with status: {}",
-            status
-        ));
-    }
+    let upstream_res = PlexError::check(plex_client.get(url.as_str()).await?)?;
 
-    // Deserialize the upstream response.
-    let mut container = MediaContainer::from_reqwest_response(upstream_res).await?;
+    // Deserialize the upstream response. For JSON the children of hubs that
+    // no transform needs are passed on as they came.
+    let mut container = match content_type {
+        ContentType::Json => {
+            MediaContainer::from_reqwest_response_keeping_children(upstream_res).await?
+        }
+        ContentType::Xml => MediaContainer::from_reqwest_response(upstream_res).await?,
+    };
 
-    TransformBuilder::new(plex_client, params)
-        .with_transform(SectionDirectoryTransform)
-        .with_transform(HideInProgressTransform)
-        .with_transform(ExcludeWatchedTransform)
-        .with_transform(SupplementHubTransform)
-        .with_transform(ReorderHubsTransform)
-        .with_transform(HubStyleTransform { is_home: false })
-        .with_transform(HubKeyTransform)
-        .apply_to(&mut container)
-        .await
-        .unwrap_or_else(|e| {
-            tracing::error!(error = %e, "Failed to transform media container");
-        });
+    // The hubs of the library on each server that is merged into library
+    // `id`.
+    let id = req.param::<String>("id");
+    HubRoute::SectionHubs
+        .transform(&mut container, &url, params, plex_client, |server| {
+            let path = format!("/hubs/sections/{}", server.section_for(id.as_deref()?)?);
+            Some((path, Vec::new()))
+        })
+        .await;
 
     let result = container.wrap(content_type);
 
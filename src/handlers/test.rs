@@ -3,10 +3,14 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     models::MediaContainer,
-    plex::{client::PlexClient, models::PlexContext, traits::Collection},
+    plex::{
+        client::PlexClient, error::PlexError, models::PlexContext,
+        traits::Collection,
+    },
     utils::get_content_type_from_headers,
 };
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 struct Test {
     test: String,
@@ -18,19 +22,19 @@ pub async fn handler(
     res: &mut Response,
     _depot: &mut Depot,
     _ctrl: &mut FlowCtrl,
-) -> Result<(), anyhow::Error> {
+) -> Result<(), PlexError> {
     dbg!("test handler");
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
+    let plex_client = PlexClient::from_request(req, &params)?;
     let content_type = get_content_type_from_headers(req.headers());
 
     let mut container = MediaContainer::default();
-    let collection = Collection::get(&plex_client, 2108706).await.unwrap();
+    let mut collection = Collection::get(&plex_client, 2108706).await?;
 
     // let encoded = bincode::serialize(&collection).unwrap();
     // let decoded: MediaContainer = bincode::deserialize(&encoded).unwrap();
 
-    container.metadata = collection.metadata;
+    *container.children_mut() = collection.take_children();
     let result = container.wrap(content_type);
     result.render(res);
 
@@ -1,6 +1,7 @@
 use crate::config::Config;
 use crate::models::{MediaContainer, TranscodingStatus};
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
 use crate::utils::{replace_query, url_from_request};
 
@@ -14,17 +15,20 @@ use salvo::prelude::*;
 pub async fn handler(
     req: &mut Request,
     _res: &mut Response,
-) -> Result<(), anyhow::Error> {
+) -> Result<(), PlexError> {
     let params: PlexContext = req.extract().await?;
-    let plex_client = PlexClient::from_request(req, &params);
-    let config = Config::load();
+    let plex_client = PlexClient::from_request(req, &params)?;
+    let config = Config::for_request(req);
     let original_queries = req.queries().clone();
 
-    let fallback_for = config
+    let Some(fallback_for) = config
         .video_transcode_fallback_for
         .as_deref()
-        .unwrap_or_default()[0]
-        .to_lowercase();
+        .and_then(|resolutions| resolutions.first())
+        .map(|resolution| resolution.to_lowercase())
+    else {
+        return Ok(());
+    };
 
     let item_key = req
         .queries()
@@ -40,7 +44,17 @@ pub async fn handler(
         .and_then(|index| index.parse().ok())
         .unwrap_or(0);
 
-    if item.metadata[0].media[media_index]
+    let Some(metadata) = item.children().first() else {
+        return Err(PlexError::NotFound("Item to play".to_string()));
+    };
+    let Some(media) = metadata.media.get(media_index) else {
+        return Err(PlexError::BadRequest(format!(
+            "Item has no media at index {}",
+            media_index
+        )));
+    };
+
+    if media
         .video_resolution
         .as_deref()
         .unwrap_or_default()
@@ -53,7 +67,7 @@ pub async fn handler(
         return Ok(());
     }
 
-    if item.metadata[0].media.len() > 1 {
+    if metadata.media.len() > 1 {
         let status = get_transcoding_for_request(req, &plex_client).await?;
 
         if status.is_transcoding {
@@ -77,13 +91,13 @@ pub async fn handler(
 async fn get_transcoding_for_request(
     req: &mut Request,
     plex_client: &PlexClient,
-) -> Result<TranscodingStatus, anyhow::Error> {
+) -> Result<TranscodingStatus, PlexError> {
     let url = url_from_request(req);
-    let response = plex_client.get(url.as_str()).await?;
+    let response = PlexError::check(plex_client.get(url.as_str()).await?)?;
     let transcode = MediaContainer::from_reqwest_response(response).await?;
-    let is_transcoding = transcode.metadata.first().map_or(false, |m| {
-        m.media.first().map_or(false, |media| {
-            media.parts.first().map_or(false, |part| {
+    let is_transcoding = transcode.children().first().is_some_and(|m| {
+        m.media.first().is_some_and(|media| {
+            media.parts.first().is_some_and(|part| {
                 part.streams.iter().any(|s| {
                     s.stream_type == Some(1)
                         && s.decision == Some("transcode".into())
@@ -103,7 +117,7 @@ async fn execute_fallback_logic(
     _item: &MediaContainer,
     _media_index: usize,
     _fallback_for: &str,
-) -> Result<bool, anyhow::Error> {
+) -> Result<bool, PlexError> {
     // This function should implement the logic to select and apply a fallback version based on certain criteria.
     // For the purposes of this example, it will simply return Ok(false) to indicate that no fallback was selected.
     // Implement the actual fallback logic here.
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use http::Method;
+use once_cell::sync::Lazy;
+use reqwest::{Request, Response};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use reqwest_retry::policies::ExponentialBackoff;
+use reqwest_retry::RetryTransientMiddleware;
+use task_local_extensions::Extensions;
+
+use crate::config::{Config, Http};
+
+// Client for the requests to Plex that Replex reads the response of. It is
+// shared by all requests so connections to Plex are kept alive and reused,
+// headers of the requesting client are set per request.
+static HTTP_CLIENT: Lazy<ArcSwap<ClientWithMiddleware>> = Lazy::new(|| {
+    let config = Config::load();
+    ArcSwap::from_pointee(build(&config.http))
+});
+
+/// Returns the client for the requests to Plex that Replex reads the
+/// response of, built from the current `http` settings.
+pub fn client() -> ClientWithMiddleware {
+    HTTP_CLIENT.load().as_ref().clone()
+}
+
+/// Rebuilds the client from `config`, for the requests that start from now
+/// on. Requests in flight finish with the previous client.
+pub fn rebuild(config: &Http) {
+    HTTP_CLIENT.store(Arc::new(build(config)));
+    tracing::info!("HTTP client rebuilt with the new `http` settings");
+}
+
+/// Client for proxied requests. Responses are passed on untouched, so unlike
+/// `client` it doesn't decompress them, and it has no timeout since it
+/// also carries streams.
+pub static PROXY_CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+fn build(config: &Http) -> ClientWithMiddleware {
+    let client = reqwest::Client::builder()
+        .gzip(true)
+        .timeout(Duration::from_secs(config.timeout))
+        .build()
+        .expect("Failed to build HTTP client");
+
+    // Full jitter is the default, so concurrent retries are spread out.
+    let policy = ExponentialBackoff::builder()
+        .retry_bounds(
+            Duration::from_millis(config.retry_min_delay),
+            Duration::from_millis(config.retry_max_delay),
+        )
+        .build_with_max_retries(config.retries);
+
+    ClientBuilder::new(client)
+        .with(RetryIdempotent(RetryTransientMiddleware::new_with_policy(policy)))
+        .build()
+}
+
+// Retries only requests that are safe to send twice.
+struct RetryIdempotent(RetryTransientMiddleware<ExponentialBackoff>);
+
+#[async_trait]
+impl Middleware for RetryIdempotent {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        if matches!(*req.method(), Method::GET | Method::HEAD) {
+            self.0.handle(req, extensions, next).await
+        } else {
+            next.run(req, extensions).await
+        }
+    }
+}
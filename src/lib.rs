@@ -2,11 +2,14 @@ pub mod cache;
 pub mod config;
 pub mod deserializers;
 pub mod handlers;
+pub mod http_client;
 pub mod middlewares;
 pub mod models;
 pub mod plex;
+pub mod plugins;
 pub mod router;
 pub mod routes;
+pub mod scripting;
 // pub mod serde_utils;
 // pub mod transform;
 pub mod proxy;
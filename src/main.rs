@@ -1,35 +1,70 @@
-use replex::config::Config;
+use replex::config::{self, check_host, config_path, Config};
 use salvo::prelude::*;
 use std::env;
+use std::process::ExitCode;
 use std::time::Duration;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 use replex::cache::CACHE_MANAGER;
+use replex::plex::{notifications, upstream};
+use replex::plugins;
 use replex::router::main_router;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     dotenv::dotenv().ok();
 
+    let args: Vec<String> = env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "check-config") {
+        let path = args
+            .get(index + 1)
+            .filter(|arg| !arg.starts_with("--"))
+            .cloned()
+            .unwrap_or_else(config_path);
+        return check_config(&path).await;
+    }
+
     let version = env!("CARGO_PKG_VERSION");
-    let config = Config::load();
+    let (config, warnings) = match Config::try_load() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
     let host = env::var("REPLEX_HOST").unwrap_or_else(|_| config.host.clone());
 
-    if host.is_empty() {
-        panic!("Host is not set!");
-    }
-
-    let _init_cache = CACHE_MANAGER.clone();
+    // RUST_LOG takes precedence over the level in the config file
+    let env_filter = match (env::var("RUST_LOG"), &config.rust_log) {
+        (Err(_), Some(level)) => EnvFilter::new(level),
+        _ => EnvFilter::from_default_env(),
+    };
 
     tracing::subscriber::set_global_default(
-        FmtSubscriber::builder()
-            .with_env_filter(EnvFilter::from_default_env())
-            .finish(),
+        FmtSubscriber::builder().with_env_filter(env_filter).finish(),
     )
     .expect("setting default subscriber failed");
 
     tracing::info!("Replex version {}", version);
     tracing::info!("Host: {}", host);
+    for warning in &warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    config::watch();
+
+    let _init_cache = CACHE_MANAGER.clone();
+    plugins::refresh(&config);
+    notifications::listen();
+    upstream::watch();
+
+    // Plex may take a while to answer, or start after Replex does, so the
+    // listener doesn't wait for the check.
+    tokio::spawn(async move {
+        if let Some(issue) = check_host(&host).await {
+            tracing::warn!("{}", issue);
+        }
+    });
 
     Server::new(
         TcpListener::new(format!("0.0.0.0:{}", config.port.unwrap_or(80)))
@@ -39,4 +74,33 @@ async fn main() {
     .conn_idle_timeout(Duration::from_secs(60 * 101))
     .serve(main_router())
     .await;
+
+    ExitCode::SUCCESS
+}
+
+/// Validates the config file at `path` and prints the resolved config, with
+/// its secrets masked.
+async fn check_config(path: &str) -> ExitCode {
+    let (config, mut report) = Config::check(path);
+
+    if let Some(config) = &config {
+        if let Some(issue) = check_host(&config.host).await {
+            report.issues.push(issue);
+        }
+
+        match config.to_redacted_yaml() {
+            Ok(yaml) => println!("{}", yaml),
+            Err(e) => eprintln!("Failed to print config: {}", e),
+        }
+    }
+
+    eprint!("{}", report);
+
+    if config.is_none() || report.has_errors() {
+        eprintln!("{} is invalid", path);
+        ExitCode::FAILURE
+    } else {
+        eprintln!("{} is valid", path);
+        ExitCode::SUCCESS
+    }
 }
@@ -0,0 +1,52 @@
+use salvo::http::{Request, Response, StatusCode};
+use salvo::{async_trait, Depot, FlowCtrl, Handler};
+
+use crate::config::Config;
+
+/// Only lets requests through that carry `admin_token` as a bearer token.
+/// Responds with 404 when no `admin_token` is configured, so the admin
+/// endpoints don't exist unless they are turned on.
+pub struct AdminAuth;
+
+#[async_trait]
+impl Handler for AdminAuth {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let config = Config::for_request(req);
+        let Some(admin_token) = config.admin_token.as_deref() else {
+            res.status_code(StatusCode::NOT_FOUND);
+            ctrl.skip_rest();
+            return;
+        };
+
+        let token = req
+            .header::<String>("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer ").map(str::to_owned));
+
+        match token {
+            Some(token) if tokens_match(&token, admin_token) => {
+                ctrl.call_next(req, depot, res).await;
+            }
+            _ => {
+                tracing::warn!(path = %req.uri().path(), "Unauthorized admin request");
+                res.status_code(StatusCode::UNAUTHORIZED);
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
+
+// Compares every byte, so the time taken doesn't reveal how much of the
+// token was right.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,33 @@
+use salvo::http::{Request, Response};
+use salvo::{async_trait, Depot, FlowCtrl, Handler};
+
+use crate::config::Config;
+
+/// Runs the wrapped handler only while `enabled` returns true for the config
+/// of the requesting client. The check happens per request, so routers built
+/// once at startup still follow config reloads and profiles.
+pub struct ConfigGate<H> {
+    enabled: fn(&Config) -> bool,
+    handler: H,
+}
+
+impl<H: Handler> ConfigGate<H> {
+    pub fn new(enabled: fn(&Config) -> bool, handler: H) -> Self {
+        ConfigGate { enabled, handler }
+    }
+}
+
+#[async_trait]
+impl<H: Handler> Handler for ConfigGate<H> {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        if (self.enabled)(&Config::for_request(req)) {
+            self.handler.handle(req, depot, res, ctrl).await;
+        }
+    }
+}
@@ -1,7 +1,13 @@
+mod admin_auth;
+mod config_gate;
 mod disable_related_query;
 mod logger;
+mod select_server;
 mod timeout;
 
+pub use admin_auth::AdminAuth;
+pub use config_gate::ConfigGate;
 pub use disable_related_query::DisableRelatedQuery;
 pub use logger::Logger;
+pub use select_server::SelectServer;
 pub use timeout::Timeout;
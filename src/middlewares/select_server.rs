@@ -0,0 +1,36 @@
+use salvo::http::{Request, Response, StatusCode};
+use salvo::{async_trait, Depot, FlowCtrl, Handler};
+
+use crate::config::Config;
+use crate::plex::upstream;
+
+/// Sends requests to the server in `servers` that is named by the `server`
+/// path parameter. Responds with 404 for servers that aren't configured.
+pub struct SelectServer;
+
+#[async_trait]
+impl Handler for SelectServer {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let config = Config::for_request(req);
+        let server = req
+            .param::<String>("server")
+            .and_then(|name| config.server(&name));
+
+        match server {
+            Some(server) => {
+                upstream::select(req, server);
+                ctrl.call_next(req, depot, res).await;
+            }
+            None => {
+                res.status_code(StatusCode::NOT_FOUND);
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
@@ -1,15 +1,23 @@
+mod children;
 mod enums;
+mod extra_fields;
 mod generic;
+mod hub_rule_matches;
 mod media;
 mod media_container;
 mod metadata;
+mod raw_children;
 mod special_bool;
 mod stream;
 
+pub use children::*;
 pub use enums::*;
+pub use extra_fields::*;
 pub use generic::*;
+pub use hub_rule_matches::*;
 pub use media::*;
 pub use media_container::*;
 pub use metadata::*;
+pub use raw_children::*;
 pub use special_bool::*;
 pub use stream::*;
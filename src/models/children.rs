@@ -0,0 +1,236 @@
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use serde::de::MapAccess;
+use serde::{Serialize, Serializer};
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+use xml::attribute::OwnedAttribute;
+use xml::namespace::Namespace;
+use xml::reader::XmlEvent;
+
+use yaserde::de::Deserializer as YaDeserializer;
+use yaserde::ser::Serializer as YaSerializer;
+use yaserde::{YaDeserialize, YaSerialize};
+
+use crate::models::{ChildKind, Intercept, MetaData};
+
+/// Children of a media container or item, like the hubs of a container or the items of a hub,
+/// in the order they came. Each child keeps the element it is in, see `MetaData::element`, so a
+/// hub that mixes `Video` and `Directory` items is written out again the way it was.
+///
+/// In JSON the children are lists by the name of their element. In XML every child is an
+/// element of its own, named by its kind. If yaserde matched the lists by name, it would take
+/// the element of an item for one of its own lists, so they are flattened into the parent
+/// instead and read from the elements yaserde leaves over.
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
+pub struct Children(Vec<MetaData>);
+
+impl Children {
+    pub fn new(children: Vec<MetaData>) -> Self {
+        Self(children)
+    }
+}
+
+impl From<Vec<MetaData>> for Children {
+    fn from(children: Vec<MetaData>) -> Self {
+        Self::new(children)
+    }
+}
+
+impl Deref for Children {
+    type Target = Vec<MetaData>;
+
+    fn deref(&self) -> &Vec<MetaData> {
+        &self.0
+    }
+}
+
+impl DerefMut for Children {
+    fn deref_mut(&mut self) -> &mut Vec<MetaData> {
+        &mut self.0
+    }
+}
+
+impl IntoIterator for Children {
+    type Item = MetaData;
+    type IntoIter = std::vec::IntoIter<MetaData>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Serialize for Children {
+    /// Serializes the children as lists by the name of their element, in the order the names
+    /// first appear. Flattened into the parent, they end up where they came from.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut lists: Vec<(&'static str, Vec<&MetaData>)> = Vec::new();
+        for child in &self.0 {
+            let name = child.element.name();
+            match lists.iter_mut().find(|(list, _)| *list == name) {
+                Some((_, list)) => list.push(child),
+                None => lists.push((name, vec![child])),
+            }
+        }
+
+        serializer.collect_map(lists)
+    }
+}
+
+/// Takes the lists of children of a model as serde reads it, tagging every child with the key
+/// of its list. See `impl_extra_fields!`.
+#[derive(Default)]
+pub(crate) struct TakeChildren(pub(crate) Children);
+
+impl<'de> Intercept<'de> for TakeChildren {
+    fn intercept<A: MapAccess<'de>>(&mut self, key: &str, map: &mut A) -> Result<bool, A::Error> {
+        let Some(kind) = ChildKind::from_name(key) else {
+            return Ok(false);
+        };
+
+        let children: Option<Vec<MetaData>> = map.next_value()?;
+        for mut child in children.unwrap_or_default() {
+            child.element = kind.clone();
+            self.0.push(child);
+        }
+        Ok(true)
+    }
+}
+
+impl Encode for Children {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.0.encode(encoder)
+    }
+}
+
+impl Decode for Children {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Vec::decode(decoder).map(Self::new)
+    }
+}
+
+impl<'de> BorrowDecode<'de> for Children {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Self::decode(decoder)
+    }
+}
+
+impl YaSerialize for Children {
+    fn serialize<W: Write>(&self, writer: &mut YaSerializer<W>) -> Result<(), String> {
+        for child in &self.0 {
+            writer.set_start_event_name(Some(child.element.name().to_string()));
+            writer.set_skip_start_end(false);
+            YaSerialize::serialize(child, writer)?;
+        }
+        Ok(())
+    }
+
+    fn serialize_attributes(
+        &self,
+        attributes: Vec<OwnedAttribute>,
+        namespace: Namespace,
+    ) -> Result<(Vec<OwnedAttribute>, Namespace), String> {
+        Ok((attributes, namespace))
+    }
+}
+
+impl YaDeserialize for Children {
+    /// Reads the child elements of the element of the parent that are named like a
+    /// `ChildKind`, in order, skipping any other.
+    fn deserialize<R: Read>(reader: &mut YaDeserializer<R>) -> Result<Self, String> {
+        let mut children = vec![];
+
+        if !matches!(reader.next_event()?, XmlEvent::StartElement { .. }) {
+            return Ok(Self::new(children));
+        }
+
+        loop {
+            match reader.peek()? {
+                XmlEvent::StartElement { name, .. } => {
+                    let Some(kind) = ChildKind::from_name(&name.local_name) else {
+                        reader.next_event()?;
+                        reader.skip_element(|_| {})?;
+                        continue;
+                    };
+
+                    let mut child = <MetaData as YaDeserialize>::deserialize(reader)?;
+                    child.element = kind;
+                    children.push(child);
+                    // The end of the child, which it leaves to its parent.
+                    reader.next_event()?;
+                }
+                XmlEvent::EndElement { .. } | XmlEvent::EndDocument => break,
+                _ => {
+                    reader.next_event()?;
+                }
+            }
+        }
+
+        Ok(Self::new(children))
+    }
+}
+
+/// Default of `Children` for yaserde, which wants the name of a function.
+pub(crate) fn default_children() -> Children {
+    Children::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::models::{ContentType, MediaContainer};
+
+    use super::*;
+
+    const MIXED_HUB: &str = r#"<MediaContainer size="1"><Hub hubIdentifier="custom.collection.6.1" title="Mixed" size="3"><Video ratingKey="1" title="One"/><Directory ratingKey="2" title="Two"/><Video ratingKey="3" title="Three"/></Hub></MediaContainer>"#;
+
+    fn titles(children: &[MetaData]) -> Vec<(&str, &str)> {
+        children
+            .iter()
+            .map(|child| (child.element.name(), child.title.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn xml_keeps_every_child_of_a_mixed_hub_in_order() {
+        let container = MediaContainer::from_bytes(Bytes::from(MIXED_HUB), ContentType::Xml)
+            .await
+            .unwrap();
+
+        assert!(container.is_hub());
+        assert_eq!(
+            titles(container.children()[0].children()),
+            [("Video", "One"), ("Directory", "Two"), ("Video", "Three")],
+        );
+
+        let xml = yaserde::ser::to_string(&container).unwrap();
+        let one = xml.find(r#"<Video ratingKey="1""#).unwrap();
+        let two = xml.find(r#"<Directory ratingKey="2""#).unwrap();
+        let three = xml.find(r#"<Video ratingKey="3""#).unwrap();
+        assert!(one < two && two < three);
+    }
+
+    #[tokio::test]
+    async fn json_writes_children_by_the_name_of_their_element() {
+        let container = MediaContainer::from_bytes(Bytes::from(MIXED_HUB), ContentType::Xml)
+            .await
+            .unwrap();
+
+        let json = serde_json::to_string(&container.children()[0]).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["Video"].as_array().unwrap().len(), 2);
+        assert_eq!(value["Directory"][0]["title"], "Two");
+
+        let hub: MetaData = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            titles(hub.children()),
+            [("Video", "One"), ("Video", "Three"), ("Directory", "Two")],
+        );
+    }
+}
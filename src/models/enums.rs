@@ -12,6 +12,24 @@ pub enum ContentType {
     Xml,
 }
 
+impl ContentType {
+    /// Returns the format of a response body, going by its `Content-Type`
+    /// header. Bodies without a known type are recognized by their first
+    /// character.
+    pub fn of_body(content_type: Option<&str>, body: &[u8]) -> Self {
+        match content_type {
+            Some(t) if t.contains("json") => return ContentType::Json,
+            Some(t) if t.contains("xml") => return ContentType::Xml,
+            _ => {}
+        }
+
+        match body.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'<') => ContentType::Xml,
+            _ => ContentType::Json,
+        }
+    }
+}
+
 #[enum_derives]
 pub enum Platform {
     Android,
@@ -60,3 +78,38 @@ impl DeviceType {
         }
     }
 }
+
+/// The element a child of a media container or item is in, which is also
+/// the key of its list in JSON.
+#[enum_derives]
+pub enum ChildKind {
+    #[default]
+    Metadata,
+    Hub,
+    Video,
+    Directory,
+}
+
+impl ChildKind {
+    /// Names of the elements of children.
+    pub const NAMES: [&'static str; 4] = ["Metadata", "Hub", "Video", "Directory"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChildKind::Metadata => "Metadata",
+            ChildKind::Hub => "Hub",
+            ChildKind::Video => "Video",
+            ChildKind::Directory => "Directory",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Metadata" => Some(ChildKind::Metadata),
+            "Hub" => Some(ChildKind::Hub),
+            "Video" => Some(ChildKind::Video),
+            "Directory" => Some(ChildKind::Directory),
+            _ => None,
+        }
+    }
+}
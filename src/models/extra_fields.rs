@@ -0,0 +1,531 @@
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use serde::de::{
+    DeserializeSeed, Deserializer, Error as _, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
+use xml::namespace::Namespace;
+use xml::reader::XmlEvent as ReaderEvent;
+use xml::writer::XmlEvent;
+
+use yaserde::de::Deserializer as YaDeserializer;
+use yaserde::ser::Serializer as YaSerializer;
+use yaserde::{YaDeserialize, YaSerialize};
+
+/// Fields of a Plex response that model `T` has no field for, so they are passed on as they
+/// came. Serialized flattened into the model.
+///
+/// In XML, values become attributes and lists of objects become child elements, the way Plex
+/// writes them. Read from XML, every value is a string.
+pub struct ExtraFields<T> {
+    pub fields: Map<String, Value>,
+    model: PhantomData<fn() -> T>,
+}
+
+/// Models that keep the fields they have no field for in an `ExtraFields`, see
+/// `impl_extra_fields!`.
+pub trait Model {
+    /// Names of the fields of the model, as serde reads them.
+    fn field_names() -> &'static [&'static str];
+}
+
+impl<T> ExtraFields<T> {
+    pub fn new(fields: Map<String, Value>) -> Self {
+        Self {
+            fields,
+            model: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for ExtraFields<T> {
+    fn default() -> Self {
+        Self::new(Map::new())
+    }
+}
+
+impl<T> Clone for ExtraFields<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.fields.clone())
+    }
+}
+
+impl<T> fmt::Debug for ExtraFields<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ExtraFields").field(&self.fields).finish()
+    }
+}
+
+impl<T> PartialEq for ExtraFields<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.fields == other.fields
+    }
+}
+
+impl<T> PartialOrd for ExtraFields<T> {
+    /// Unknown fields have no order, they are only equal or not.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
+
+impl<T> Serialize for ExtraFields<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.fields.serialize(serializer)
+    }
+}
+
+impl<T> Encode for ExtraFields<T> {
+    /// Encodes the fields as a JSON string, as bincode can't encode a `Value` by itself.
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        let json = serde_json::to_string(&self.fields)
+            .map_err(|e| EncodeError::OtherString(e.to_string()))?;
+        json.encode(encoder)
+    }
+}
+
+impl<T> Decode for ExtraFields<T> {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let json = String::decode(decoder)?;
+        serde_json::from_str(&json)
+            .map(Self::new)
+            .map_err(|e| DecodeError::OtherString(e.to_string()))
+    }
+}
+
+impl<'de, T> BorrowDecode<'de> for ExtraFields<T> {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Self::decode(decoder)
+    }
+}
+
+impl<T> YaSerialize for ExtraFields<T> {
+    /// Writes the lists and objects as child elements. Attributes come from `serialize_attributes`.
+    fn serialize<W: Write>(&self, writer: &mut YaSerializer<W>) -> Result<(), String> {
+        for (name, value) in &self.fields {
+            write_elements(writer, name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the plain values as attributes of the element of the model.
+    fn serialize_attributes(
+        &self,
+        mut attributes: Vec<OwnedAttribute>,
+        namespace: Namespace,
+    ) -> Result<(Vec<OwnedAttribute>, Namespace), String> {
+        attributes.extend(
+            self.fields
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some(OwnedAttribute::new(OwnedName::local(name), attribute_value(value)?))
+                }),
+        );
+        Ok((attributes, namespace))
+    }
+}
+
+impl<T: Model> YaDeserialize for ExtraFields<T> {
+    /// Reads what yaserde leaves of the element of the model: its attributes, of which the ones
+    /// that aren't fields of `T` are kept, and the child elements that `T` has no field for.
+    fn deserialize<R: Read>(reader: &mut YaDeserializer<R>) -> Result<Self, String> {
+        let mut fields = Map::new();
+
+        if let ReaderEvent::StartElement { attributes, .. } = reader.next_event()? {
+            let known = T::field_names();
+            for attribute in attributes {
+                if !known.contains(&attribute.name.local_name.as_str()) {
+                    fields.insert(attribute.name.local_name, Value::String(attribute.value));
+                }
+            }
+            read_children(reader, known, &mut fields)?;
+        }
+
+        Ok(Self::new(fields))
+    }
+}
+
+// Reads the child elements up to the end of the current element into `fields`, as lists of
+// objects by their name. Elements called like one of `known` are skipped, like the items that
+// `Items` reads.
+fn read_children<R: Read>(
+    reader: &mut YaDeserializer<R>,
+    known: &[&str],
+    fields: &mut Map<String, Value>,
+) -> Result<(), String> {
+    loop {
+        match reader.next_event()? {
+            ReaderEvent::StartElement { name, .. } if known.contains(&name.local_name.as_str()) => {
+                reader.skip_element(|_| {})?;
+            }
+            ReaderEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let mut child: Map<String, Value> = attributes
+                    .into_iter()
+                    .map(|attribute| (attribute.name.local_name, Value::String(attribute.value)))
+                    .collect();
+                read_children(reader, &[], &mut child)?;
+
+                // An attribute with the same name wins, like it does in JSON.
+                if let Value::Array(items) = fields
+                    .entry(name.local_name)
+                    .or_insert_with(|| Value::Array(vec![]))
+                {
+                    items.push(Value::Object(child));
+                }
+            }
+            ReaderEvent::EndElement { .. } | ReaderEvent::EndDocument => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+// Plex writes booleans as "1" and "0" in XML.
+fn attribute_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(if *b { "1" } else { "0" }.to_string()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+fn write_elements<W: Write>(
+    writer: &mut YaSerializer<W>,
+    name: &str,
+    value: &Value,
+) -> Result<(), String> {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                write_elements(writer, name, item)?;
+            }
+            Ok(())
+        }
+        Value::Object(fields) => {
+            let attributes: Vec<(&String, String)> = fields
+                .iter()
+                .filter_map(|(name, value)| Some((name, attribute_value(value)?)))
+                .collect();
+
+            let mut start = XmlEvent::start_element(name);
+            for (name, value) in &attributes {
+                start = start.attr(name.as_str(), value);
+            }
+            writer.write(start).map_err(|e| e.to_string())?;
+
+            for (name, value) in fields {
+                write_elements(writer, name, value)?;
+            }
+
+            writer
+                .write(XmlEvent::end_element())
+                .map_err(|e| e.to_string())
+        }
+        // Plain values are attributes of the parent.
+        _ => Ok(()),
+    }
+}
+
+/// Default of `ExtraFields` for yaserde, which wants the name of a function.
+pub(crate) fn default_extra_fields<T>() -> ExtraFields<T> {
+    ExtraFields::default()
+}
+
+/// Takes fields of a model before serde gets to them, see `Capture`.
+pub(crate) trait Intercept<'de> {
+    /// Reads the value of `key` from `map` and returns true when it takes the field.
+    fn intercept<A: MapAccess<'de>>(&mut self, key: &str, map: &mut A) -> Result<bool, A::Error>;
+}
+
+impl<'de> Intercept<'de> for () {
+    fn intercept<A: MapAccess<'de>>(&mut self, _key: &str, _map: &mut A) -> Result<bool, A::Error> {
+        Ok(false)
+    }
+}
+
+/// Deserializer for the `deserialize` function that `#[serde(remote = "Self")]` derives for a
+/// model. It collects the fields that aren't fields of the model into `extra`, and gives the
+/// ones `intercept` wants to it. Unlike `#[serde(flatten)]` the model is read straight from the
+/// input instead of being buffered first.
+pub(crate) struct Capture<'a, D, I> {
+    deserializer: D,
+    extra: &'a mut Map<String, Value>,
+    intercept: &'a mut I,
+}
+
+impl<'a, D, I> Capture<'a, D, I> {
+    pub(crate) fn new(deserializer: D, extra: &'a mut Map<String, Value>, intercept: &'a mut I) -> Self {
+        Self {
+            deserializer,
+            extra,
+            intercept,
+        }
+    }
+}
+
+impl<'de, 'a, D, I> Deserializer<'de> for Capture<'a, D, I>
+where
+    D: Deserializer<'de>,
+    I: Intercept<'de>,
+{
+    type Error = D::Error;
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserializer.deserialize_map(CaptureVisitor {
+            visitor,
+            fields,
+            extra: self.extra,
+            intercept: self.intercept,
+        })
+    }
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserializer.deserialize_any(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+struct CaptureVisitor<'a, V, I> {
+    visitor: V,
+    fields: &'static [&'static str],
+    extra: &'a mut Map<String, Value>,
+    intercept: &'a mut I,
+}
+
+impl<'de, 'a, V, I> Visitor<'de> for CaptureVisitor<'a, V, I>
+where
+    V: Visitor<'de>,
+    I: Intercept<'de>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.visitor.visit_map(CaptureMap {
+            map,
+            fields: self.fields,
+            extra: self.extra,
+            intercept: self.intercept,
+        })
+    }
+}
+
+struct CaptureMap<'a, A, I> {
+    map: A,
+    fields: &'static [&'static str],
+    extra: &'a mut Map<String, Value>,
+    intercept: &'a mut I,
+}
+
+impl<'de, 'a, A, I> MapAccess<'de> for CaptureMap<'a, A, I>
+where
+    A: MapAccess<'de>,
+    I: Intercept<'de>,
+{
+    type Error = A::Error;
+
+    // Only the fields of the model reach its visitor.
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        while let Some(Key(key)) = self.map.next_key()? {
+            if self.intercept.intercept(&key, &mut self.map)? {
+                continue;
+            }
+
+            if self.fields.contains(&key.as_ref()) {
+                return seed.deserialize(key.as_ref().into_deserializer()).map(Some);
+            }
+
+            let value: Value = self.map.next_value()?;
+            self.extra.insert(key.into_owned(), value);
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.map.next_value_seed(seed)
+    }
+}
+
+// A key of an object, borrowed from the input when it can be.
+struct Key<'de>(Cow<'de, str>);
+
+impl<'de> Deserialize<'de> for Key<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = Key<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a field name")
+            }
+
+            fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E> {
+                Ok(Key(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Key(Cow::Owned(v.to_owned())))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+                Ok(Key(Cow::Owned(v)))
+            }
+        }
+
+        deserializer.deserialize_str(KeyVisitor)
+    }
+}
+
+/// Deserializer that only reads the names of the fields of a model, which serde passes to
+/// `deserialize_struct`. See `Model::field_names`.
+pub(crate) struct FieldNames<'a>(pub(crate) &'a mut &'static [&'static str]);
+
+impl<'de, 'a> Deserializer<'de> for FieldNames<'a> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        *self.0 = fields;
+        Err(Self::Error::custom("only the field names are read"))
+    }
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Self::Error::custom("only structs have field names"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Implements `Deserialize`, `Serialize` and `Model` for a model with an `extra: ExtraFields`
+/// field and `#[serde(remote = "Self")]`, which turns the derived implementations into the
+/// functions these call.
+///
+/// Models with a `children: Children` field pass `children`, which reads the lists named like a
+/// `ChildKind` into it.
+macro_rules! impl_extra_fields {
+    ($model:ident) => {
+        impl<'de> serde::Deserialize<'de> for $model {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let mut extra = serde_json::Map::new();
+                let mut model =
+                    $model::deserialize($crate::models::Capture::new(deserializer, &mut extra, &mut ()))?;
+                model.extra = $crate::models::ExtraFields::new(extra);
+                Ok(model)
+            }
+        }
+
+        $crate::models::impl_extra_fields!(@model $model, &[]);
+    };
+    ($model:ident, children) => {
+        impl<'de> serde::Deserialize<'de> for $model {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let mut extra = serde_json::Map::new();
+                let mut children = $crate::models::TakeChildren::default();
+                let mut model = $model::deserialize($crate::models::Capture::new(
+                    deserializer,
+                    &mut extra,
+                    &mut children,
+                ))?;
+                model.extra = $crate::models::ExtraFields::new(extra);
+                model.children = children.0;
+                Ok(model)
+            }
+        }
+
+        $crate::models::impl_extra_fields!(@model $model, &$crate::models::ChildKind::NAMES);
+    };
+    (@model $model:ident, $children:expr) => {
+        impl serde::Serialize for $model {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                $model::serialize(self, serializer)
+            }
+        }
+
+        impl $crate::models::Model for $model {
+            fn field_names() -> &'static [&'static str] {
+                static NAMES: once_cell::sync::Lazy<&'static [&'static str]> =
+                    once_cell::sync::Lazy::new(|| {
+                        let mut names: &'static [&'static str] = &[];
+                        let _ = $model::deserialize($crate::models::FieldNames(&mut names));
+                        // The children are read by `Children`, not serde.
+                        let children: &[&'static str] = $children;
+                        Box::leak([names, children].concat().into_boxed_slice())
+                    });
+                *NAMES
+            }
+        }
+    };
+}
+
+pub(crate) use impl_extra_fields;
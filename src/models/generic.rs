@@ -59,11 +59,11 @@ pub struct Image{
     pub alt: Option<String>,
 
     #[serde(default, rename = "type")]
-    #[yaserde(attribute = true, rename = "type")]
+    #[yaserde(attribute = true, rename = "type", default = "default_string")]
     pub r#type: String,
 
     #[serde(default)]
-    #[yaserde(attribute = true)]
+    #[yaserde(attribute = true, default = "default_string")]
     pub url: String,
 }
 
@@ -83,6 +83,10 @@ fn default_image() -> Vec<Image> {
     vec![Image::default()]
 }
 
+fn default_string() -> String {
+    String::new()
+}
+
 #[struct_derives()]
 #[serde(rename_all = "camelCase")]
 pub struct Context {
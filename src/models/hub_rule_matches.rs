@@ -0,0 +1,85 @@
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use once_cell::sync::OnceCell;
+use std::cmp::Ordering;
+use std::io::{Read, Write};
+use xml::attribute::OwnedAttribute;
+use xml::namespace::Namespace;
+
+use yaserde::de::Deserializer as YaDeserializer;
+use yaserde::ser::Serializer as YaSerializer;
+use yaserde::{YaDeserialize, YaSerialize};
+
+/// The `hub_rules` that match a hub, by their index in the config of the request, once they
+/// were looked up. Matching a rule on a collection label asks Plex for the collection, so the
+/// transforms that check the rules of a hub share the result. See `MetaData::hub_rules`.
+///
+/// The indices only hold for the config they were found with, so they are never written out,
+/// not even to the cache.
+#[derive(Debug, Clone, Default)]
+pub struct HubRuleMatches(OnceCell<Vec<usize>>);
+
+impl HubRuleMatches {
+    pub fn get(&self) -> Option<&[usize]> {
+        self.0.get().map(Vec::as_slice)
+    }
+
+    /// Keeps `indices`, unless another transform got there first with the same result.
+    pub fn set(&self, indices: Vec<usize>) {
+        let _ = self.0.set(indices);
+    }
+}
+
+impl PartialEq for HubRuleMatches {
+    /// Matches are a lookup, not part of the hub.
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl PartialOrd for HubRuleMatches {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        Some(Ordering::Equal)
+    }
+}
+
+impl Encode for HubRuleMatches {
+    fn encode<E: Encoder>(&self, _encoder: &mut E) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl Decode for HubRuleMatches {
+    fn decode<D: Decoder>(_decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(HubRuleMatches::default())
+    }
+}
+
+bincode::impl_borrow_decode!(HubRuleMatches);
+
+impl YaSerialize for HubRuleMatches {
+    fn serialize<W: Write>(&self, _writer: &mut YaSerializer<W>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn serialize_attributes(
+        &self,
+        attributes: Vec<OwnedAttribute>,
+        namespace: Namespace,
+    ) -> Result<(Vec<OwnedAttribute>, Namespace), String> {
+        Ok((attributes, namespace))
+    }
+}
+
+impl YaDeserialize for HubRuleMatches {
+    fn deserialize<R: Read>(_reader: &mut YaDeserializer<R>) -> Result<Self, String> {
+        Ok(HubRuleMatches::default())
+    }
+}
+
+/// Default of `HubRuleMatches` for yaserde, which wants the name of a function.
+pub(crate) fn default_hub_rule_matches() -> HubRuleMatches {
+    HubRuleMatches::default()
+}
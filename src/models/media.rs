@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::models::{SpecialBool, Stream};
+use crate::models::{default_extra_fields, impl_extra_fields, ExtraFields, SpecialBool, Stream};
 use serde_aux::prelude::deserialize_string_from_number;
 
 use replex_common::{struct_derives, struct_imports};
@@ -8,7 +8,7 @@ use replex_common::{struct_derives, struct_imports};
 struct_imports!();
 
 #[struct_derives()]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", remote = "Self")]
 pub struct Media {
     #[yaserde(attribute = true)]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -67,8 +67,13 @@ pub struct Media {
     #[yaserde(rename = "Part")]
     #[serde(skip_serializing_if = "Vec::is_empty", default, rename = "Part")]
     pub parts: Vec<MediaPart>,
+    #[yaserde(flatten = true, default = "default_extra_fields")]
+    #[serde(flatten, skip_deserializing)]
+    pub extra: ExtraFields<Media>,
 }
 
+impl_extra_fields!(Media);
+
 impl fmt::Display for Media {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -82,7 +87,7 @@ impl fmt::Display for Media {
 }
 
 #[struct_derives()]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", remote = "Self")]
 pub struct MediaPart {
     #[yaserde(attribute = true)]
     #[serde(deserialize_with = "deserialize_string_from_number")]
@@ -117,4 +122,9 @@ pub struct MediaPart {
     #[yaserde(rename = "Stream")]
     #[serde(skip_serializing_if = "Vec::is_empty", default, rename = "Stream")]
     pub streams: Vec<Stream>,
+    #[yaserde(flatten = true, default = "default_extra_fields")]
+    #[serde(flatten, skip_deserializing)]
+    pub extra: ExtraFields<MediaPart>,
 }
+
+impl_extra_fields!(MediaPart);
@@ -1,5 +1,6 @@
 type HyperResponse = hyper::Response<ResBody>;
 
+use std::collections::HashMap;
 use std::str;
 
 use async_trait::async_trait;
@@ -11,15 +12,23 @@ use salvo::{
         ResBody, Response, StatusError,
     },
     writing::Json,
-    Error, Scribe,
+    Scribe,
 };
+use serde::de::MapAccess;
 use serde::Deserializer;
+use serde_json::value::RawValue;
+use serde_json::Map;
 use serde_with::serde_as;
+use xml::reader::{EventReader, XmlEvent};
 use yaserde::{ser::to_string as to_xml_str, YaSerialize};
 
 use crate::config::Config;
-use crate::models::{ContentType, Meta, MetaData, SpecialBool};
+use crate::models::{
+    default_children, default_extra_fields, impl_extra_fields, Capture, ChildKind, Children,
+    ContentType, ExtraFields, Intercept, Meta, MetaData, RawChildren, SpecialBool, TakeChildren,
+};
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::utils::sort_by_last_viewed;
 
 use replex_common::{struct_derives, struct_imports};
@@ -53,7 +62,7 @@ impl WrappedMediaContainer {
     }
 
     pub fn is_hub(&self) -> bool {
-        !self.media_container.hub.is_empty()
+        self.media_container.is_hub()
     }
 
     pub fn is_section_hub(&self) -> bool {
@@ -98,7 +107,7 @@ where
 
 #[struct_derives()]
 #[serde_as]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", remote = "Self")]
 pub struct MediaContainer {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     #[yaserde(attribute = true)]
@@ -121,11 +130,11 @@ pub struct MediaContainer {
     pub identifier: Option<String>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    #[yaserde(attribute = true)]
+    #[yaserde(attribute = true, rename = "parentTitle")]
     pub parent_title: Option<String>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    #[yaserde(attribute = true)]
+    #[yaserde(attribute = true, rename = "title2")]
     pub title_2: Option<String>,
 
     #[serde(
@@ -145,21 +154,10 @@ pub struct MediaContainer {
     #[yaserde(attribute = true, rename = "librarySectionUUID")]
     pub library_section_uuid: Option<String>,
 
-    #[serde(default, rename = "Hub", skip_serializing_if = "Vec::is_empty")]
-    #[yaserde(rename = "Hub")]
-    pub hub: Vec<MetaData>,
-
-    #[serde(default, rename = "Metadata", skip_serializing_if = "Vec::is_empty")]
-    #[yaserde(rename = "Metadata")]
-    pub metadata: Vec<MetaData>,
-
-    #[serde(default, rename = "Video", skip_serializing_if = "Vec::is_empty")]
-    #[yaserde(rename = "Video")]
-    pub video: Vec<MetaData>,
-
-    #[serde(default, rename = "Directory", skip_serializing_if = "Vec::is_empty")]
-    #[yaserde(rename = "Directory")]
-    pub directory: Vec<MetaData>,
+    /// The `Hub`, `Metadata`, `Video` and `Directory` children.
+    #[serde(flatten, skip_deserializing)]
+    #[yaserde(flatten = true, default = "default_children")]
+    children: Children,
 
     #[serde(
         default,
@@ -304,6 +302,134 @@ pub struct MediaContainer {
     #[serde(default, rename = "Meta", skip_serializing_if = "Option::is_none")]
     #[yaserde(attribute = true, rename = "Meta")]
     pub meta: Option<Meta>,
+
+    /// Fields that aren't modeled, passed on unchanged.
+    #[serde(flatten, skip_deserializing)]
+    #[yaserde(flatten = true, default = "default_extra_fields")]
+    pub extra: ExtraFields<MediaContainer>,
+}
+
+impl_extra_fields!(MediaContainer, children);
+
+// A media container whose hubs keep their children as JSON.
+#[derive(Deserialize)]
+struct RawHubsContainer {
+    #[serde(rename = "MediaContainer")]
+    media_container: RawHubs,
+}
+
+struct RawHubs(MediaContainer);
+
+impl<'de> Deserialize<'de> for RawHubs {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut extra = Map::new();
+        let mut hubs = KeepHubs::default();
+        let mut container =
+            MediaContainer::deserialize(Capture::new(deserializer, &mut extra, &mut hubs))?;
+        container.extra = ExtraFields::new(extra);
+        container.children = hubs.0 .0;
+        Ok(RawHubs(container))
+    }
+}
+
+// Takes the children of a container, with the children of its hubs as JSON.
+#[derive(Default)]
+struct KeepHubs(TakeChildren);
+
+impl<'de> Intercept<'de> for KeepHubs {
+    fn intercept<A: MapAccess<'de>>(&mut self, key: &str, map: &mut A) -> Result<bool, A::Error> {
+        if key != "Hub" {
+            return self.0.intercept(key, map);
+        }
+        let hubs: Option<Vec<RawHub>> = map.next_value()?;
+        for RawHub(mut hub) in hubs.unwrap_or_default() {
+            hub.element = ChildKind::Hub;
+            self.0 .0.push(hub);
+        }
+        Ok(true)
+    }
+}
+
+struct RawHub(MetaData);
+
+impl<'de> Deserialize<'de> for RawHub {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut extra = Map::new();
+        let mut children = KeepChildren::default();
+        let mut hub = MetaData::deserialize(Capture::new(deserializer, &mut extra, &mut children))?;
+        hub.extra = ExtraFields::new(extra);
+        hub.raw_children = children.0;
+        Ok(RawHub(hub))
+    }
+}
+
+// Takes the children of a hub as they are.
+#[derive(Default)]
+struct KeepChildren(RawChildren);
+
+impl<'de> Intercept<'de> for KeepChildren {
+    fn intercept<A: MapAccess<'de>>(&mut self, key: &str, map: &mut A) -> Result<bool, A::Error> {
+        if !["Metadata", "Video", "Directory"].contains(&key) {
+            return Ok(false);
+        }
+        let raw: Option<Box<RawValue>> = map.next_value()?;
+        if let Some(raw) = raw {
+            self.0 .0.push((key.to_string(), raw));
+        }
+        Ok(true)
+    }
+}
+
+// Path to the innermost element that is still open at the end of `xml`, like
+// `MediaContainer.Hub[1].Video[0]`.
+fn xml_path(xml: &[u8]) -> String {
+    let mut path: Vec<(String, usize)> = Vec::new();
+    // Number of elements by name, for each open element and the document.
+    let mut counts: Vec<HashMap<String, usize>> = vec![HashMap::new()];
+
+    for event in EventReader::new(xml) {
+        match event {
+            Ok(XmlEvent::StartElement { name, .. }) => {
+                let count = counts
+                    .last_mut()
+                    .map(|counts| {
+                        let count = counts.entry(name.local_name.clone()).or_default();
+                        *count += 1;
+                        *count - 1
+                    })
+                    .unwrap_or_default();
+
+                path.push((name.local_name, count));
+                counts.push(HashMap::new());
+            }
+            Ok(XmlEvent::EndElement { .. }) => {
+                path.pop();
+                counts.pop();
+            }
+            // The end of `xml`, which usually cuts an element short.
+            Ok(XmlEvent::EndDocument) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    if path.is_empty() {
+        return ".".to_string();
+    }
+
+    path.iter()
+        .enumerate()
+        .map(|(depth, (name, index))| match depth {
+            0 => name.clone(),
+            _ => format!("{}[{}]", name, index),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
 }
 
 pub(crate) fn option_number_from_string<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
@@ -325,7 +451,7 @@ impl MediaContainer {
     }
 
     pub async fn better_on_deck(&mut self, collection_title: &str, plex_client: &PlexClient) {
-        let config = Config::load();
+        let config = &plex_client.config;
 
         if config.better_on_deck.enabled {
             if let Some(in_progress) = &config.better_on_deck.in_progress {
@@ -342,51 +468,128 @@ impl MediaContainer {
         }
     }
 
-    pub async fn from_reqwest_response(res: reqwest::Response) -> Result<Self, Error> {
-        let bytes = res.bytes().await.unwrap();
+    pub async fn from_reqwest_response(res: reqwest::Response) -> Result<Self, PlexError> {
+        let content_type = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let bytes = res.bytes().await?;
+
+        let content_type = ContentType::of_body(content_type.as_deref(), &bytes);
 
-        Self::from_bytes(bytes).await
+        Self::from_bytes(bytes, content_type).await
     }
 
-    pub async fn from_hyper_response(res: HyperResponse) -> Result<Self, Error> {
-        let bytes = res.into_body().collect().await.unwrap().to_bytes();
+    pub async fn from_hyper_response(res: HyperResponse) -> Result<Self, PlexError> {
+        let content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let bytes = res
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| PlexError::Upstream(e.to_string()))?
+            .to_bytes();
+
+        let content_type = ContentType::of_body(content_type.as_deref(), &bytes);
+
+        Self::from_bytes(bytes, content_type).await
+    }
 
-        Self::from_bytes(bytes).await
+    /// Like `from_reqwest_response`, but keeps the children of hubs as the
+    /// JSON they came in, so hubs that no transform changes are passed on
+    /// without decoding them. XML is decoded as a whole.
+    pub async fn from_reqwest_response_keeping_children(
+        res: reqwest::Response,
+    ) -> Result<Self, PlexError> {
+        let content_type = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let bytes = res.bytes().await?;
+
+        match ContentType::of_body(content_type.as_deref(), &bytes) {
+            ContentType::Json => Self::from_json_keeping_children(&bytes),
+            ContentType::Xml => Self::from_xml(&bytes),
+        }
     }
 
-    pub async fn from_bytes(bytes: Bytes) -> Result<Self, Error> {
+    /// Decodes a media container in the format of `content_type`. Errors
+    /// carry the path to the value, or for XML the element, that failed.
+    pub async fn from_bytes(bytes: Bytes, content_type: ContentType) -> Result<Self, PlexError> {
+        match content_type {
+            ContentType::Json => Self::from_json(&bytes),
+            ContentType::Xml => Self::from_xml(&bytes),
+        }
+    }
+
+    fn from_json(bytes: &[u8]) -> Result<Self, PlexError> {
         // Attempt to convert bytes to a UTF-8 string
-        match str::from_utf8(&bytes) {
+        match str::from_utf8(bytes) {
             Ok(json_str) => {
                 // Proceed with deserialization
                 let deserializer = &mut serde_json::Deserializer::from_reader(json_str.as_bytes());
-                let result: WrappedMediaContainer =
-                    serde_path_to_error::deserialize(deserializer).map_err(Error::other)?;
+                let result: WrappedMediaContainer = serde_path_to_error::deserialize(deserializer)
+                    .map_err(|e| PlexError::Decode {
+                        path: e.path().to_string(),
+                        message: e.inner().to_string(),
+                    })?;
 
                 Ok(result.media_container)
             }
             Err(e) => {
                 // Log an error if the bytes cannot be converted to a string
                 tracing::error!("Failed to convert bytes to UTF-8 string: {}", e);
-                Err(Error::other(e))
+                Err(PlexError::Decode {
+                    path: ".".to_string(),
+                    message: e.to_string(),
+                })
             }
         }
     }
 
-    pub fn is_hub(&self) -> bool {
-        !self.hub.is_empty()
+    fn from_json_keeping_children(bytes: &[u8]) -> Result<Self, PlexError> {
+        let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+        let result: RawHubsContainer = serde_path_to_error::deserialize(deserializer)
+            .map_err(|e| PlexError::Decode {
+                path: e.path().to_string(),
+                message: e.inner().to_string(),
+            })?;
+
+        Ok(result.media_container.0)
     }
 
-    pub fn exclude_watched(&self) -> bool {
-        let config = Config::load();
+    fn from_xml(bytes: &[u8]) -> Result<Self, PlexError> {
+        let mut reader = bytes;
 
+        yaserde::de::from_reader(&mut reader).map_err(|message| {
+            // The parser reads a byte at a time, so what it has read ends
+            // in the element that failed.
+            let read = bytes.len() - reader.len();
+
+            PlexError::Decode {
+                path: xml_path(&bytes[..read]),
+                message,
+            }
+        })
+    }
+
+    pub fn is_hub(&self) -> bool {
+        self.children.iter().any(|child| child.element == ChildKind::Hub)
+    }
+
+    pub fn exclude_watched(&self, config: &Config) -> bool {
         if config.exclude_watched.all  {
             return true;
         }
 
-        if let Some(first_meta) = self.metadata.first() {
+        if let Some(first_meta) = self.children.first() {
             let has_excluded_label = first_meta.has_label("REPLEX_EXCLUDE_WATCHED".to_string());
-            let is_config_excluded = config.exclude_watched.collections.as_ref().map_or(false, |collections| {
+            let is_config_excluded = config.exclude_watched.collections.as_ref().is_some_and(|collections| {
                 collections.contains(&first_meta.title)
             });
 
@@ -397,62 +600,35 @@ impl MediaContainer {
     }
 
     pub fn set_type(&mut self, value: String) {
-        for hub in &mut self.hub {
+        for hub in self.children.iter_mut().filter(|child| child.element == ChildKind::Hub) {
             hub.r#type = value.clone();
         }
     }
 
     pub fn set_children(&mut self, value: Vec<MetaData>) {
         let len: i64 = value.len().try_into().unwrap();
-        if !self.metadata.is_empty() {
-            self.metadata = value;
-        } else if !self.hub.is_empty() {
-            self.hub = value;
-        } else if !self.video.is_empty() {
-            self.video = value;
-        } else if !self.directory.is_empty() {
-            self.directory = value;
-        };
+        *self.children_mut() = value;
         self.size = Some(len);
     }
 
-    pub fn set_children_mut(&mut self, value: &mut Vec<MetaData>) {
-        let len: i64 = value.len().try_into().unwrap();
-        if !self.metadata.is_empty() {
-            self.metadata = value.to_owned();
-        } else if !self.hub.is_empty() {
-            self.hub = value.to_owned();
-        } else if !self.video.is_empty() {
-            self.video = value.to_owned();
-        } else if !self.directory.is_empty() {
-            self.directory = value.to_owned();
-        };
-        self.size = Some(len);
+    pub fn children(&self) -> &[MetaData] {
+        &self.children
     }
 
-    pub fn children_mut(&mut self) -> &mut Vec<MetaData> {
-        if !self.metadata.is_empty() {
-            return &mut self.metadata;
-        } else if !self.hub.is_empty() {
-            return &mut self.hub;
-        } else if !self.video.is_empty() {
-            return &mut self.video;
-        } else if !self.directory.is_empty() {
-            return &mut self.directory;
-        };
-        &mut self.metadata
+    pub fn iter_children(&self) -> std::slice::Iter<'_, MetaData> {
+        self.children().iter()
+    }
+
+    pub fn iter_children_mut(&mut self) -> std::slice::IterMut<'_, MetaData> {
+        self.children_mut().iter_mut()
     }
 
-    pub fn children(&mut self) -> Vec<MetaData> {
-        if !self.metadata.is_empty() {
-            return self.metadata.clone();
-        } else if !self.hub.is_empty() {
-            return self.hub.clone();
-        } else if !self.video.is_empty() {
-            return self.video.clone();
-        } else if !self.directory.is_empty() {
-            return self.directory.clone();
-        };
-        vec![]
+    /// Moves the children out of the container, leaving their list empty.
+    pub fn take_children(&mut self) -> Vec<MetaData> {
+        std::mem::take(self.children_mut())
+    }
+
+    pub fn children_mut(&mut self) -> &mut Vec<MetaData> {
+        &mut self.children
     }
 }
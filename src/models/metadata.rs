@@ -1,8 +1,8 @@
-use crate::config::*;
+use crate::config::{HubRule, HubTarget};
 use crate::models::*;
 use crate::plex::client::PlexClient;
 use crate::utils::*;
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use serde_with::serde_as;
 
 use crate::deserializers::{option_number_from_string, option_string_from_number};
@@ -32,8 +32,16 @@ fn default_tag() -> Vec<Tag> {
     vec![Tag::default()]
 }
 
+fn default_string() -> String {
+    String::new()
+}
+
+fn default_element() -> ChildKind {
+    ChildKind::default()
+}
+
 #[struct_derives()]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", remote = "Self")]
 #[serde_as]
 pub struct MetaData {
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -53,7 +61,7 @@ pub struct MetaData {
     pub primary_guid: Option<String>,
 
     #[serde(default)]
-    #[yaserde(attribute = true)]
+    #[yaserde(attribute = true, default = "default_string")]
     pub title: String,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -85,7 +93,7 @@ pub struct MetaData {
     pub icon: Option<String>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    #[yaserde(attribute = true)]
+    #[yaserde(attribute = true, rename = "viewGroup")]
     pub view_group: Option<String>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -109,7 +117,7 @@ pub struct MetaData {
     pub duration: Option<i64>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    #[yaserde(attribute = true)]
+    #[yaserde(attribute = true, rename = "viewMode")]
     pub view_mode: Option<i32>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -238,7 +246,7 @@ pub struct MetaData {
     pub library_section_key: Option<String>,
 
     #[serde(default)]
-    #[yaserde(attribute = true, rename = "type")]
+    #[yaserde(attribute = true, rename = "type", default = "default_string")]
     pub r#type: String,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -289,17 +297,10 @@ pub struct MetaData {
     #[yaserde(attribute = true, rename = "Meta")]
     pub meta: Option<Meta>,
 
-    #[serde(default, rename = "Metadata", skip_serializing_if = "Vec::is_empty")]
-    #[yaserde(rename = "Metadata")]
-    pub metadata: Vec<MetaData>,
-
-    #[serde(default, rename = "Directory", skip_serializing_if = "Vec::is_empty")]
-    #[yaserde(rename = "Directory")]
-    pub directory: Vec<MetaData>, // only avaiable in XML
-
-    #[serde(default, rename = "Video", skip_serializing_if = "Vec::is_empty")]
-    #[yaserde(rename = "Video")]
-    pub video: Vec<MetaData>, // again only xml, but its the same as directory and metadata
+    /// The `Metadata`, `Video` and `Directory` items, like those of a hub.
+    #[serde(flatten, skip_deserializing)]
+    #[yaserde(flatten = true, default = "default_children")]
+    children: Children,
 
     #[serde(
         default,
@@ -380,11 +381,33 @@ pub struct MetaData {
     #[serde(default, rename = "Genre", skip_serializing_if = "Vec::is_empty")]
     #[yaserde(default = "default_tag", rename = "Genre")]
     pub genres: Vec<Tag>,
+
+    /// Fields that aren't modeled, passed on unchanged.
+    #[serde(flatten, skip_deserializing)]
+    #[yaserde(flatten = true, default = "default_extra_fields")]
+    pub extra: ExtraFields<MetaData>,
+
+    /// Children of a hub that weren't decoded, written out as they came.
+    #[serde(flatten, skip_deserializing)]
+    #[yaserde(skip_serializing = true, default = "default_raw_children")]
+    pub raw_children: RawChildren,
+
+    /// The element the item is in within its parent.
+    #[serde(skip)]
+    #[yaserde(skip_serializing = true, default = "default_element")]
+    pub element: ChildKind,
+
+    /// The `hub_rules` that match this hub, once `hub_rules` looked them up.
+    #[serde(skip)]
+    #[yaserde(skip_serializing = true, default = "default_hub_rule_matches")]
+    pub hub_rule_matches: HubRuleMatches,
 }
 
+impl_extra_fields!(MetaData, children);
+
 impl MetaData {
     pub async fn better_on_deck(&mut self, plex_client: &PlexClient) {
-        let config = Config::load();
+        let config = &plex_client.config;
 
         if config.better_on_deck.enabled {
             if let Some(in_progress) = &config.better_on_deck.in_progress {
@@ -416,7 +439,18 @@ impl MetaData {
             return Ok(false);
         }
 
-        let config = Config::load();
+        // Hub rules come first, they can also turn hero rows into shelves.
+        if let Some(style) = self
+            .hub_rules(plex_client)
+            .await?
+            .iter()
+            .rev()
+            .find_map(|rule| rule.style.clone())
+        {
+            return Ok(style == Style::Hero);
+        }
+
+        let config = &plex_client.config;
 
         // Check if the hub identifier matches any of the hero row identifiers.
         if let Some(hero_rows) = &config.hero_rows {
@@ -436,7 +470,7 @@ impl MetaData {
             let collection = Collection::get(plex_client, collection_id).await?;
 
             // Check if the first child of the collection details has the "REPLEXHERO" label.
-            if let Some(collection) = collection.metadata.first() {
+            if let Some(collection) = collection.children().first() {
                 return Ok(collection.has_label("REPLEXHERO".to_string()));
             }
         }
@@ -444,15 +478,76 @@ impl MetaData {
         Ok(false)
     }
 
-    pub fn is_watched(&self) -> bool {
-        let view_count = self.view_count.clone();
-        let leaf_count = self.leaf_count.clone();
-        let viewed_leaf_count = self.viewed_leaf_count.clone();
+    /// The `hub_rules` that match this hub, in the order of the config. They
+    /// are looked up once per hub, and kept for the other transforms.
+    pub async fn hub_rules<'a>(&self, plex_client: &'a PlexClient) -> Result<Vec<&'a HubRule>> {
+        let rules = &plex_client.config.hub_rules;
+        if rules.is_empty() || !self.is_hub() {
+            return Ok(Vec::new());
+        }
 
-        if view_count.is_some() && view_count.unwrap() > 0 {
-            if leaf_count.is_none() && viewed_leaf_count.is_none() {
-                return true;
-            }
+        if let Some(indices) = self.hub_rule_matches.get() {
+            return Ok(indices.iter().filter_map(|&i| rules.get(i)).collect());
+        }
+
+        let target = HubTarget {
+            hub_identifier: self.hub_identifier.as_deref(),
+            context: self.context.as_deref(),
+            title: &self.title,
+            section: self.section_id(),
+        };
+        let mut matching: Vec<usize> = rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matcher.matches(&target))
+            .map(|(i, _)| i)
+            .collect();
+
+        // Labels are only fetched when a rule that matches otherwise asks for them.
+        if matching.iter().any(|&i| rules[i].matcher.collection_label.is_some()) {
+            let labels: Vec<String> = if self.is_collection_hub() {
+                let collection =
+                    Collection::get(plex_client, get_collection_id_from_hub(self)).await?;
+                collection
+                    .children()
+                    .first()
+                    .map(|c| c.labels.iter().map(|l| l.tag.clone()).collect())
+                    .unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            matching.retain(|&i| rules[i].matcher.matches_labels(labels.iter().map(String::as_str)));
+        }
+
+        let matched = matching.iter().map(|&i| &rules[i]).collect();
+        self.hub_rule_matches.set(matching);
+        Ok(matched)
+    }
+
+    /// The library of a hub. Hubs of the home screen only say so in their
+    /// identifier, like `home.movies.recent.6` or `custom.collection.6.254687`.
+    pub fn section_id(&self) -> Option<i64> {
+        if self.library_section_id.is_some() {
+            return self.library_section_id;
+        }
+
+        let hub_id = self.hub_identifier.as_deref()?;
+        if let Some(rest) = hub_id.strip_prefix("custom.collection.") {
+            return rest.split('.').next()?.parse().ok();
+        }
+        hub_id.rsplit('.').next()?.parse().ok()
+    }
+
+    pub fn is_watched(&self) -> bool {
+        let view_count = self.view_count;
+        let leaf_count = self.leaf_count;
+        let viewed_leaf_count = self.viewed_leaf_count;
+
+        if view_count.is_some_and(|count| count > 0)
+            && leaf_count.is_none()
+            && viewed_leaf_count.is_none()
+        {
+            return true;
         }
 
         if let (Some(leaf_count), Some(viewed_leaf_count)) = (leaf_count, viewed_leaf_count) {
@@ -465,30 +560,39 @@ impl MetaData {
     }
 
     pub async fn exclude_watched(&self, plex_client: &PlexClient) -> Result<bool> {
+        if self.is_hub()
+            && self
+                .hub_rules(plex_client)
+                .await?
+                .iter()
+                .any(|rule| rule.exclude_watched)
+        {
+            return Ok(true);
+        }
+
         if !self.is_collection_hub() {
             return Ok(false);
         }
 
-        let config = Config::load();
+        let config = &plex_client.config;
 
         if config.exclude_watched.all {
             return Ok(true);
         }
 
         let collection = Collection::get(plex_client, get_collection_id_from_hub(self)).await?;
+        let Some(collection) = collection.children().first() else {
+            return Ok(false);
+        };
 
-        let has_excluded_label = collection
-            .metadata
-            .first()
-            .unwrap()
-            .has_label("REPLEX_EXCLUDE_WATCHED".to_string());
+        let has_excluded_label = collection.has_label("REPLEX_EXCLUDE_WATCHED".to_string());
 
         let is_config_excluded = config
             .exclude_watched
             .collections
             .as_ref()
-            .map_or(false, |collections| {
-                collections.contains(&collection.metadata.first().unwrap().title)
+            .is_some_and(|collections| {
+                collections.contains(&collection.title)
             });
 
         Ok(has_excluded_label || is_config_excluded)
@@ -525,35 +629,52 @@ impl MetaData {
 
     pub fn set_children(&mut self, value: Vec<MetaData>) {
         let len: i32 = value.len().try_into().unwrap();
-        if !self.metadata.is_empty() {
-            self.metadata = value;
-        } else if !self.video.is_empty() {
-            self.video = value;
-        } else if !self.directory.is_empty() {
-            self.directory = value;
-        };
+        *self.children_mut() = value;
         self.size = Some(len);
     }
 
-    pub fn children(&mut self) -> Vec<MetaData> {
-        if !self.metadata.is_empty() {
-            return self.metadata.clone();
-        } else if !self.video.is_empty() {
-            return self.video.clone();
-        } else if !self.directory.is_empty() {
-            return self.directory.clone();
-        };
-        vec![]
+    pub fn children(&self) -> &[MetaData] {
+        &self.children
+    }
+
+    pub fn iter_children(&self) -> std::slice::Iter<'_, MetaData> {
+        self.children().iter()
+    }
+
+    pub fn iter_children_mut(&mut self) -> std::slice::IterMut<'_, MetaData> {
+        self.children_mut().iter_mut()
+    }
+
+    /// Moves the children out of the item, leaving their list empty.
+    pub fn take_children(&mut self) -> Vec<MetaData> {
+        std::mem::take(self.children_mut())
+    }
+
+    pub fn has_raw_children(&self) -> bool {
+        !self.raw_children.is_empty()
+    }
+
+    /// Decodes the children of a hub that were kept as JSON, see
+    /// `MediaContainer::from_reqwest_response_keeping_children`.
+    pub fn decode_children(&mut self) -> Result<()> {
+        let mut children = Vec::new();
+        for (name, raw) in &self.raw_children.0 {
+            let list: Vec<MetaData> = serde_json::from_str(raw.get())
+                .with_context(|| format!("Failed to decode {} of hub {}", name, self.title))?;
+            let kind = ChildKind::from_name(name).unwrap_or_default();
+            children.extend(list.into_iter().map(|mut child| {
+                child.element = kind.clone();
+                child
+            }));
+        }
+
+        self.children = children.into();
+        self.raw_children = RawChildren::default();
+
+        Ok(())
     }
 
     pub fn children_mut(&mut self) -> &mut Vec<MetaData> {
-        if !self.metadata.is_empty() {
-            return &mut self.metadata;
-        } else if !self.video.is_empty() {
-            return &mut self.video;
-        } else if !self.directory.is_empty() {
-            return &mut self.directory;
-        };
-        &mut self.metadata
+        &mut self.children
     }
 }
@@ -0,0 +1,134 @@
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use serde::{Serialize, Serializer};
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::io::{Read, Write};
+use xml::attribute::OwnedAttribute;
+use xml::namespace::Namespace;
+
+use yaserde::de::Deserializer as YaDeserializer;
+use yaserde::ser::Serializer as YaSerializer;
+use yaserde::{YaDeserialize, YaSerialize};
+
+/// Children of a hub that are still the JSON they came in, by the name of their list, like
+/// `Metadata`. As long as no transform needs them they are written out again as they are, which
+/// saves decoding and encoding them. See `MetaData::decode_children`.
+#[derive(Debug, Clone, Default)]
+pub struct RawChildren(pub Vec<(String, Box<RawValue>)>);
+
+impl RawChildren {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Removes `fields` from every child, without decoding them into `MetaData`. Lists that
+    /// don't mention any of the fields are left untouched.
+    pub fn remove_fields(&mut self, fields: &[&str]) -> serde_json::Result<()> {
+        for (_, raw) in &mut self.0 {
+            let json = raw.get();
+            if !fields.iter().any(|field| json.contains(&format!("\"{}\"", field))) {
+                continue;
+            }
+
+            let mut children: Vec<Map<String, Value>> = serde_json::from_str(json)?;
+            for child in &mut children {
+                for field in fields {
+                    child.remove(*field);
+                }
+            }
+            *raw = serde_json::value::to_raw_value(&children)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PartialEq for RawChildren {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|((name, raw), (other_name, other_raw))| {
+                    name == other_name && raw.get() == other_raw.get()
+                })
+    }
+}
+
+impl PartialOrd for RawChildren {
+    /// Children have no order, they are only equal or not.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
+
+impl Serialize for RawChildren {
+    /// Serializes the lists by their names, with the JSON as it is. Flattened into the hub, they
+    /// end up where they came from.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(name, raw)| (name, raw)))
+    }
+}
+
+impl Encode for RawChildren {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        let lists: Vec<(String, String)> = self
+            .0
+            .iter()
+            .map(|(name, raw)| (name.clone(), raw.get().to_owned()))
+            .collect();
+        lists.encode(encoder)
+    }
+}
+
+impl Decode for RawChildren {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let lists: Vec<(String, String)> = Vec::decode(decoder)?;
+        lists
+            .into_iter()
+            .map(|(name, json)| {
+                RawValue::from_string(json)
+                    .map(|raw| (name, raw))
+                    .map_err(|e| DecodeError::OtherString(e.to_string()))
+            })
+            .collect::<Result<_, _>>()
+            .map(RawChildren)
+    }
+}
+
+bincode::impl_borrow_decode!(RawChildren);
+
+impl YaSerialize for RawChildren {
+    /// Raw children are JSON, so they have to be decoded before the hub is written as XML.
+    fn serialize<W: Write>(&self, _writer: &mut YaSerializer<W>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn serialize_attributes(
+        &self,
+        attributes: Vec<OwnedAttribute>,
+        namespace: Namespace,
+    ) -> Result<(Vec<OwnedAttribute>, Namespace), String> {
+        Ok((attributes, namespace))
+    }
+}
+
+impl YaDeserialize for RawChildren {
+    /// Children in XML are always decoded.
+    fn deserialize<R: Read>(_reader: &mut YaDeserializer<R>) -> Result<Self, String> {
+        Ok(RawChildren::default())
+    }
+}
+
+/// Default of `RawChildren` for yaserde, which wants the name of a function.
+pub(crate) fn default_raw_children() -> RawChildren {
+    RawChildren::default()
+}
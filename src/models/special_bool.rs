@@ -1,17 +1,18 @@
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt::{self, Display, Formatter};
-use std::io::Write;
+use std::io::{Read, Write};
+use xml::reader::XmlEvent as ReaderEvent;
 use xml::writer::XmlEvent;
 
+use yaserde::de::Deserializer as YaDeserializer;
 use yaserde::ser::Serializer as YaSerializer;
-use yaserde::YaSerialize;
-use yaserde_derive::YaDeserialize;
+use yaserde::{YaDeserialize, YaSerialize};
 
 /// A special boolean struct designed for XML serialization, catering to the annoying requirement
 /// of an Android mobile client that expects boolean values to be represented as "0" or "1" instead
 /// of "true" or "false".
-#[derive(Debug, Clone, PartialEq, Eq, Default, PartialOrd, YaDeserialize, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, PartialOrd, Encode, Decode)]
 pub struct SpecialBool {
     /// The inner boolean value.
     inner: bool,
@@ -55,6 +56,24 @@ impl YaSerialize for SpecialBool {
     }
 }
 
+impl YaDeserialize for SpecialBool {
+    /// Deserializes "1" and "true" as true, and anything else as false. Attributes are passed in
+    /// as the text of an element, like `<SpecialBool>1</SpecialBool>`.
+    fn deserialize<R: Read>(reader: &mut YaDeserializer<R>) -> Result<Self, String> {
+        reader.read_inner_value(|reader| {
+            let inner = match reader.peek()? {
+                ReaderEvent::Characters(text) => {
+                    matches!(text.trim().to_lowercase().as_str(), "1" | "true")
+                }
+                _ => return Ok(SpecialBool::new(false)),
+            };
+            reader.next_event()?;
+
+            Ok(SpecialBool::new(inner))
+        })
+    }
+}
+
 impl Serialize for SpecialBool {
     /// Serializes the boolean using Serde's boolean serialization mechanism.
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -1,10 +1,11 @@
+use crate::models::{default_extra_fields, impl_extra_fields, ExtraFields};
 use replex_common::{struct_derives, struct_imports};
 use serde_aux::prelude::deserialize_string_from_number;
 
 struct_imports!();
 
 #[struct_derives()]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", remote = "Self")]
 pub struct Stream {
     #[yaserde(attribute = true)]
     #[serde(deserialize_with = "deserialize_string_from_number")]
@@ -141,4 +142,9 @@ pub struct Stream {
     #[yaserde(attribute = true)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub decision: Option<String>,
+    #[yaserde(flatten = true, default = "default_extra_fields")]
+    #[serde(flatten, skip_deserializing)]
+    pub extra: ExtraFields<Stream>,
 }
+
+impl_extra_fields!(Stream);
@@ -1,27 +1,36 @@
-use anyhow::{Error as AnyhowError, Result};
+use anyhow::Result;
 use bincode::{Decode, Encode};
 use futures_util::Future;
 use http::header::{ACCEPT_LANGUAGE, CONNECTION, COOKIE, FORWARDED};
 use http::{HeaderMap, HeaderValue, Method};
 use reqwest::{header, Url};
-use salvo::{Error as SalvoError, Request};
+use salvo::Request;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fmt::Debug;
 use std::sync::Arc;
-use std::time::Duration;
 
-use crate::cache::{CacheManager, CACHE_MANAGER};
-use crate::config::Config;
+use crate::cache::{CacheManager, CacheScope, CACHE_MANAGER};
+use crate::config::{Config, Server};
+use crate::http_client;
 use crate::models::*;
 
+use super::error::PlexError;
 use super::models::PlexContext;
+use super::upstream;
 
 #[derive(Debug, Clone)]
 pub struct PlexClient {
     pub http_client: reqwest_middleware::ClientWithMiddleware,
+    // Headers sent along with every request, taken from the requesting
+    // client.
+    pub headers: HeaderMap,
     pub host: String, // TODO: Dont think this suppsoed to be here. Should be higher up
+    // Name of the server in `servers` that requests go to, `None` for `host`.
+    pub server: Option<String>,
     pub cache: Arc<CacheManager>,
+    // Config resolved for the client this request came from.
+    pub config: Arc<Config>,
     // Platform name, e.g. iOS, macOS, etc.
     pub x_plex_platform: Platform,
     // UUID, serial number, or other number unique per device.
@@ -36,9 +45,18 @@ impl PlexClient {
     pub async fn get(
         &self,
         path_or_url: &str,
-    ) -> Result<reqwest::Response, SalvoError> {
+    ) -> Result<reqwest::Response, PlexError> {
         // Check if the input is a valid URL. If parsing fails, it's likely a path.
         let url = match Url::parse(path_or_url) {
+            // URLs built from the request point at `host`, which may not be
+            // the server this client talks to.
+            Ok(parsed_url) if parsed_url.as_str().starts_with(&self.config.host) => {
+                format!(
+                    "{}{}",
+                    self.host.trim_end_matches('/'),
+                    &parsed_url.as_str()[self.config.host.len()..]
+                )
+            }
             Ok(parsed_url) => parsed_url.to_string(),
             Err(_) => {
                 format!(
@@ -57,22 +75,17 @@ impl PlexClient {
         method: http::Method,
         url: &str,
         extra_headers: Option<HeaderMap>,
-    ) -> Result<reqwest::Response, SalvoError> {
+    ) -> Result<reqwest::Response, PlexError> {
         let target_uri = url::Url::parse(url).map_err(|e| {
             tracing::error!("Failed to parse URL '{}': {}", url, e);
-            AnyhowError::new(e)
+            PlexError::Internal(format!("Invalid URL {}: {}", url, e))
+        })?;
+        let target_host = target_uri.host_str().ok_or_else(|| {
+            PlexError::Internal(format!("Missing host in URL {}", url))
         })?;
-        // let target_uri = url::Url::parse(url).map_err(AnyhowError::new)?;
-        let target_host = target_uri
-            .host_str()
-            .ok_or_else(|| AnyhowError::msg("Missing host in URL"))?;
 
-        let mut headers = http::HeaderMap::new();
-        headers.insert(
-            http::header::HOST,
-            header::HeaderValue::from_str(target_host)
-                .map_err(AnyhowError::new)?,
-        );
+        let mut headers = self.headers.clone();
+        headers.insert(http::header::HOST, Self::header_value(target_host)?);
 
         if let Some(extra_headers) = extra_headers {
             headers.extend(extra_headers);
@@ -83,52 +96,137 @@ impl PlexClient {
             .request(method, url)
             .headers(headers)
             .send()
-            .await
-            .map_err(AnyhowError::new)?;
+            .await?;
 
         Ok(response)
     }
 
+    /// Returns the cached value for `cache_key`, or runs `fetcher` and caches
+    /// its result. The cache is skipped entirely when `cache.enabled` is off.
+    ///
+    /// Concurrent calls for the same key share a single run of `fetcher`, and
+    /// its error if it fails.
+    /// With `cache.auto_refresh`, entries that are close to expiring are still
+    /// returned right away, while `fetcher` refreshes them in the background.
     pub async fn cache_or_fetch<T, F, Fut>(
+        &self,
         cache_key: &str,
         fetcher: F,
-    ) -> Result<T>
+    ) -> Result<T, PlexError>
     where
-        T: DeserializeOwned + Serialize + 'static + Debug + Decode + Encode,
-        F: FnOnce() -> Fut,
-        Fut: Future<Output = Result<T>>,
+        T: DeserializeOwned
+            + Serialize
+            + 'static
+            + Debug
+            + Decode
+            + Encode
+            + Clone
+            + Send
+            + Sync,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, PlexError>> + Send + 'static,
     {
+        let enabled = self.config.cache.enabled;
+
         // Attempt to retrieve from cache first
-        match CACHE_MANAGER.get::<T>(cache_key).await {
-            Ok(Some(cached)) => Ok(cached),
-            _ => {
-                // If not found in cache, invoke the fetcher to get the data
-                let result = fetcher().await.map_err(|e| {
-                    anyhow::anyhow!("Error fetching data: {}", e)
-                })?;
+        if enabled {
+            if let Ok(Some(cached)) = self.cache.get_cached::<T>(cache_key).await
+            {
+                if cached.stale && self.config.cache.auto_refresh {
+                    self.refresh(cache_key, fetcher);
+                }
+
+                return Ok(cached.value);
+            }
+        }
 
-                // Insert fetched data into cache for future requests
-                CACHE_MANAGER.insert(cache_key, &result).await.map_err(
-                    |e| anyhow::anyhow!("Error inserting into cache: {}", e),
-                )?;
+        let cache = self.cache.clone();
+        let key = cache_key.to_owned();
+
+        self.cache
+            .single_flight(cache_key, move || async move {
+                // If not found in cache, invoke the fetcher to get the data
+                let result = fetcher().await?;
+
+                // Insert fetched data into cache for future requests. The
+                // data is still good when that fails.
+                if enabled {
+                    if let Err(e) = cache.insert(&key, &result).await {
+                        tracing::warn!(
+                            error = %e,
+                            key = %key,
+                            "Failed to insert cache entry"
+                        );
+                    }
+                }
 
                 Ok(result)
-            }
-        }
+            })
+            .await
+            .map_err(PlexError::from)
     }
 
-    pub async fn get_hubs(&self, _id: i32) -> Result<MediaContainer> {
-        let resp = self.get("/hubs").await.unwrap();
-        let container =
-            MediaContainer::from_reqwest_response(resp).await.unwrap();
+    // Re-runs `fetcher` in the background and replaces the cached value for
+    // `cache_key` with the result. At most one refresh per key runs at a time.
+    fn refresh<T, F, Fut>(&self, cache_key: &str, fetcher: F)
+    where
+        T: Serialize + 'static + Debug + Encode + Send + Sync,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, PlexError>> + Send + 'static,
+    {
+        // Held by the task, so a refresh that fails or panics doesn't keep
+        // the key from being refreshed again.
+        let Some(refreshing) = self.cache.start_refresh(cache_key) else {
+            return;
+        };
+
+        let cache = self.cache.clone();
+        let cache_key = cache_key.to_owned();
+
+        tokio::spawn(async move {
+            let _refreshing = refreshing;
+
+            match fetcher().await {
+                Ok(result) => {
+                    if let Err(e) = cache.insert(&cache_key, &result).await {
+                        tracing::warn!(
+                            error = %e,
+                            key = %cache_key,
+                            "Failed to update cache entry"
+                        );
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error = %e,
+                        key = %cache_key,
+                        "Failed to refresh cache entry"
+                    );
+                }
+            }
+        });
+    }
 
-        Ok(container)
+    pub async fn get_hubs(
+        &self,
+        _id: i32,
+    ) -> Result<MediaContainer, PlexError> {
+        let resp = PlexError::check(self.get("/hubs").await?)?;
+        MediaContainer::from_reqwest_response(resp).await
     }
 
-    pub async fn get_item_by_key(self, key: String) -> Result<MediaContainer> {
-        let resp = self.get(&key).await.unwrap();
-        let container =
-            MediaContainer::from_reqwest_response(resp).await.unwrap();
+    /// Fetches the item at `key`, failing with `PlexError::NotFound` when
+    /// Plex returns no metadata for it.
+    pub async fn get_item_by_key(
+        self,
+        key: String,
+    ) -> Result<MediaContainer, PlexError> {
+        let resp = PlexError::check(self.get(&key).await?)?;
+        let container = MediaContainer::from_reqwest_response(resp).await?;
+
+        if container.children().is_empty() {
+            return Err(PlexError::NotFound(key));
+        }
 
         Ok(container)
     }
@@ -136,91 +234,154 @@ impl PlexClient {
     pub async fn get_provider_data(
         &self,
         guid: &String,
-    ) -> Result<MediaContainer> {
+    ) -> Result<MediaContainer, PlexError> {
         let path = format!(
             "https://metadata.provider.plex.tv/library/metadata/{}",
             guid
         );
 
         let mut headers = HeaderMap::new();
-        headers.insert(
-            "X-Plex-Token",
-            header::HeaderValue::from_str(self.x_plex_token.clone().as_str())
-                .unwrap(),
-        );
+        headers.insert("X-Plex-Token", Self::header_value(&self.x_plex_token)?);
         headers.insert(
             "Accept",
             header::HeaderValue::from_static("application/json"),
         );
 
+        // Failures of the provider itself are told apart from those of the
+        // Plex server.
         let res = self
             .request(Method::GET, &path, Some(headers))
             .await
-            .map_err(|e| {
-                anyhow::anyhow!("Failed to get provider data: {}", e)
+            .and_then(PlexError::check)
+            .map_err(|e| match e {
+                PlexError::Timeout | PlexError::Upstream(_) => {
+                    PlexError::ProviderUnavailable(e.to_string())
+                }
+                e => e,
             })?;
 
-        let container = MediaContainer::from_reqwest_response(res)
-            .await
-            .map_err(|e| {
-                anyhow::anyhow!("Error deserializing response: {}", e)
-            })?;
+        MediaContainer::from_reqwest_response(res).await
+    }
 
-        Ok(container)
+    /// Builds a client that makes requests on behalf of the client of `req`.
+    /// Fails with `PlexError::Unauthorized` when the request has no token.
+    pub fn from_request(
+        req: &Request,
+        params: &PlexContext,
+    ) -> Result<Self, PlexError> {
+        let mut client = Self::from_context(params, req.headers())?;
+        client.host = upstream::host_for(req, &client.config);
+        client.server = upstream::selected_server(req);
+
+        Ok(client)
     }
 
-    pub fn from_request(req: &Request, params: &PlexContext) -> Self {
-        let headers = Self::build_headers(params, req.headers());
-        let config = Config::load();
-        let token = params
-            .token
-            .as_ref()
-            .expect("Expected to have a token in header or query");
-
-        Self {
-            http_client: reqwest_middleware::ClientBuilder::new(
-                reqwest::Client::builder()
-                    .default_headers(headers)
-                    .gzip(true)
-                    .timeout(Duration::from_secs(30))
-                    .build()
-                    .expect("Failed to build HTTP client"),
-            )
-            .build(),
-            host: config.host.clone(),
+    /// Builds a client for requests that Replex makes on behalf of the user
+    /// and device in `params`, with the Plex headers in `headers`, to
+    /// `host` or the standby host that is up.
+    pub fn from_context(
+        params: &PlexContext,
+        headers: &HeaderMap,
+    ) -> Result<Self, PlexError> {
+        let token = params.token.as_ref().ok_or(PlexError::Unauthorized)?;
+        let headers = Self::build_headers(token, params, headers)?;
+        let config = Config::for_context(params);
+
+        Ok(Self {
+            http_client: http_client::client(),
+            headers,
+            host: upstream::active_host(&config),
+            server: None,
             x_plex_token: token.to_string(),
             x_plex_client_identifier: params.client_identifier.clone(),
             x_plex_platform: params.platform.clone(),
             cache: CACHE_MANAGER.clone(),
+            config,
+        })
+    }
+
+    /// Returns a client that makes the same requests to `server` instead,
+    /// for the responses that Replex merges. The requests keep the token of
+    /// the client, unless `server` shares the view of its owner, whose token
+    /// is then used once `host` accepted the token of the client.
+    pub async fn for_server(&self, server: &Server) -> Result<Self, PlexError> {
+        let mut client = self.clone();
+        client.host = server.host.clone();
+        client.server = Some(server.name.clone());
+
+        if let Some(token) = server.shared_token() {
+            self.check_token().await?;
+            client
+                .headers
+                .insert("X-Plex-Token", Self::header_value(token)?);
+            client.x_plex_token = token.clone();
         }
+
+        Ok(client)
     }
 
-    fn header_value(value: &str) -> HeaderValue {
-        HeaderValue::from_str(value).expect("Invalid header value")
+    // Fails with `PlexError::Unauthorized` when the active host doesn't
+    // accept the token of the client. Accepted tokens are cached.
+    async fn check_token(&self) -> Result<(), PlexError> {
+        let mut client = self.clone();
+        client.server = None;
+        let cache_key = client.generate_cache_key("token_check".to_string(), CacheScope::User);
+
+        let url = format!("{}/", upstream::active_host(&self.config));
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Plex-Token", Self::header_value(&self.x_plex_token)?);
+
+        self.cache_or_fetch(&cache_key, move || async move {
+            PlexError::check(client.request(Method::GET, &url, Some(headers)).await?)?;
+            Ok(true)
+        })
+        .await
+        .map(|_| ())
     }
 
-    pub fn generate_cache_key(&self, name: String) -> String {
-        format!(
-            "{}:{}-{}",
-            name, self.x_plex_token,
-            self.x_plex_client_identifier.clone().unwrap_or_default()
-        )
+    fn header_value(value: &str) -> Result<HeaderValue, PlexError> {
+        HeaderValue::from_str(value).map_err(|_| {
+            PlexError::BadRequest(format!("Invalid header value {:?}", value))
+        })
+    }
+
+    /// Builds the cache key for `name`, which starts with the key family.
+    /// Only `CacheScope::User` keys include the token and client identifier.
+    /// Keys for the other `servers` end with `@` and the name of the server.
+    pub fn generate_cache_key(
+        &self,
+        name: String,
+        scope: CacheScope,
+    ) -> String {
+        let key = match scope {
+            CacheScope::Global => return format!("{}:global", name),
+            CacheScope::Server => format!("{}:server", name),
+            CacheScope::User => format!(
+                "{}:{}-{}",
+                name, self.x_plex_token,
+                self.x_plex_client_identifier.clone().unwrap_or_default()
+            ),
+        };
+
+        match &self.server {
+            Some(server) => format!("{}@{}", key, server),
+            None => key,
+        }
     }
 
     fn build_headers(
+        token: &str,
         params: &PlexContext,
         req_headers: &HeaderMap,
-    ) -> HeaderMap {
+    ) -> Result<HeaderMap, PlexError> {
         let mut headers = HeaderMap::new();
 
-        headers.insert("Accept", Self::header_value("application/json"));
-        headers.insert(
-            "X-Plex-Token",
-            Self::header_value(params.token.as_ref().unwrap()),
-        );
+        // JSON is cheaper to decode, but responses in XML are decoded as well.
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+        headers.insert("X-Plex-Token", Self::header_value(token)?);
         headers.insert(
             "X-Plex-Platform",
-            Self::header_value(&params.platform.to_string()),
+            Self::header_value(&params.platform.to_string())?,
         );
 
         if let Some(i) = req_headers.get("X-Plex-Client-Identifier") {
@@ -321,6 +482,80 @@ impl PlexClient {
             headers.insert(CONNECTION, i.clone());
         }
 
-        headers
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::plex::testing;
+
+    fn client(share_owner_view: bool) -> PlexClient {
+        let config = testing::config(json!({
+            "servers": [{
+                "name": "4k",
+                "host": "http://4k.test:32400",
+                "token": "owner",
+                "share_owner_view": share_owner_view,
+            }],
+        }));
+        testing::client(config, |req| {
+            let accepted = req.headers().get("X-Plex-Token").is_some_and(|t| t == "token");
+            testing::response(if accepted { 200 } else { 401 }, json!({}))
+        })
+    }
+
+    #[test]
+    fn server_keys_are_shared_by_users_of_the_same_server() {
+        let plex_client = client(false);
+        let mut other_user = plex_client.clone();
+        other_user.x_plex_token = "other".to_string();
+        let mut other_server = plex_client.clone();
+        other_server.server = Some("4k".to_string());
+
+        let key = |client: &PlexClient, scope| client.generate_cache_key("collection:1".to_string(), scope);
+
+        assert_eq!(key(&plex_client, CacheScope::Server), "collection:1:server");
+        assert_eq!(key(&other_user, CacheScope::Server), "collection:1:server");
+        assert_eq!(key(&other_server, CacheScope::Server), "collection:1:server@4k");
+        assert_eq!(key(&other_user, CacheScope::User), "collection:1:other-client");
+        assert_eq!(key(&other_server, CacheScope::Global), "collection:1:global");
+    }
+
+    #[tokio::test]
+    async fn other_servers_get_the_token_of_the_client() {
+        let plex_client = client(false);
+        let server = &plex_client.config.servers[0];
+
+        let other = plex_client.for_server(server).await.unwrap();
+
+        assert_eq!(other.host, "http://4k.test:32400");
+        assert_eq!(other.x_plex_token, "token");
+        assert!(other.headers.get("X-Plex-Token").is_none());
+    }
+
+    #[tokio::test]
+    async fn shared_servers_get_the_token_of_the_owner() {
+        let plex_client = client(true);
+        let server = &plex_client.config.servers[0];
+
+        let other = plex_client.for_server(server).await.unwrap();
+
+        assert_eq!(other.x_plex_token, "owner");
+        assert_eq!(other.headers["X-Plex-Token"], "owner");
+    }
+
+    #[tokio::test]
+    async fn shared_servers_need_a_token_that_host_accepts() {
+        let mut plex_client = client(true);
+        plex_client.x_plex_token = "stranger".to_string();
+        let server = &plex_client.config.servers[0];
+
+        let result = plex_client.for_server(server).await;
+
+        assert!(matches!(result, Err(PlexError::Unauthorized)));
     }
 }
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use salvo::http::header::{HeaderValue, CONTENT_TYPE};
+use salvo::http::{ParseError, StatusCode};
+use salvo::{async_trait, Depot, Request, Response, Writer};
+use serde_json::json;
+
+use crate::models::ContentType;
+use crate::utils::get_content_type_from_headers;
+
+/// Why a request to Plex failed. Handlers return it to answer clients with a
+/// matching status code and an error body in the format Plex uses.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum PlexError {
+    #[error("Missing or invalid Plex token")]
+    Unauthorized,
+    #[error("{0} was not found on Plex")]
+    NotFound(String),
+    #[error("Plex did not respond in time")]
+    Timeout,
+    #[error("Failed to decode the Plex response at `{path}`: {message}")]
+    Decode { path: String, message: String },
+    #[error("Plex metadata provider is unavailable: {0}")]
+    ProviderUnavailable(String),
+    #[error("Plex request failed: {0}")]
+    Upstream(String),
+    #[error("Invalid request: {0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl PlexError {
+    /// Passes through successful responses and turns the others into the
+    /// matching error.
+    pub fn check(res: reqwest::Response) -> Result<reqwest::Response, Self> {
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+
+        Err(match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized,
+            StatusCode::NOT_FOUND => Self::NotFound(res.url().path().to_owned()),
+            StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => Self::Timeout,
+            status => Self::Upstream(format!("{} returned {}", res.url().path(), status)),
+        })
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::Decode { .. } | Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::ProviderUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<reqwest::Error> for PlexError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout
+        } else {
+            // The URL holds the token, which shouldn't end up in responses.
+            Self::Upstream(e.without_url().to_string())
+        }
+    }
+}
+
+impl From<reqwest_middleware::Error> for PlexError {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        match e {
+            reqwest_middleware::Error::Reqwest(e) => e.into(),
+            reqwest_middleware::Error::Middleware(e) => Self::Upstream(e.to_string()),
+        }
+    }
+}
+
+impl From<ParseError> for PlexError {
+    fn from(e: ParseError) -> Self {
+        Self::BadRequest(e.to_string())
+    }
+}
+
+impl From<serde_urlencoded::ser::Error> for PlexError {
+    fn from(e: serde_urlencoded::ser::Error) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+// Errors that went through the cache, which keeps them as `anyhow::Error`.
+impl From<&anyhow::Error> for PlexError {
+    fn from(e: &anyhow::Error) -> Self {
+        e.downcast_ref::<PlexError>()
+            .cloned()
+            .unwrap_or_else(|| Self::Internal(format!("{:#}", e)))
+    }
+}
+
+impl From<Arc<anyhow::Error>> for PlexError {
+    fn from(e: Arc<anyhow::Error>) -> Self {
+        Self::from(e.as_ref())
+    }
+}
+
+#[async_trait]
+impl Writer for PlexError {
+    async fn write(mut self, req: &mut Request, _depot: &mut Depot, res: &mut Response) {
+        let status = self.status_code();
+        let message = self.to_string();
+
+        // Plex answers with a list of errors, in the format that was asked for.
+        let (content_type, body) = match get_content_type_from_headers(req.headers()) {
+            ContentType::Json => (
+                "application/json; charset=utf-8",
+                json!({
+                    "errors": [{
+                        "code": status.as_u16(),
+                        "message": message,
+                        "status": status.as_u16(),
+                    }]
+                })
+                .to_string(),
+            ),
+            ContentType::Xml => (
+                "text/xml; charset=utf-8",
+                format!(
+                    concat!(
+                        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                        "<Errors><Error code=\"{0}\" message=\"{1}\" status=\"{0}\"/></Errors>",
+                    ),
+                    status.as_u16(),
+                    escape_xml(&message),
+                ),
+            ),
+        };
+
+        res.status_code(status);
+        res.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        res.write_body(body).ok();
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
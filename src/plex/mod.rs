@@ -1,3 +1,10 @@
 pub mod client;
+pub mod error;
 pub mod models;
+pub mod notifications;
+pub mod servers;
 pub mod traits;
+pub mod upstream;
+
+#[cfg(test)]
+pub(crate) mod testing;
@@ -94,6 +94,21 @@ pub struct PlexContext {
     pub url: Option<String>,
 }
 
+impl PlexContext {
+    /// The context without the token and the ids of the sessions, for
+    /// handing to code that isn't part of Replex, like plugins.
+    pub fn without_secrets(&self) -> Self {
+        Self {
+            token: None,
+            session_id: None,
+            session_identifier: None,
+            playback_session_id: None,
+            playback_id: None,
+            ..self.clone()
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Encode, Decode)]
 pub struct PlexContextProduct {
     #[serde(default, rename(deserialize = "x-plex-product"))]
@@ -0,0 +1,468 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::cache::{CacheManager, CACHE_MANAGER};
+use crate::config::Config;
+use crate::deserializers::option_number_from_string;
+use crate::http_client;
+use crate::models::MediaContainer;
+use crate::plex::error::PlexError;
+use crate::plex::upstream;
+
+// Invalidations are collected and applied together, so a library scan that
+// touches hundreds of items doesn't empty the cache hundreds of times.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+
+// How often `servers` are checked for servers that aren't listened to yet.
+const SERVERS_INTERVAL: Duration = Duration::from_secs(30);
+
+// Timeline states of an item that is done processing or was deleted.
+const STATE_PROCESSED: i64 = 5;
+const STATE_DELETED: i64 = 9;
+
+// Timeline type of collections.
+const TYPE_COLLECTION: i64 = 18;
+
+#[derive(Debug, Deserialize)]
+struct Notification {
+    #[serde(rename = "NotificationContainer")]
+    container: NotificationContainer,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationContainer {
+    #[serde(rename = "type")]
+    r#type: String,
+    #[serde(default, rename = "TimelineEntry")]
+    timeline: Vec<TimelineEntry>,
+    #[serde(default, rename = "PlaySessionStateNotification")]
+    playing: Vec<PlaySessionState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TimelineEntry {
+    #[serde(
+        default,
+        rename = "sectionID",
+        deserialize_with = "option_number_from_string"
+    )]
+    section_id: Option<i64>,
+    #[serde(
+        default,
+        rename = "itemID",
+        deserialize_with = "option_number_from_string"
+    )]
+    item_id: Option<i64>,
+    #[serde(default, rename = "type")]
+    r#type: i64,
+    #[serde(default)]
+    state: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaySessionState {
+    state: String,
+    #[serde(default, rename = "clientIdentifier")]
+    client_identifier: Option<String>,
+}
+
+/// Cache entries made outdated by a notification: the keys that start with
+/// `prefix`, their family and id, see the traits in `plex::traits`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Invalidation {
+    prefix: String,
+    // Only the per-user entries of the user of the client with this
+    // identifier, on any of their clients.
+    client: Option<String>,
+}
+
+impl Invalidation {
+    fn prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            client: None,
+        }
+    }
+
+    // Whether `key` is outdated, when the notification came from `server`,
+    // or `host` for `None`. Keys of the other servers end with `@` and the
+    // name of the server, see `PlexClient::generate_cache_key`. `users` has
+    // the tokens of the users of each client.
+    fn matches(&self, key: &str, server: Option<&str>, users: &Users) -> bool {
+        let key = match server {
+            Some(server) => match key.strip_suffix(server).and_then(|k| k.strip_suffix('@')) {
+                Some(key) => key,
+                None => return false,
+            },
+            None if key.contains('@') => return false,
+            None => key,
+        };
+
+        if !key.starts_with(&self.prefix) {
+            return false;
+        }
+
+        let Some(client) = &self.client else {
+            return true;
+        };
+        match users.get(client).filter(|tokens| !tokens.is_empty()) {
+            Some(tokens) => tokens.iter().any(|token| key.contains(&format!(":{}-", token))),
+            // Clients that nothing was cached for, like ones that don't go
+            // through Replex, could belong to anyone.
+            None => true,
+        }
+    }
+}
+
+// Tokens of the users of clients, by client identifier.
+type Users = HashMap<String, HashSet<String>>;
+
+/// What the notifications since the last flush made outdated.
+#[derive(Debug, Default)]
+struct Pending {
+    invalidations: HashSet<Invalidation>,
+    // Changed items, whose parents are looked up when flushing.
+    items: HashSet<i64>,
+    // Libraries of changed items, whose collections are looked up when
+    // flushing, as the items can show up in any of them.
+    sections: HashSet<i64>,
+}
+
+impl Pending {
+    fn is_empty(&self) -> bool {
+        self.invalidations.is_empty() && self.items.is_empty() && self.sections.is_empty()
+    }
+
+    // Adds what `container` makes outdated.
+    fn add(&mut self, container: &NotificationContainer) {
+        match container.r#type.as_str() {
+            // Watch state changes once playback stops, which affects the
+            // watched and in progress items of that user in every collection
+            // and season, on every device.
+            "playing" => {
+                for state in &container.playing {
+                    if state.state != "stopped" {
+                        continue;
+                    }
+                    let Some(client) = &state.client_identifier else {
+                        continue;
+                    };
+
+                    for family in ["collection_children:", "metadata_children:"] {
+                        self.invalidations.insert(Invalidation {
+                            prefix: family.to_owned(),
+                            client: Some(client.clone()),
+                        });
+                    }
+                }
+            }
+            "timeline" => {
+                for entry in &container.timeline {
+                    if entry.state != STATE_PROCESSED && entry.state != STATE_DELETED {
+                        continue;
+                    }
+
+                    if entry.r#type == TYPE_COLLECTION {
+                        if let Some(id) = entry.item_id {
+                            self.invalidations.insert(Invalidation::prefix(format!("collection:{}:", id)));
+                            self.invalidations.insert(Invalidation::prefix(format!("collection_children:{},", id)));
+                        }
+                        if let Some(section_id) = entry.section_id {
+                            self.invalidations.insert(Invalidation::prefix(format!("section_collections:{}:", section_id)));
+                        }
+                        continue;
+                    }
+
+                    // Added, changed or deleted items change their own
+                    // children, those of their parents and the collections
+                    // of their library.
+                    if let Some(id) = entry.item_id {
+                        self.invalidations.insert(Invalidation::prefix(format!("metadata_children:{}:", id)));
+                        // Deleted items can't be looked up anymore.
+                        if entry.state == STATE_PROCESSED {
+                            self.items.insert(id);
+                        }
+                    }
+                    if let Some(section_id) = entry.section_id {
+                        self.sections.insert(section_id);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Spawns background tasks that listen to the notifications of the Plex
+/// servers and remove the cache entries they make outdated. `host` is
+/// listened to with `plex_token`, and each of `servers` with its `token`.
+pub fn listen() {
+    tokio::spawn(listen_to_servers());
+}
+
+async fn listen_to_servers() {
+    let mut listening = HashSet::new();
+
+    loop {
+        // The global config on purpose: the notifications of a server are
+        // the same for every client, and listened to once with its token.
+        let config = Config::load();
+        let names = std::iter::once(None)
+            .chain(config.servers.iter().map(|server| Some(server.name.clone())));
+
+        for name in names {
+            if listening.insert(name.clone()) {
+                tokio::spawn(run(name));
+            }
+        }
+
+        tokio::time::sleep(SERVERS_INTERVAL).await;
+    }
+}
+
+// Listens to `host`, or to the server called `server`, for as long as it has
+// a token.
+async fn run(server: Option<String>) {
+    let mut delay = MIN_RECONNECT_DELAY;
+
+    loop {
+        // The global config on purpose, see `listen_to_servers`.
+        let config = Config::load();
+        let target = match &server {
+            None => config
+                .plex_token
+                .clone()
+                .map(|token| (upstream::active_host(&config), token)),
+            Some(name) => config
+                .server(name)
+                .and_then(|s| Some((s.host.clone(), s.token.clone()?))),
+        };
+
+        if let Some((host, token)) = target {
+            match connect(&host, &token, server.as_deref()).await {
+                // Only back off when the connection keeps failing.
+                Ok(()) => delay = MIN_RECONNECT_DELAY,
+                Err(e) => {
+                    tracing::warn!(
+                        server = ?server,
+                        error = %e,
+                        "Lost connection to Plex notifications, retrying in {:?}",
+                        delay
+                    );
+                }
+            }
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+async fn connect(host: &str, token: &str, server: Option<&str>) -> anyhow::Result<()> {
+    let host = host.trim_end_matches('/');
+    // http:// becomes ws:// and https:// becomes wss://
+    let url = format!(
+        "{}/:/websockets/notifications?X-Plex-Token={}",
+        host.replacen("http", "ws", 1),
+        token
+    );
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+    tracing::info!(server = ?server, "Listening to Plex notifications");
+
+    let mut pending = Pending::default();
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = socket.next() => {
+                let message = match message {
+                    Some(message) => message?,
+                    None => break,
+                };
+
+                if let Message::Text(text) = message {
+                    match serde_json::from_str::<Notification>(&text) {
+                        Ok(notification) => pending.add(&notification.container),
+                        Err(e) => {
+                            tracing::trace!(error = %e, "Skipping notification");
+                        }
+                    }
+                }
+            }
+            _ = flush.tick() => {
+                invalidate(std::mem::take(&mut pending), host, token, server).await;
+            }
+        }
+    }
+
+    invalidate(pending, host, token, server).await;
+    Ok(())
+}
+
+async fn invalidate(mut pending: Pending, host: &str, token: &str, server: Option<&str>) {
+    if pending.is_empty() {
+        return;
+    }
+
+    for id in std::mem::take(&mut pending.items) {
+        match fetch(host, token, &format!("/library/metadata/{}", id)).await {
+            Ok(container) => {
+                let parents = container.iter_children().flat_map(|item| {
+                    [&item.parent_rating_key, &item.grandparent_rating_key]
+                });
+                for parent in parents.flatten() {
+                    pending
+                        .invalidations
+                        .insert(Invalidation::prefix(format!("metadata_children:{}:", parent)));
+                }
+            }
+            Err(e) => {
+                tracing::debug!(item = id, error = %e, "Failed to look up changed item");
+            }
+        }
+    }
+
+    for id in std::mem::take(&mut pending.sections) {
+        match fetch(host, token, &format!("/library/sections/{}/collections", id)).await {
+            Ok(container) => {
+                let ids = container.iter_children().filter_map(|c| c.rating_key.as_ref());
+                for id in ids {
+                    pending
+                        .invalidations
+                        .insert(Invalidation::prefix(format!("collection_children:{},", id)));
+                }
+            }
+            Err(e) => {
+                tracing::warn!(section = id, error = %e, "Failed to look up collections of library");
+            }
+        }
+    }
+
+    tracing::debug!(server = ?server, invalidations = ?pending.invalidations, "Invalidating cache entries");
+
+    if let Err(e) = delete_outdated(&CACHE_MANAGER, pending.invalidations, server).await {
+        tracing::warn!(error = %e, "Failed to invalidate cache entries");
+    }
+}
+
+// Deletes the entries of `cache` that `invalidations` from `server` make
+// outdated. Notifications only name the client, whose user is known by the
+// token in the keys of its entries.
+async fn delete_outdated(
+    cache: &CacheManager,
+    invalidations: HashSet<Invalidation>,
+    server: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut users = Users::new();
+    for client in invalidations.iter().filter_map(|i| i.client.as_ref()) {
+        if !users.contains_key(client) {
+            users.insert(client.clone(), cache.tokens_of_client(client).await?);
+        }
+    }
+
+    let server = server.map(str::to_owned);
+    let filter = Arc::new(move |key: &str| {
+        invalidations
+            .iter()
+            .any(|invalidation| invalidation.matches(key, server.as_deref(), &users))
+    });
+
+    cache.delete_if(filter).await
+}
+
+async fn fetch(host: &str, token: &str, path: &str) -> Result<MediaContainer, PlexError> {
+    let res = http_client::client()
+        .get(format!("{}{}", host, path))
+        .header("X-Plex-Token", token)
+        .header("Accept", "application/json")
+        .send()
+        .await?;
+
+    MediaContainer::from_reqwest_response(PlexError::check(res)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::MemoryBackend;
+
+    use super::*;
+
+    fn cache() -> CacheManager {
+        let config = serde_json::from_str("{}").unwrap();
+        CacheManager::new(Box::new(MemoryBackend::new(1024 * 1024)), &config)
+    }
+
+    fn stopped(client: &str) -> Pending {
+        let notification: Notification = serde_json::from_value(serde_json::json!({
+            "NotificationContainer": {
+                "type": "playing",
+                "PlaySessionStateNotification": [
+                    { "state": "stopped", "clientIdentifier": client },
+                ],
+            },
+        }))
+        .unwrap();
+
+        let mut pending = Pending::default();
+        pending.add(&notification.container);
+        pending
+    }
+
+    #[tokio::test]
+    async fn stopped_playback_outdates_every_client_of_the_user() {
+        let cache = cache();
+        let keys = [
+            "collection_children:1,0,10:alice-phone",
+            "metadata_children:5:alice-tv",
+            "metadata_children:5:alice-tv@4k",
+            "metadata_children:5:bob-laptop",
+            "collection:1:server",
+        ];
+        for key in keys {
+            cache.insert(key, &1).await.unwrap();
+        }
+
+        delete_outdated(&cache, stopped("phone").invalidations, None)
+            .await
+            .unwrap();
+
+        let mut kept = Vec::new();
+        for key in keys {
+            if cache.get::<i32>(key).await.unwrap().is_some() {
+                kept.push(key);
+            }
+        }
+        // Keys of the other servers go with notifications of those servers.
+        assert_eq!(
+            kept,
+            [
+                "metadata_children:5:alice-tv@4k",
+                "metadata_children:5:bob-laptop",
+                "collection:1:server"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stopped_playback_of_unknown_clients_outdates_every_user() {
+        let cache = cache();
+        cache.insert("metadata_children:5:alice-tv", &1).await.unwrap();
+        cache.insert("metadata_children:5:bob-laptop", &1).await.unwrap();
+
+        delete_outdated(&cache, stopped("plexamp").invalidations, None)
+            .await
+            .unwrap();
+
+        assert_eq!(cache.get::<i32>("metadata_children:5:alice-tv").await.unwrap(), None);
+        assert_eq!(cache.get::<i32>("metadata_children:5:bob-laptop").await.unwrap(), None);
+    }
+}
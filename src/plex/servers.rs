@@ -0,0 +1,200 @@
+use futures::future::join_all;
+use url::Url;
+
+use crate::config::{Config, PipelineStep, Server};
+use crate::models::MediaContainer;
+use crate::transforms::registry::{self, StepContext};
+use crate::transforms::{
+    merge_server_hubs, ReorderHubsTransform, ScriptHook, Transform, TransformBuilder,
+};
+
+use super::client::PlexClient;
+use super::error::PlexError;
+use super::models::PlexContext;
+
+/// Routes whose hubs are merged with the ones of the other servers.
+#[derive(Debug, Clone, Copy)]
+pub enum HubRoute {
+    PromotedHubs,
+    SectionHubs,
+}
+
+impl HubRoute {
+    fn steps(self, config: &Config) -> &[PipelineStep] {
+        match self {
+            HubRoute::PromotedHubs => &config.pipelines.promoted_hubs,
+            HubRoute::SectionHubs => &config.pipelines.section_hubs,
+        }
+    }
+
+    fn context(self) -> StepContext {
+        let hook = match self {
+            HubRoute::PromotedHubs => ScriptHook::PromotedHubs,
+            HubRoute::SectionHubs => ScriptHook::SectionHubs,
+        };
+
+        StepContext {
+            hook: Some(hook),
+            ..StepContext::default()
+        }
+    }
+
+    /// Runs the steps of the pipeline of the route on `container`. The hubs
+    /// of the other servers are merged in at the `merge_servers` step, with
+    /// `request` giving the path and query parameters for each server, or
+    /// `None` when nothing of that server goes with the request.
+    pub async fn transform<F>(
+        self,
+        container: &mut MediaContainer,
+        url: &Url,
+        params: &PlexContext,
+        plex_client: &PlexClient,
+        request: F,
+    ) where
+        F: Fn(&Server) -> Option<(String, Vec<(&'static str, String)>)>,
+    {
+        let split = registry::split_at_merge(self.steps(&plex_client.config));
+
+        self.apply(split.before, container, params, plex_client).await;
+
+        // Requests for one of the other servers only show that server.
+        if plex_client.server.is_none() && split.merge {
+            self.merge_other_servers(container, url, params, plex_client, request)
+                .await;
+        }
+
+        // The steps after `merge_servers` also apply to the hubs of the other
+        // servers.
+        self.apply(split.after, container, params, plex_client).await;
+    }
+
+    async fn apply(
+        self,
+        steps: &[PipelineStep],
+        container: &mut MediaContainer,
+        params: &PlexContext,
+        plex_client: &PlexClient,
+    ) {
+        TransformBuilder::new(plex_client, params)
+            .with_pipeline(steps, &self.context())
+            .apply_to(container)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!(error = %e, "Failed to transform media container");
+            });
+    }
+
+    // Adds the hubs of the other servers, after running the steps before
+    // `merge_servers` on them.
+    async fn merge_other_servers<F>(
+        self,
+        container: &mut MediaContainer,
+        url: &Url,
+        params: &PlexContext,
+        plex_client: &PlexClient,
+        request: F,
+    ) where
+        F: Fn(&Server) -> Option<(String, Vec<(&'static str, String)>)>,
+    {
+        let futures = plex_client.config.servers.iter().filter_map(|server| {
+            let (path, params_for_server) = request(server)?;
+
+            Some(async move {
+                let (client, mut other) =
+                    fetch(plex_client, server, url, &path, &params_for_server)
+                        .await
+                        .inspect_err(|e| {
+                            tracing::error!(server = %server.name, error = %e, "Failed to fetch hubs");
+                        })
+                        .ok()?;
+
+                let steps = registry::split_at_merge(self.steps(&client.config)).before;
+                self.apply(steps, &mut other, params, &client).await;
+                Some((server, other))
+            })
+        });
+
+        let others: Vec<_> = join_all(futures).await.into_iter().flatten().collect();
+        if others.is_empty() {
+            return;
+        }
+
+        for (server, mut other) in others {
+            merge_server_hubs(container, other.take_children(), &server.name);
+        }
+
+        // Hubs of the other servers go after the ones of `host`, unless they
+        // have priority.
+        ReorderHubsTransform
+            .transform_mediacontainer(container, plex_client, params)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!(error = %e, "Failed to reorder hubs");
+            });
+    }
+}
+
+/// Fetches `path` from `server` with the query of `url`, with `params` in
+/// place of the query parameters of the same name. Returns the response
+/// along with the client that was used, so it can be transformed on behalf
+/// of `server`.
+pub async fn fetch(
+    plex_client: &PlexClient,
+    server: &Server,
+    url: &Url,
+    path: &str,
+    params: &[(&str, String)],
+) -> Result<(PlexClient, MediaContainer), PlexError> {
+    let client = plex_client.for_server(server).await?;
+
+    let mut params = params.to_vec();
+    if let Some(token) = server.shared_token() {
+        params.push(("X-Plex-Token", token.clone()));
+    }
+
+    let mut url = url.clone();
+    url.set_path(path);
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !params.iter().any(|(name, _)| key == name))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .extend_pairs(params.iter().map(|(key, value)| (*key, value.as_str())));
+
+    let path_and_query = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_owned(),
+    };
+
+    let res = PlexError::check(client.get(&path_and_query).await?)?;
+    let container = MediaContainer::from_reqwest_response(res).await?;
+
+    Ok((client, container))
+}
+
+/// Splits a list of collection ids, as used in the keys of merged hubs, into
+/// the ids on `host` and the ids on each of the other servers, which are
+/// written as `id@server`. Groups keep the order of their first id.
+pub fn group_collection_ids(ids: &str) -> Vec<(Option<String>, Vec<i64>)> {
+    let mut groups: Vec<(Option<String>, Vec<i64>)> = Vec::new();
+
+    for id in ids.split(',') {
+        let (id, server) = match id.split_once('@') {
+            Some((id, server)) => (id, Some(server.to_owned())),
+            None => (id, None),
+        };
+        let Ok(id) = id.parse::<i64>() else {
+            continue;
+        };
+
+        match groups.iter_mut().find(|(s, _)| *s == server) {
+            Some((_, ids)) => ids.push(id),
+            None => groups.push((server, vec![id])),
+        }
+    }
+
+    groups
+}
@@ -0,0 +1,82 @@
+//! Clients for tests, whose requests are answered without Plex.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use http::HeaderMap;
+use reqwest::{Request, Response};
+use reqwest_middleware::{ClientBuilder, Middleware, Next};
+use serde_json::{json, Value};
+use task_local_extensions::Extensions;
+
+use crate::cache::{CacheManager, MemoryBackend};
+use crate::config::Config;
+use crate::models::Platform;
+
+use super::client::PlexClient;
+
+pub(crate) const HOST: &str = "http://plex.test:32400";
+
+/// A config with the settings that have no default, and `settings` on top.
+pub(crate) fn config(settings: Value) -> Config {
+    let mut config = json!({
+        "host": HOST,
+        "better_on_deck": {},
+        "cache": {},
+        "exclude_watched": {},
+        "redirect_streams": {},
+    });
+    if let (Some(config), Value::Object(settings)) = (config.as_object_mut(), settings) {
+        config.extend(settings);
+    }
+    serde_json::from_value(config).unwrap()
+}
+
+/// A client for `config` whose requests are answered by `respond`.
+pub(crate) fn client<F>(config: Config, respond: F) -> PlexClient
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let cache = CacheManager::new(Box::new(MemoryBackend::new(1024 * 1024)), &config.cache);
+
+    PlexClient {
+        http_client: ClientBuilder::new(reqwest::Client::new())
+            .with(Respond(respond))
+            .build(),
+        headers: HeaderMap::new(),
+        host: HOST.to_string(),
+        server: None,
+        cache: Arc::new(cache),
+        config: Arc::new(config),
+        x_plex_platform: Platform::default(),
+        x_plex_client_identifier: Some("client".to_string()),
+        x_plex_token: "token".to_string(),
+    }
+}
+
+/// A JSON response with `status`.
+pub(crate) fn response(status: u16, body: Value) -> Response {
+    http::Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(body.to_string())
+        .unwrap()
+        .into()
+}
+
+struct Respond<F>(F);
+
+#[async_trait]
+impl<F> Middleware for Respond<F>
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    async fn handle(
+        &self,
+        req: Request,
+        _extensions: &mut Extensions,
+        _next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        Ok((self.0)(&req))
+    }
+}
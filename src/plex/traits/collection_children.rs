@@ -1,9 +1,10 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use std::collections::HashMap;
 
+use crate::cache::CacheScope;
 use crate::models::MediaContainer;
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 
 #[async_trait]
 pub trait CollectionChildren {
@@ -12,7 +13,7 @@ pub trait CollectionChildren {
         id: i64,
         offset: Option<i32>,
         limit: Option<i32>,
-    ) -> anyhow::Result<MediaContainer>;
+    ) -> Result<MediaContainer, PlexError>;
 }
 
 #[async_trait]
@@ -22,30 +23,26 @@ impl CollectionChildren for PlexClient {
         id: i64,
         offset: Option<i32>,
         limit: Option<i32>,
-    ) -> Result<MediaContainer> {
+    ) -> Result<MediaContainer, PlexError> {
         let cache_name = format!(
             "collection_children:{},offset:{:?},limit:{:?}",
             id, offset, limit
         );
-        let cache_key = self.generate_cache_key(cache_name);
-        let path = build_path(id, offset, limit);
+        let cache_key =
+            self.generate_cache_key(cache_name, CacheScope::User);
+        let path = build_path(id, offset, limit)?;
 
-        Self::cache_or_fetch(&cache_key, || async {
-            let res = self.get(&path).await.map_err(|e| {
-                anyhow::anyhow!("Failed to get collection children: {}", e)
-            })?;
+        let client = self.clone();
 
-            MediaContainer::from_reqwest_response(res)
-                .await
-                .map_err(|e| {
-                    anyhow::anyhow!("Error deserializing response: {}", e)
-                })
+        self.cache_or_fetch(&cache_key, move || async move {
+            let res = PlexError::check(client.get(&path).await?)?;
+            MediaContainer::from_reqwest_response(res).await
         })
         .await
     }
 }
 
-fn build_path(id: i64, offset: Option<i32>, limit: Option<i32>) -> String {
+fn build_path(id: i64, offset: Option<i32>, limit: Option<i32>) -> Result<String, PlexError> {
     let mut params: HashMap<&str, String> = HashMap::new();
 
     // Always include `includeGuids`
@@ -60,7 +57,7 @@ fn build_path(id: i64, offset: Option<i32>, limit: Option<i32>) -> String {
         params.insert("X-Plex-Container-Size", limit_val.to_string());
     }
 
-    let query_string = serde_urlencoded::to_string(params).unwrap();
+    let query_string = serde_urlencoded::to_string(params)?;
 
-    format!("/library/collections/{}/children?{}", id, query_string)
+    Ok(format!("/library/collections/{}/children?{}", id, query_string))
 }
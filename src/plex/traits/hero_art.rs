@@ -1,20 +1,25 @@
-use anyhow::Result;
 use async_trait::async_trait;
 
+use crate::cache::CacheScope;
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 
 #[async_trait]
 pub trait HeroArt {
-    async fn get(&self, guid: &str) -> anyhow::Result<Option<String>>;
+    async fn get(&self, guid: &str) -> Result<Option<String>, PlexError>;
 }
 
 #[async_trait]
 impl HeroArt for PlexClient {
-    async fn get(&self, guid: &str) -> Result<Option<String>> {
+    async fn get(&self, guid: &str) -> Result<Option<String>, PlexError> {
         let cache_name = format!("hero_art:{}", guid);
-        let cache_key = self.generate_cache_key(cache_name);
+        let cache_key =
+            self.generate_cache_key(cache_name, CacheScope::Global);
 
-        Self::cache_or_fetch(&cache_key, || async {
+        let client = self.clone();
+        let guid = guid.to_owned();
+
+        self.cache_or_fetch(&cache_key, move || async move {
             if guid.starts_with("local://") {
                 tracing::debug!("Skipping local item: {}", guid);
                 return Ok(None);
@@ -25,9 +30,10 @@ impl HeroArt for PlexClient {
                 patterns.iter().fold(guid.to_owned(), |acc, pat| {
                     acc.replace(&format!("plex://{}", pat), "")
                 });
-            let mut container = self.get_provider_data(&cleaned_guid).await?;
+            let container =
+                client.get_provider_data(&cleaned_guid).await?;
 
-            let cover_art = container.children().iter().find_map(|meta| {
+            let cover_art = container.iter_children().find_map(|meta| {
                 meta.images
                     .iter()
                     .find(|image| image.r#type == "coverArt")
@@ -1,31 +1,28 @@
-use anyhow::Result;
 use async_trait::async_trait;
 
+use crate::cache::CacheScope;
 use crate::models::MediaContainer;
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 
 #[async_trait]
 pub trait SectionCollections {
-    async fn get(&self, id: i64) -> anyhow::Result<MediaContainer>;
+    async fn get(&self, id: i64) -> Result<MediaContainer, PlexError>;
 }
 
 #[async_trait]
 impl SectionCollections for PlexClient {
-    async fn get(&self, id: i64) -> Result<MediaContainer> {
+    async fn get(&self, id: i64) -> Result<MediaContainer, PlexError> {
         let cache_name = format!("section_collections:{}", id);
-        let cache_key = self.generate_cache_key(cache_name);
+        let cache_key =
+            self.generate_cache_key(cache_name, CacheScope::User);
         let path = format!("/library/sections/{}/collections", id);
 
-        Self::cache_or_fetch(&cache_key, || async {
-            let res = self.get(&path).await.map_err(|e| {
-                anyhow::anyhow!("Failed to get section collections: {}", e)
-            })?;
+        let client = self.clone();
 
-            MediaContainer::from_reqwest_response(res)
-                .await
-                .map_err(|e| {
-                    anyhow::anyhow!("Error deserializing response: {}", e)
-                })
+        self.cache_or_fetch(&cache_key, move || async move {
+            let res = PlexError::check(client.get(&path).await?)?;
+            MediaContainer::from_reqwest_response(res).await
         })
         .await
     }
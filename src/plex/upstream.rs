@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use salvo::Request;
+
+use crate::config::{Config, Server};
+
+// How long a host gets to answer a health check.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Whether each host passed its last health check. Hosts that weren't checked
+// yet are assumed to be up.
+static HEALTH: Lazy<Mutex<HashMap<String, bool>>> = Lazy::new(Mutex::default);
+
+/// Returns the Plex server that requests should go to: `host` while it is
+/// up, otherwise the first of `standby_hosts` that is. Falls back to `host`
+/// when every server is down.
+pub fn active_host(config: &Config) -> String {
+    let health = HEALTH.lock().unwrap();
+
+    hosts(config)
+        .find(|host| health.get(*host).copied().unwrap_or(true))
+        .unwrap_or_else(|| config.host.trim_end_matches('/'))
+        .to_owned()
+}
+
+/// Prefix of the paths that Replex routes to one of `servers`, followed by
+/// the name of the server.
+pub const SERVER_PREFIX: &str = "/replex/servers/";
+
+// Name of the server in `servers` that a request goes to, see `select`.
+#[derive(Clone)]
+struct SelectedServer(String);
+
+/// Makes `req` go to `server` instead of `host`, by removing the server
+/// prefix from its path if it has one. The request keeps the token of the
+/// client, the one of the server is never handed to clients' requests.
+pub fn select(req: &mut Request, server: &Server) {
+    let prefix = format!("{}{}", SERVER_PREFIX, server.name);
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_default();
+
+    if let Some(rest) = path_and_query.strip_prefix(&prefix) {
+        let rest = if rest.starts_with('/') { rest.to_owned() } else { format!("/{}", rest) };
+        if let Ok(uri) = rest.parse() {
+            req.set_uri(uri);
+        }
+    }
+
+    req.extensions_mut().insert(SelectedServer(server.name.clone()));
+}
+
+/// Returns the name of the server that `req` was sent to with `select`.
+pub fn selected_server(req: &Request) -> Option<String> {
+    req.extensions()
+        .get::<SelectedServer>()
+        .map(|server| server.0.clone())
+}
+
+/// Returns the Plex server that `req` should go to: the one it was sent to
+/// with `select`, otherwise `active_host`.
+pub fn host_for(req: &Request, config: &Config) -> String {
+    selected_server(req)
+        .and_then(|name| config.server(&name).map(|server| server.host.clone()))
+        .unwrap_or_else(|| active_host(config))
+}
+
+// `host` followed by `standby_hosts`, without trailing slashes.
+fn hosts(config: &Config) -> impl Iterator<Item = &str> {
+    std::iter::once(&config.host)
+        .chain(config.standby_hosts.iter().flatten())
+        .map(|host| host.trim_end_matches('/'))
+}
+
+/// Spawns a background task that checks every `health_check_interval`
+/// seconds whether the Plex servers answer on `/identity`. Does nothing
+/// while there are no `standby_hosts`.
+pub fn watch() {
+    tokio::spawn(run());
+}
+
+async fn run() {
+    let client = reqwest::Client::builder()
+        .timeout(CHECK_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    loop {
+        // The global config on purpose, as the hosts are checked for every
+        // request and not for the client of one.
+        let config = Config::load();
+
+        if config.standby_hosts.as_ref().is_some_and(|h| !h.is_empty()) {
+            let active = active_host(&config);
+
+            for host in hosts(&config) {
+                let up = check(&client, host).await;
+                let was_up = HEALTH.lock().unwrap().insert(host.to_owned(), up);
+
+                match (was_up.unwrap_or(true), up) {
+                    (true, false) => tracing::warn!(host = %host, "Plex host is down"),
+                    (false, true) => tracing::info!(host = %host, "Plex host is back up"),
+                    _ => {}
+                }
+            }
+
+            let now_active = active_host(&config);
+            if now_active != active {
+                tracing::warn!(from = %active, to = %now_active, "Switched Plex host");
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(config.health_check_interval)).await;
+    }
+}
+
+async fn check(client: &reqwest::Client, host: &str) -> bool {
+    match client.get(format!("{}/identity", host)).send().await {
+        Ok(res) => res.status().is_success(),
+        Err(e) => {
+            tracing::debug!(host = %host, error = %e, "Health check failed");
+            false
+        }
+    }
+}
@@ -0,0 +1,405 @@
+//! WebAssembly plugins that transform and filter responses like the built-in
+//! transforms, loaded from the `.wasm` files in `plugins.directory`.
+//!
+//! Plugins talk to Replex through version 1 of this ABI. A plugin exports:
+//!
+//! - `memory`, its linear memory.
+//! - `replex_abi_version() -> i32`, returning `1`.
+//! - `replex_alloc(len: i32) -> i32`, returning a buffer of `len` bytes that
+//!   Replex writes the input to.
+//!
+//! And any of these hooks, named after the methods of `Transform` and
+//! `Filter`. Each gets the input as `(ptr: i32, len: i32)`, a JSON object with
+//! the `route` (`promoted_hubs`, `section_hubs` or `collection_children`),
+//! the `context` of the request without the token of the user, and the
+//! `media_container` or `metadata`:
+//!
+//! - `replex_transform_mediacontainer(ptr, len) -> i64`
+//! - `replex_transform_metadata(ptr, len) -> i64`
+//! - `replex_filter_mediacontainer(ptr, len) -> i32`
+//! - `replex_filter_metadata(ptr, len) -> i32`
+//!
+//! Transforms return the changed `media_container` or `metadata` as JSON in
+//! the plugin's memory, as `ptr << 32 | len`, or `0` to leave it as it is.
+//! Filters return `0` to remove what they got, anything else to keep it.
+//!
+//! Plugins are loaded at startup and picked up again in the background when
+//! the config or a plugin file changes, see `refresh`.
+//!
+//! The only function a plugin can import is `replex.log(ptr: i32, len: i32)`,
+//! which logs a UTF-8 message. Every call gets a fresh instance, which is
+//! stopped when it runs out of `plugins.fuel` or grows its memory past
+//! `plugins.max_memory` megabytes.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Context, Result};
+use arc_swap::ArcSwap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use wasmi::{
+    Caller, Engine, Instance, Linker, Module, Store, StoreLimits,
+    StoreLimitsBuilder,
+};
+
+use crate::config::{Config, Plugins};
+
+/// Version of the ABI that plugins have to implement.
+pub const ABI_VERSION: i32 = 1;
+
+pub const TRANSFORM_MEDIACONTAINER: &str = "replex_transform_mediacontainer";
+pub const TRANSFORM_METADATA: &str = "replex_transform_metadata";
+pub const FILTER_MEDIACONTAINER: &str = "replex_filter_mediacontainer";
+pub const FILTER_METADATA: &str = "replex_filter_metadata";
+
+const HOOKS: [&str; 4] = [
+    TRANSFORM_MEDIACONTAINER,
+    TRANSFORM_METADATA,
+    FILTER_MEDIACONTAINER,
+    FILTER_METADATA,
+];
+
+static ENGINE: Lazy<Engine> = Lazy::new(|| {
+    let mut config = wasmi::Config::default();
+    config.consume_fuel(true);
+    Engine::new(&config)
+});
+
+// Plugins by path.
+static PLUGINS: Lazy<Mutex<HashMap<PathBuf, Loaded>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// The plugins of every plugin directory, as of the last `refresh`.
+static DIRECTORIES: Lazy<ArcSwap<HashMap<String, Vec<Arc<Plugin>>>>> =
+    Lazy::new(ArcSwap::default);
+
+// A plugin file as it was last loaded. Files that failed to load are kept
+// too, so they are only reported once until they change.
+struct Loaded {
+    modified: SystemTime,
+    plugin: Option<Arc<Plugin>>,
+}
+
+/// A compiled plugin.
+#[derive(Debug)]
+pub struct Plugin {
+    pub name: String,
+    module: Module,
+}
+
+/// Returns the plugins in `directory` as of the last `refresh`, ordered by
+/// file name. Doesn't touch the file system, so it can be called from
+/// requests.
+pub fn loaded(directory: &str) -> Vec<Arc<Plugin>> {
+    DIRECTORIES
+        .load()
+        .get(directory)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Loads the plugins of the plugin directories of `config` and its profiles,
+/// for `loaded`. Plugins are compiled once and again whenever their file
+/// changes, and plugins that fail to load are skipped. Blocks, so it runs at
+/// startup and in the background whenever the config or a plugin changes.
+pub fn refresh(config: &Config) {
+    let mut directories = HashMap::new();
+    for directory in config.plugin_directories() {
+        let plugins = load(directory).unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to load plugins");
+            Vec::new()
+        });
+        directories.insert(directory.to_owned(), plugins);
+    }
+
+    let changed = {
+        let current = DIRECTORIES.load();
+        current.len() != directories.len()
+            || directories.iter().any(|(directory, plugins)| {
+                current.get(directory).is_none_or(|loaded| {
+                    loaded.len() != plugins.len()
+                        || loaded.iter().zip(plugins).any(|(a, b)| !Arc::ptr_eq(a, b))
+                })
+            })
+    };
+    if changed {
+        DIRECTORIES.store(Arc::new(directories));
+    }
+}
+
+// Loads the plugins in `directory`, ordered by file name.
+fn load(directory: &str) -> Result<Vec<Arc<Plugin>>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory)
+        .with_context(|| format!("Failed to read plugin directory {}", directory))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    paths.sort();
+
+    let mut plugins = PLUGINS.lock().unwrap();
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            match plugins.get(&path) {
+                Some(loaded) if loaded.modified == modified => loaded.plugin.clone(),
+                _ => {
+                    let plugin = compile(&path)
+                        .inspect_err(|e| {
+                            tracing::error!(plugin = %path.display(), error = %e, "Failed to load plugin");
+                        })
+                        .ok();
+                    plugins.insert(
+                        path,
+                        Loaded {
+                            modified,
+                            plugin: plugin.clone(),
+                        },
+                    );
+                    plugin
+                }
+            }
+        })
+        .collect())
+}
+
+fn compile(path: &Path) -> Result<Arc<Plugin>> {
+    let wasm = fs::read(path)?;
+    let module = Module::new(&ENGINE, &wasm).map_err(|e| anyhow!("{}", e))?;
+    let name = path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let plugin = Arc::new(Plugin { name, module });
+
+    // Checked once here, so calls can't fail on it later.
+    let version = plugin.instantiate(&Plugins::default())?.call_version()?;
+    if version != ABI_VERSION {
+        bail!(
+            "Plugin implements ABI version {}, expected {}",
+            version,
+            ABI_VERSION
+        );
+    }
+
+    tracing::info!(plugin = %plugin.name, hooks = ?plugin.hooks(), "Loaded plugin");
+    Ok(plugin)
+}
+
+impl Plugin {
+    /// Whether the plugin exports `hook`.
+    pub fn has_hook(&self, hook: &str) -> bool {
+        self.module.get_export(hook).is_some()
+    }
+
+    pub fn hooks(&self) -> Vec<&'static str> {
+        HOOKS.into_iter().filter(|hook| self.has_hook(hook)).collect()
+    }
+
+    /// Calls a transform `hook` with `input` as JSON. Returns the JSON that
+    /// the plugin returned, or `None` when it changed nothing.
+    pub fn transform(
+        &self,
+        hook: &str,
+        input: &impl Serialize,
+        limits: &Plugins,
+    ) -> Result<Option<Vec<u8>>> {
+        let input = serde_json::to_vec(input)?;
+        let mut call = self.instantiate(limits)?;
+        let (ptr, len) = call.write_input(&input)?;
+
+        let output = call
+            .instance
+            .get_typed_func::<(i32, i32), i64>(&call.store, hook)
+            .and_then(|f| f.call(&mut call.store, (ptr, len)))
+            .map_err(|e| anyhow!("{} failed: {}", hook, e))?;
+        if output == 0 {
+            return Ok(None);
+        }
+
+        let (ptr, len) = ((output >> 32) as u32, output as u32);
+        call.read(ptr, len).map(Some)
+    }
+
+    /// Calls a filter `hook` with `input` as JSON. Returns whether to keep
+    /// what was filtered.
+    pub fn filter(
+        &self,
+        hook: &str,
+        input: &impl Serialize,
+        limits: &Plugins,
+    ) -> Result<bool> {
+        let input = serde_json::to_vec(input)?;
+        let mut call = self.instantiate(limits)?;
+        let (ptr, len) = call.write_input(&input)?;
+
+        let keep = call
+            .instance
+            .get_typed_func::<(i32, i32), i32>(&call.store, hook)
+            .and_then(|f| f.call(&mut call.store, (ptr, len)))
+            .map_err(|e| anyhow!("{} failed: {}", hook, e))?;
+
+        Ok(keep != 0)
+    }
+
+    fn instantiate(&self, limits: &Plugins) -> Result<Call> {
+        let store_limits = StoreLimitsBuilder::new()
+            .memory_size(limits.max_memory.saturating_mul(1024 * 1024))
+            .build();
+        let mut store = Store::new(&ENGINE, store_limits);
+        store.limiter(|store_limits| store_limits);
+        store.set_fuel(limits.fuel).map_err(|e| anyhow!("{}", e))?;
+
+        let mut linker = <Linker<StoreLimits>>::new(&ENGINE);
+        let name = self.name.clone();
+        linker
+            .func_wrap(
+                "replex",
+                "log",
+                move |caller: Caller<'_, StoreLimits>, ptr: i32, len: i32| {
+                    if let Some(message) = read_string(&caller, ptr, len) {
+                        tracing::info!(plugin = %name, "{}", message);
+                    }
+                },
+            )
+            .map_err(|e| anyhow!("{}", e))?;
+
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| anyhow!("Failed to instantiate plugin {}: {}", self.name, e))?;
+
+        Ok(Call { store, instance })
+    }
+}
+
+#[cfg(test)]
+impl Plugin {
+    /// A plugin compiled from the text format of WebAssembly.
+    pub(crate) fn from_wat(name: &str, wat: &str) -> Self {
+        let wasm = wat::parse_str(wat).unwrap();
+        Self {
+            name: name.to_string(),
+            module: Module::new(&ENGINE, &wasm).unwrap(),
+        }
+    }
+}
+
+struct Call {
+    store: Store<StoreLimits>,
+    instance: Instance,
+}
+
+impl Call {
+    fn call_version(mut self) -> Result<i32> {
+        self.instance
+            .get_typed_func::<(), i32>(&self.store, "replex_abi_version")
+            .and_then(|f| f.call(&mut self.store, ()))
+            .map_err(|e| anyhow!("replex_abi_version failed: {}", e))
+    }
+
+    fn write_input(&mut self, input: &[u8]) -> Result<(i32, i32)> {
+        let len = i32::try_from(input.len())?;
+        let ptr = self
+            .instance
+            .get_typed_func::<i32, i32>(&self.store, "replex_alloc")
+            .and_then(|f| f.call(&mut self.store, len))
+            .map_err(|e| anyhow!("replex_alloc failed: {}", e))?;
+
+        self.memory()?
+            .write(&mut self.store, ptr as u32 as usize, input)
+            .map_err(|e| anyhow!("Failed to write input: {}", e))?;
+
+        Ok((ptr, len))
+    }
+
+    fn read(&self, ptr: u32, len: u32) -> Result<Vec<u8>> {
+        let memory = self.memory()?;
+        slice(memory.data(&self.store), ptr, len)
+            .map(<[u8]>::to_vec)
+            .with_context(|| {
+                format!("Output of {} bytes at {} is outside the plugin's memory", len, ptr)
+            })
+    }
+
+    fn memory(&self) -> Result<wasmi::Memory> {
+        self.instance
+            .get_memory(&self.store, "memory")
+            .context("Plugin doesn't export its memory")
+    }
+}
+
+fn read_string(caller: &Caller<'_, StoreLimits>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller.get_export("memory")?.into_memory()?;
+    let buffer = slice(memory.data(caller), ptr as u32, u32::try_from(len).ok()?)?;
+    String::from_utf8(buffer.to_vec()).ok()
+}
+
+// The `len` bytes at `ptr` of the memory of a plugin, or `None` when they
+// aren't all in it. Both come from the plugin, so they are checked before
+// anything is allocated for them.
+fn slice(data: &[u8], ptr: u32, len: u32) -> Option<&[u8]> {
+    let start = ptr as usize;
+    data.get(start..start.checked_add(len as usize)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // Returns the `{}` at 0 from `replex_transform_mediacontainer`, but claims
+    // 4 GiB from `replex_transform_metadata` and logs 2 GiB from
+    // `replex_filter_metadata`.
+    const HOSTILE: &str = r#"
+        (module
+          (import "replex" "log" (func $log (param i32 i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{}")
+          (func (export "replex_abi_version") (result i32) i32.const 1)
+          (func (export "replex_alloc") (param i32) (result i32) i32.const 1024)
+          (func (export "replex_transform_mediacontainer") (param i32 i32) (result i64)
+            i64.const 2)
+          (func (export "replex_transform_metadata") (param i32 i32) (result i64)
+            i64.const 0xffffffff)
+          (func (export "replex_filter_metadata") (param i32 i32) (result i32)
+            i32.const 0
+            i32.const 0x7fffffff
+            call $log
+            i32.const 1))
+    "#;
+
+    fn plugin() -> Plugin {
+        Plugin::from_wat("hostile", HOSTILE)
+    }
+
+    #[test]
+    fn transform_reads_output_in_memory() {
+        let output = plugin()
+            .transform(TRANSFORM_MEDIACONTAINER, &json!({}), &Plugins::default())
+            .unwrap();
+
+        assert_eq!(output.as_deref(), Some(&b"{}"[..]));
+    }
+
+    #[test]
+    fn transform_rejects_output_past_memory() {
+        let result = plugin().transform(TRANSFORM_METADATA, &json!({}), &Plugins::default());
+
+        assert!(result.unwrap_err().to_string().contains("outside the plugin's memory"));
+    }
+
+    #[test]
+    fn log_ignores_message_past_memory() {
+        let keep = plugin()
+            .filter(FILTER_METADATA, &json!({}), &Plugins::default())
+            .unwrap();
+
+        assert!(keep);
+    }
+}
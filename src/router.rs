@@ -5,7 +5,7 @@ use salvo::routing::PathFilter;
 
 use crate::middlewares::{DisableRelatedQuery, Logger, Timeout};
 
-use crate::routes::{common_routes, streaming, transcoding};
+use crate::routes::{admin, common_routes, servers, streaming, transcoding};
 
 pub fn main_router() -> Router {
     tracing::info!("Setting up main router");
@@ -22,6 +22,10 @@ pub fn main_router() -> Router {
         .hoop(Logger)
         .hoop(Timeout::default())
         .hoop(DisableRelatedQuery)
+        // Before the common routes, which would handle everything under
+        // `/replex` as a style.
+        .push(admin())
+        .push(servers())
         .push(common_routes())
         .push(streaming())
         .push(transcoding())
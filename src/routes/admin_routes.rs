@@ -0,0 +1,18 @@
+use salvo::prelude::*;
+
+use crate::handlers::*;
+use crate::middlewares::AdminAuth;
+
+pub const ADMIN_CACHE: &str = "/replex/admin/cache";
+pub const ADMIN_CACHE_WARM: &str = "/replex/admin/cache/warm";
+
+pub fn routes() -> Router {
+    Router::new()
+        .hoop(AdminAuth)
+        .push(
+            Router::with_path(ADMIN_CACHE)
+                .get(cache_stats)
+                .delete(purge_cache),
+        )
+        .push(Router::with_path(ADMIN_CACHE_WARM).post(warm_cache))
+}
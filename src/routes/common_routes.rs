@@ -17,16 +17,12 @@ pub const PING: &str = "/ping";
 pub const REST: &str = "<**rest>";
 
 pub fn routes() -> Router {
-    let config = Config::load();
     Router::new()
-        .then(|mut router| {
-            if config.better_on_deck.enabled {
-                router = router.push(
-                    Router::with_path(HUBS_CONTINUE_WATCHING).get(empty_media_container_handler),
-                );
-            }
-            router
-        })
+        .push(
+            Router::with_path(HUBS_CONTINUE_WATCHING)
+                .filter_fn(|req, _| Config::for_request(req).better_on_deck.enabled)
+                .get(empty_media_container_handler),
+        )
         .push(
             Router::new()
                 .path(LIBRARY_METADATA_RELATED)
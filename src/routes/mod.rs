@@ -1,7 +1,11 @@
+mod admin_routes;
 mod common_routes;
+mod server_routes;
 mod streaming_routes;
 mod transcoding_routes;
 
+pub use admin_routes::routes as admin;
 pub use common_routes::routes as common_routes;
+pub use server_routes::routes as servers;
 pub use streaming_routes::routes as streaming;
 pub use transcoding_routes::routes as transcoding;
@@ -0,0 +1,16 @@
+use salvo::prelude::*;
+
+use crate::middlewares::SelectServer;
+
+use super::{common_routes, streaming, transcoding};
+
+pub const REPLEX_SERVER: &str = "/replex/servers/<server>";
+
+/// The regular routes, for requests that go to one of the other `servers`.
+pub fn routes() -> Router {
+    Router::with_path(REPLEX_SERVER)
+        .hoop(SelectServer)
+        .push(common_routes())
+        .push(streaming())
+        .push(transcoding())
+}
@@ -1,17 +1,14 @@
 use crate::config::Config;
+use crate::plex::upstream;
 
 use salvo::http::header::CONTENT_TYPE;
 use salvo::prelude::*;
 
 pub fn routes() -> Router {
-    let config = Config::load();
-
-    if !config.redirect_streams.enabled {
-        // If redirect_streams is false, return an empty router
-        return Router::new();
-    }
-
+    // Only match while redirect_streams is enabled, otherwise requests fall
+    // through to the other routers.
     Router::new()
+        .filter_fn(|req, _| Config::for_request(req).redirect_streams.enabled)
         .push(
             Router::with_path(
                 "/video/<colon:colon>/transcode/universal/session/<**rest>",
@@ -28,12 +25,12 @@ pub fn routes() -> Router {
 
 #[handler]
 async fn redirect_stream(req: &mut Request, res: &mut Response) {
-    let config = Config::load();
+    let config = Config::for_request(req);
     let redirect_url = config
         .redirect_streams
         .host
         .clone()
-        .or_else(|| Some(config.host.clone()));
+        .unwrap_or_else(|| upstream::host_for(req, &config));
 
     let path_and_query = req
         .uri()
@@ -41,7 +38,11 @@ async fn redirect_stream(req: &mut Request, res: &mut Response) {
         .expect("Request must have a path and query")
         .as_str(); // Safely extract the string representation
 
-    let redirect_url = format!("{:?}{}", redirect_url, path_and_query);
+    let redirect_url = format!(
+        "{}{}",
+        redirect_url.trim_end_matches('/'),
+        path_and_query
+    );
 
     let mime = mime_guess::from_path(req.uri().path()).first_or_octet_stream();
     res.headers_mut()
@@ -4,11 +4,10 @@ use crate::handlers::{
     force_maximum_quality_handler, proxy_request_handler,
     video_transcode_fallback_handler,
 };
+use crate::middlewares::ConfigGate;
 use salvo::prelude::*;
 
 pub fn routes() -> Router {
-    let config = Config::load();
-
     let decision_path = "/video/<colon:colon>/transcode/universal/decision";
     let start_path = "/video/<colon:colon>/transcode/universal/start<**rest>";
     let subtitles_path = "/video/<colon:colon>/transcode/universal/subtitles";
@@ -26,23 +25,19 @@ pub fn routes() -> Router {
         .path(subtitles_path)
         .get(proxy_request_handler);
 
-    // Apply middlewares based on config
-    if config.auto_select_version {
-        decision_router = decision_router.hoop(auto_select_version_handler);
-        start_router = start_router.hoop(auto_select_version_handler);
-        subtitles_router = subtitles_router.hoop(auto_select_version_handler);
-    }
+    // Apply middlewares, gated on the config at request time
+    decision_router = decision_router.hoop(auto_select_version());
+    start_router = start_router.hoop(auto_select_version());
+    subtitles_router = subtitles_router.hoop(auto_select_version());
 
-    if config.force_maximum_quality || config.disable_transcode {
-        decision_router = decision_router.hoop(force_maximum_quality_handler);
-        start_router = start_router.hoop(force_maximum_quality_handler);
-        subtitles_router = subtitles_router.hoop(force_maximum_quality_handler);
-    }
+    decision_router = decision_router.hoop(force_maximum_quality());
+    start_router = start_router.hoop(force_maximum_quality());
+    subtitles_router = subtitles_router.hoop(force_maximum_quality());
 
-    if config.video_transcode_fallback_for.is_some() {
-        decision_router =
-            decision_router.hoop(video_transcode_fallback_handler);
-    }
+    decision_router = decision_router.hoop(ConfigGate::new(
+        |config| config.video_transcode_fallback_for.is_some(),
+        video_transcode_fallback_handler,
+    ));
 
     decision_router = decision_router.hoop(direct_stream_fallback_handler);
 
@@ -54,3 +49,19 @@ pub fn routes() -> Router {
 
     router
 }
+
+fn auto_select_version() -> impl Handler {
+    ConfigGate::new(
+        |config: &Config| config.auto_select_version,
+        auto_select_version_handler,
+    )
+}
+
+fn force_maximum_quality() -> impl Handler {
+    ConfigGate::new(
+        |config: &Config| {
+            config.force_maximum_quality || config.disable_transcode
+        },
+        force_maximum_quality_handler,
+    )
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde_json::Value;
+
+use crate::config::Scripts;
+
+// Compiled scripts by path.
+static SCRIPTS: Lazy<Mutex<HashMap<PathBuf, Compiled>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Limits on what a script can build, so a script can't take the memory or
+// the stack of the server down with it.
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_ARRAY_SIZE: usize = 100_000;
+const MAX_MAP_SIZE: usize = 10_000;
+const MAX_CALL_LEVELS: usize = 64;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_FUNCTION_EXPR_DEPTH: usize = 32;
+
+struct Compiled {
+    // Modification time of the file the script was compiled from.
+    modified: SystemTime,
+    ast: Arc<AST>,
+}
+
+/// Runs the script at `path` with `container` and `context` in scope, as
+/// `media_container` and `context`, and returns `media_container` as the
+/// script left it.
+///
+/// Scripts are compiled once and again whenever the file changes, so they
+/// can be edited without a restart. They can't load modules or `eval` code,
+/// can only build strings, arrays and maps up to a size, and are stopped
+/// after `limits.max_operations` operations or `limits.timeout` milliseconds.
+pub async fn run(
+    path: &str,
+    container: Value,
+    context: Value,
+    limits: &Scripts,
+) -> Result<Value> {
+    let max_operations = limits.max_operations;
+    let timeout = Duration::from_millis(limits.timeout);
+    let name = path.to_owned();
+
+    // Scripts can run for a while, and are read and compiled when they
+    // changed, so they get a thread of their own.
+    tokio::task::spawn_blocking(move || {
+        let ast = compiled(Path::new(&name))?;
+        let engine = engine(&name, max_operations, timeout);
+
+        let mut scope = Scope::new();
+        scope.push_dynamic("media_container", to_dynamic(container)?);
+        scope.push_dynamic("context", to_dynamic(context)?);
+
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow!("{}", e))?;
+
+        let container = scope
+            .get_value::<Dynamic>("media_container")
+            .context("The script removed `media_container`")?;
+        from_dynamic::<Value>(&container).map_err(|e| anyhow!("{}", e))
+    })
+    .await?
+}
+
+/// Compiles the script at `path`, for checking it without running it.
+pub fn check(path: &str) -> Result<()> {
+    compiled(Path::new(path)).map(|_| ())
+}
+
+// The lock is only held to look the script up and to store it, so reading
+// and compiling a script doesn't hold up the other scripts. A script that
+// changed may be compiled twice at the same time, which is harmless.
+fn compiled(path: &Path) -> Result<Arc<AST>> {
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .with_context(|| format!("Failed to read script {}", path.display()))?;
+
+    if let Some(compiled) = SCRIPTS.lock().unwrap().get(path) {
+        if compiled.modified == modified {
+            return Ok(compiled.ast.clone());
+        }
+    }
+
+    let source = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read script {}", path.display()))?;
+    let ast = Arc::new(
+        limited()
+            .compile(source)
+            .map_err(|e| anyhow!("Failed to compile script {}: {}", path.display(), e))?,
+    );
+
+    tracing::info!(script = %path.display(), "Loaded script");
+    let mut scripts = SCRIPTS.lock().unwrap();
+    // Another thread may have compiled a newer version in the meantime.
+    if scripts.get(path).is_none_or(|compiled| compiled.modified <= modified) {
+        scripts.insert(
+            path.to_owned(),
+            Compiled {
+                modified,
+                ast: ast.clone(),
+            },
+        );
+    }
+
+    Ok(ast)
+}
+
+// An engine with the limits that don't depend on the config, which scripts
+// are compiled with too.
+fn limited() -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_FUNCTION_EXPR_DEPTH);
+
+    engine
+}
+
+fn engine(name: &str, max_operations: u64, timeout: Duration) -> Engine {
+    let mut engine = limited();
+    let started = Instant::now();
+
+    engine.set_max_operations(max_operations);
+    engine.on_progress(move |_| {
+        (started.elapsed() > timeout).then(|| Dynamic::from("timeout"))
+    });
+
+    let script = name.to_owned();
+    engine.on_print(move |text| tracing::info!(script = %script, "{}", text));
+    let script = name.to_owned();
+    engine.on_debug(move |text, _, position| {
+        tracing::debug!(script = %script, %position, "{}", text)
+    });
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn script(dir: &TempDir, source: &str) -> String {
+        let path = dir.path().join("script.rhai");
+        fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    async fn run_script(path: &str, limits: &Scripts) -> Result<Value> {
+        run(path, json!({ "size": 0 }), json!({}), limits).await
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_operations() {
+        let dir = TempDir::new().unwrap();
+        let path = script(&dir, "loop {}");
+        let limits = Scripts {
+            max_operations: 1000,
+            timeout: 60_000,
+            ..Scripts::default()
+        };
+
+        let error = run_script(&path, &limits).await.unwrap_err();
+        assert!(error.to_string().contains("Too many operations"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn stops_after_timeout() {
+        let dir = TempDir::new().unwrap();
+        let path = script(&dir, "loop {}");
+        let limits = Scripts {
+            max_operations: 0,
+            timeout: 50,
+            ..Scripts::default()
+        };
+
+        let started = Instant::now();
+        let error = run_script(&path, &limits).await.unwrap_err();
+        assert!(error.to_string().contains("terminated"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn stops_strings_past_max_size() {
+        let dir = TempDir::new().unwrap();
+        let path = script(&dir, r#"let s = "x"; loop { s += s; }"#);
+
+        let error = run_script(&path, &Scripts::default()).await.unwrap_err();
+        assert!(error.to_string().contains("too large"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_eval() {
+        let dir = TempDir::new().unwrap();
+        let path = script(&dir, r#"eval("media_container.size = 1");"#);
+
+        assert!(check(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn reloads_changed_script() {
+        let dir = TempDir::new().unwrap();
+        let path = script(&dir, "media_container.size = 1;");
+        let output = run_script(&path, &Scripts::default()).await.unwrap();
+        assert_eq!(output["size"], 1);
+
+        // Written within the resolution of the clock of the file system, the
+        // change might not show, so it is dated a bit later.
+        fs::write(&path, "media_container.size = 2;").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(1))
+            .unwrap();
+
+        let output = run_script(&path, &Scripts::default()).await.unwrap();
+        assert_eq!(output["size"], 2);
+    }
+}
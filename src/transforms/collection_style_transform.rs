@@ -25,19 +25,17 @@ impl Transform for CollectionStyleTransform {
         plex_client: &PlexClient,
         options: &PlexContext,
     ) -> Result<()> {
-        let mut collection =
-            Collection::get(plex_client, self.collection_ids[0])
-                .await
-                .unwrap();
+        let Some(&id) = self.collection_ids.first() else {
+            return Ok(());
+        };
+        let collection = Collection::get(plex_client, id).await?;
 
-        let is_hero = collection
-            .children()
-            .first()
-            .unwrap()
-            .has_label("REPLEXHERO".to_string());
+        let Some(collection) = collection.children().first() else {
+            return Ok(());
+        };
 
-        if is_hero {
-            let children = container.children();
+        if collection.has_label("REPLEXHERO".to_string()) {
+            let children = container.take_children();
             let style = ClientHeroStyle::from_context(options);
             let mut futures = FuturesOrdered::new();
 
@@ -52,10 +50,13 @@ impl Transform for CollectionStyleTransform {
 
                 futures.push_back(async move {
                     let transform = MediaStyleTransform { style: Style::Hero };
-                    transform
+                    // Items whose art can't be loaded are shown as they are.
+                    if let Err(e) = transform
                         .transform_metadata(&mut child, plex_client, options)
                         .await
-                        .expect("Failed to transform metadata");
+                    {
+                        tracing::error!(item = %child.title, error = %e, "Failed to style hero item");
+                    }
                     child
                 });
             }
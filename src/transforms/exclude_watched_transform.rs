@@ -1,7 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use crate::models::MediaContainer;
+use crate::models::{MediaContainer, MetaData};
 use crate::plex::client::PlexClient;
 use crate::plex::models::PlexContext;
 
@@ -16,11 +16,11 @@ impl Transform for ExcludeWatchedTransform {
         &self, container: &mut MediaContainer, plex_client: &PlexClient,
         _options: &PlexContext,
     ) -> Result<()> {
-        if container.hub.is_empty() {
+        if !container.is_hub() {
             return Ok(());
         }
 
-        for hub in &mut container.hub {
+        for hub in container.iter_children_mut() {
             if hub.exclude_watched(plex_client).await.unwrap_or(false) {
                 hub.children_mut().retain(|x| !x.is_watched());
             }
@@ -28,4 +28,14 @@ impl Transform for ExcludeWatchedTransform {
 
         Ok(())
     }
+
+    async fn needs_children(
+        &self,
+        hub: &MetaData,
+        _hubs: &[MetaData],
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        hub.exclude_watched(plex_client).await.unwrap_or(false)
+    }
 }
@@ -1,8 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use crate::config::Config;
-use crate::models::MediaContainer;
+use crate::models::{MediaContainer, MetaData};
 use crate::plex::client::PlexClient;
 use crate::plex::models::PlexContext;
 
@@ -16,12 +15,12 @@ impl Transform for HideInProgressTransform {
     async fn transform_mediacontainer(
         &self,
         container: &mut MediaContainer,
-        _plex_client: &PlexClient,
+        plex_client: &PlexClient,
         _options: &PlexContext,
     ) -> Result<()> {
-        let config = Config::load();
+        let config = &plex_client.config;
 
-        if container.hub.is_empty() || !config.better_on_deck.enabled {
+        if !container.is_hub() || !config.better_on_deck.enabled {
             return Ok(());
         }
 
@@ -32,4 +31,14 @@ impl Transform for HideInProgressTransform {
 
         Ok(())
     }
+
+    async fn needs_children(
+        &self,
+        _hub: &MetaData,
+        _hubs: &[MetaData],
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        false
+    }
 }
@@ -35,4 +35,14 @@ impl Transform for HubKeyTransform {
 
         return Ok(());
     }
+
+    async fn needs_children(
+        &self,
+        _hub: &MetaData,
+        _hubs: &[MetaData],
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        false
+    }
 }
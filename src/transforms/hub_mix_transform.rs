@@ -18,13 +18,13 @@ impl Transform for HubMixTransform {
         plex_client: &PlexClient,
         _options: &PlexContext,
     ) -> Result<()> {
-        if container.hub.is_empty() {
+        if !container.is_hub() {
             return Ok(());
         }
 
-        let mut new_hubs: Vec<MetaData> = Vec::new();
+        let mut new_hubs: Vec<MetaData> = Vec::with_capacity(container.children().len());
 
-        for hub in &mut container.hub {
+        for mut hub in container.take_children() {
             if hub.size.unwrap_or_default() == 0 {
                 continue;
             }
@@ -48,12 +48,11 @@ impl Transform for HubMixTransform {
                         existing_hub.key = Some(merge_hub_keys(&keys_to_merge));
                     }
 
-                    // Prepare an iterator over all children, avoiding early cloning.
+                    // Move the children of both hubs into the merged one.
                     let all_children = existing_hub
-                        .children_mut()
-                        .iter()
-                        .cloned()
-                        .interleave(hub.clone().children().iter().cloned())
+                        .take_children()
+                        .into_iter()
+                        .interleave(hub.take_children())
                         .collect();
 
                     existing_hub.set_children(all_children);
@@ -62,7 +61,7 @@ impl Transform for HubMixTransform {
                 }
                 None => {
                     // No hub with the same title exists, add the current hub as is
-                    new_hubs.push(hub.clone());
+                    new_hubs.push(hub);
                 }
             }
         }
@@ -71,10 +70,41 @@ impl Transform for HubMixTransform {
             hub.better_on_deck(plex_client).await;
         }
 
-        container.hub = new_hubs;
+        *container.children_mut() = new_hubs;
 
         Ok(())
     }
+
+    async fn needs_children(
+        &self,
+        hub: &MetaData,
+        hubs: &[MetaData],
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        if hub.size.unwrap_or_default() == 0 {
+            return false;
+        }
+
+        // Hubs with the same title are mixed, and the better on deck hubs
+        // sorted.
+        let is_mixed = hubs
+            .iter()
+            .filter(|other| {
+                other.title == hub.title && other.size.unwrap_or_default() > 0
+            })
+            .count()
+            > 1;
+
+        let better_on_deck = &plex_client.config.better_on_deck;
+        let is_sorted = better_on_deck.enabled
+            && [&better_on_deck.in_progress, &better_on_deck.next_up]
+                .into_iter()
+                .flatten()
+                .any(|title| *title == hub.title);
+
+        is_mixed || is_sorted
+    }
 }
 
 pub fn merge_hub_keys(keys: &[&str]) -> String {
@@ -91,3 +121,78 @@ pub fn merge_hub_keys(keys: &[&str]) -> String {
     // Return the formatted key with all IDs merged.
     format!("/library/collections/{}/children", cleaned_keys.join(","))
 }
+
+/// Merges `hubs` of one of the other `servers` into `container`. Collection
+/// hubs are merged into the collection hub with the same title, whose key
+/// then also lists the collections of `server`, as `id@server`. The other
+/// hubs are added as they are.
+pub fn merge_server_hubs(
+    container: &mut MediaContainer,
+    hubs: Vec<MetaData>,
+    server: &str,
+) {
+    for mut hub in hubs {
+        if hub.size.unwrap_or_default() == 0 {
+            continue;
+        }
+
+        let existing = container.iter_children_mut().find(|existing| {
+            existing.title == hub.title
+                && existing.is_collection_hub()
+                && hub.is_collection_hub()
+        });
+
+        let Some(existing) = existing else {
+            container.children_mut().push(hub);
+            continue;
+        };
+
+        // The children of `host` may not have been decoded yet.
+        if let Err(e) = existing.decode_children() {
+            tracing::error!(hub = %existing.title, error = %e, "Failed to decode hub");
+            container.children_mut().push(hub);
+            continue;
+        }
+
+        if let (Some(existing_key), Some(key)) = (&existing.key, &hub.key) {
+            if let Some(merged) = merge_server_hub_keys(existing_key, key, server) {
+                existing.key = Some(merged);
+            }
+        }
+
+        let all_children = existing
+            .take_children()
+            .into_iter()
+            .interleave(hub.take_children())
+            .collect();
+
+        existing.set_children(all_children);
+    }
+}
+
+// Adds the collection ids in `key`, a hub key of `server`, to the ones in
+// `existing_key`.
+fn merge_server_hub_keys(
+    existing_key: &str,
+    key: &str,
+    server: &str,
+) -> Option<String> {
+    let (prefix, existing_ids, suffix) = split_collection_key(existing_key)?;
+    let (_, ids, _) = split_collection_key(key)?;
+
+    let ids = ids.split(',').map(|id| format!("{}@{}", id, server)).join(",");
+
+    Some(format!(
+        "{}/library/collections/{},{}/{}",
+        prefix, existing_ids, ids, suffix
+    ))
+}
+
+// Splits a collection key into the part before the ids, the ids and the part
+// after them.
+fn split_collection_key(key: &str) -> Option<(&str, &str, &str)> {
+    let (prefix, rest) = key.split_once("/library/collections/")?;
+    let (ids, suffix) = rest.split_once('/')?;
+
+    Some((prefix, ids, suffix))
+}
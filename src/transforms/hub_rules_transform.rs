@@ -0,0 +1,297 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+
+use crate::config::HubRule;
+use crate::models::{MediaContainer, MetaData};
+use crate::plex::client::PlexClient;
+use crate::plex::models::PlexContext;
+
+use super::Transform;
+
+/// Applies the `hub_rules` of the config. Styles and watched items are left
+/// to `HubStyleTransform` and `ExcludeWatchedTransform`, which check the
+/// rules themselves. This goes after all other transforms, and after hubs of
+/// the other servers are merged in, so positions are final.
+#[derive(Default, Debug)]
+pub struct HubRulesTransform;
+
+#[async_trait]
+impl Transform for HubRulesTransform {
+    async fn transform_mediacontainer(
+        &self,
+        container: &mut MediaContainer,
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> Result<()> {
+        if !container.is_hub() || plex_client.config.hub_rules.is_empty()
+        {
+            return Ok(());
+        }
+
+        let hubs = container.take_children();
+        let mut kept: Vec<MetaData> = Vec::with_capacity(hubs.len());
+        let mut pinned: Vec<(usize, MetaData)> = Vec::new();
+
+        // Rules on collection labels ask Plex for the collection, so hubs are
+        // matched a few at a time.
+        let concurrency = plex_client.config.http.concurrency.max(1);
+        let lookups: Vec<_> = hubs
+            .iter()
+            .map(|hub| matching_rules(hub, plex_client))
+            .collect();
+        let matches: Vec<Vec<&HubRule>> = stream::iter(lookups)
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        for (mut hub, rules) in hubs.into_iter().zip(matches) {
+
+            if rules.iter().any(|rule| rule.hide) {
+                continue;
+            }
+
+            if let Some(title) = rules.iter().rev().find_map(|r| r.title.as_ref())
+            {
+                hub.title = title.clone();
+            }
+
+            if let Some(limit) = rules.iter().rev().find_map(|r| r.limit) {
+                if hub.children().len() > limit {
+                    let mut children = hub.take_children();
+                    children.truncate(limit);
+                    hub.set_children(children);
+                }
+            }
+
+            match rules.iter().rev().find_map(|r| r.position) {
+                Some(position) => pinned.push((position, hub)),
+                None => kept.push(hub),
+            }
+        }
+
+        // Pinned hubs go in from the top, so each ends up where it asked to
+        // be, or at the bottom when there are fewer hubs.
+        pinned.sort_by_key(|(position, _)| *position);
+        for (position, hub) in pinned {
+            let index = position.saturating_sub(1).min(kept.len());
+            kept.insert(index, hub);
+        }
+
+        container.set_children(kept);
+        Ok(())
+    }
+
+    async fn needs_children(
+        &self,
+        hub: &MetaData,
+        _hubs: &[MetaData],
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        hub.hub_rules(plex_client)
+            .await
+            .is_ok_and(|rules| rules.iter().any(|rule| rule.limit.is_some()))
+    }
+}
+
+// Rules of hubs that fail to match are left out, so the hub stays as it is.
+async fn matching_rules<'a>(hub: &MetaData, plex_client: &'a PlexClient) -> Vec<&'a HubRule> {
+    hub.hub_rules(plex_client).await.unwrap_or_else(|e| {
+        tracing::error!(hub = %hub.title, error = %e, "Failed to match hub rules");
+        Vec::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use crate::plex::testing;
+
+    use super::*;
+
+    fn hub(hub_identifier: &str, title: &str, items: usize) -> Value {
+        let items: Vec<Value> = (1..=items)
+            .map(|i| json!({ "ratingKey": i.to_string(), "title": format!("{} {}", title, i) }))
+            .collect();
+        json!({
+            "hubIdentifier": hub_identifier,
+            "title": title,
+            "size": items.len(),
+            "Metadata": items,
+        })
+    }
+
+    fn hubs() -> MediaContainer {
+        serde_json::from_value(json!({
+            "size": 4,
+            "Hub": [
+                hub("home.continue", "Continue Watching", 3),
+                hub("home.movies.recent.1", "Recently Added Movies", 3),
+                hub("home.television.recent.2", "Recently Added TV", 3),
+                hub("custom.collection.6.100", "Favorites", 3),
+            ],
+        }))
+        .unwrap()
+    }
+
+    async fn apply(rules: Value, container: &mut MediaContainer) {
+        let config = testing::config(json!({ "hub_rules": rules }));
+        let plex_client = testing::client(config, |_| testing::response(503, json!({})));
+
+        HubRulesTransform
+            .transform_mediacontainer(container, &plex_client, &PlexContext::default())
+            .await
+            .unwrap();
+    }
+
+    fn titles(container: &MediaContainer) -> Vec<&str> {
+        container
+            .children()
+            .iter()
+            .map(|hub| hub.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn section_id_comes_from_the_hub_identifier() {
+        let section_id = |value: Value| {
+            serde_json::from_value::<MetaData>(value)
+                .unwrap()
+                .section_id()
+        };
+
+        assert_eq!(section_id(hub("home.movies.recent.6", "", 0)), Some(6));
+        assert_eq!(
+            section_id(hub("custom.collection.6.254687", "", 0)),
+            Some(6)
+        );
+        assert_eq!(section_id(hub("home.continue", "", 0)), None);
+        assert_eq!(
+            section_id(json!({ "hubIdentifier": "movie.recent", "librarySectionID": 3 })),
+            Some(3)
+        );
+    }
+
+    #[tokio::test]
+    async fn pinned_hubs_go_where_they_ask_in_order() {
+        let mut container = hubs();
+        apply(
+            json!([
+                { "match": { "hub_identifier": "custom.collection" }, "position": 1 },
+                { "match": { "hub_identifier": "home.continue" }, "position": 3 },
+                { "match": { "hub_identifier": "home.television" }, "position": 10 },
+            ]),
+            &mut container,
+        )
+        .await;
+
+        assert_eq!(
+            titles(&container),
+            [
+                "Favorites",
+                "Recently Added Movies",
+                "Continue Watching",
+                "Recently Added TV"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn last_matching_rule_wins() {
+        let mut container = hubs();
+        apply(
+            json!([
+                { "match": { "section": 1 }, "limit": 1, "title": "Movies" },
+                { "match": { "title": "^Recently" }, "limit": 2 },
+                { "match": { "hub_identifier": "home.continue" }, "hide": true },
+            ]),
+            &mut container,
+        )
+        .await;
+
+        assert_eq!(
+            titles(&container),
+            ["Movies", "Recently Added TV", "Favorites"]
+        );
+        let sizes: Vec<usize> = container
+            .children()
+            .iter()
+            .map(|hub| hub.children().len())
+            .collect();
+        assert_eq!(sizes, [2, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn collection_labels_are_fetched_once_per_hub() {
+        let mut container = hubs();
+        container.children_mut()[3].context = Some("hub.custom.collection".to_string());
+
+        let config = testing::config(json!({
+            "hub_rules": [{
+                "match": { "collection_label": "pinned" },
+                "position": 1,
+                "style": "hero",
+            }],
+        }));
+        let collection = json!({
+            "MediaContainer": {
+                "size": 1,
+                "Metadata": [{
+                    "ratingKey": "100",
+                    "title": "Favorites",
+                    "Label": [{ "id": 7, "tag": "Pinned", "filter": "label=7" }],
+                }],
+            },
+        });
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        let plex_client = testing::client(config, move |req| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(req.url().path(), "/library/collections/100");
+            testing::response(200, collection.clone())
+        });
+
+        HubRulesTransform
+            .transform_mediacontainer(&mut container, &plex_client, &PlexContext::default())
+            .await
+            .unwrap();
+        // Styles check the rules again, without asking Plex again.
+        assert!(container.children()[0].is_hero(&plex_client).await.unwrap());
+
+        assert_eq!(
+            titles(&container),
+            [
+                "Favorites",
+                "Continue Watching",
+                "Recently Added Movies",
+                "Recently Added TV"
+            ]
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn hubs_whose_rules_fail_are_left_alone() {
+        let mut container = hubs();
+        apply(
+            json!([{ "match": { "collection_label": "pinned" }, "position": 1 }]),
+            &mut container,
+        )
+        .await;
+
+        assert_eq!(
+            titles(&container),
+            [
+                "Continue Watching",
+                "Recently Added Movies",
+                "Recently Added TV",
+                "Favorites"
+            ]
+        );
+    }
+}
@@ -29,7 +29,7 @@ impl Transform for HubStyleTransform {
         }
 
         let style = ClientHeroStyle::from_context(options);
-        let children = item.children();
+        let children = item.take_children();
         let child_type = style.child_type;
         let mut futures = FuturesOrdered::new();
 
@@ -48,10 +48,13 @@ impl Transform for HubStyleTransform {
 
             futures.push_back(async move {
                 let transform = MediaStyleTransform { style: Style::Hero };
-                transform
+                // Items whose art can't be loaded are shown as they are.
+                if let Err(e) = transform
                     .transform_metadata(&mut child, plex_client, options)
                     .await
-                    .expect("Failed to transform metadata");
+                {
+                    tracing::error!(item = %child.title, error = %e, "Failed to style hero item");
+                }
                 child
             });
         }
@@ -61,4 +64,72 @@ impl Transform for HubStyleTransform {
 
         Ok(())
     }
+
+    async fn needs_children(
+        &self,
+        hub: &MetaData,
+        _hubs: &[MetaData],
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        hub.is_hero(plex_client).await.unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::plex::error::PlexError;
+    use crate::plex::testing;
+
+    use super::*;
+
+    fn hero_hub() -> MetaData {
+        serde_json::from_value(json!({
+            "hubIdentifier": "home.movies.recent",
+            "title": "Recently Added",
+            "size": 2,
+            "Metadata": [
+                { "title": "One", "guid": "plex://movie/1", "type": "movie" },
+                { "title": "Two", "guid": "plex://movie/2", "type": "movie" },
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn unavailable_provider() -> PlexClient {
+        let config = testing::config(json!({ "hero_rows": ["home.movies.recent"] }));
+        testing::client(config, |_| testing::response(503, json!({})))
+    }
+
+    #[tokio::test]
+    async fn provider_failures_are_errors_of_the_item() {
+        let plex_client = unavailable_provider();
+        let mut item = hero_hub().take_children().remove(0);
+
+        let result = MediaStyleTransform { style: Style::Hero }
+            .transform_metadata(&mut item, &plex_client, &PlexContext::default())
+            .await;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<PlexError>(),
+            Some(PlexError::ProviderUnavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn hero_hubs_keep_their_items_when_the_provider_fails() {
+        let plex_client = unavailable_provider();
+        let mut hub = hero_hub();
+
+        HubStyleTransform::default()
+            .transform_metadata(&mut hub, &plex_client, &PlexContext::default())
+            .await
+            .unwrap();
+
+        let titles: Vec<&str> = hub.iter_children().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, ["One", "Two"]);
+        assert!(hub.iter_children().all(|item| item.images.is_empty()));
+    }
 }
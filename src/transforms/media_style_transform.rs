@@ -35,12 +35,15 @@ impl Transform for MediaStyleTransform {
         options: &PlexContext,
     ) -> Result<()> {
         if self.style == Style::Hero {
-            let mut guid = item.guid.clone().unwrap();
+            let Some(mut guid) = item.guid.clone() else {
+                return Ok(());
+            };
             let style_def = ClientHeroStyle::from_context(options);
 
-            if guid.starts_with("plex://episode") && item.parent_guid.is_some()
-            {
-                guid = item.parent_guid.clone().unwrap();
+            if guid.starts_with("plex://episode") {
+                if let Some(parent_guid) = &item.parent_guid {
+                    guid = parent_guid.clone();
+                }
             }
 
             if let Some(child_type) = &style_def.child_type {
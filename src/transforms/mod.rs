@@ -3,25 +3,38 @@ mod exclude_watched_transform;
 mod hide_in_progress_transform;
 mod hub_key_transform;
 mod hub_mix_transform;
+mod hub_rules_transform;
 mod hub_style_transform;
 mod media_style_transform;
+mod plugin_transform;
+pub mod registry;
 mod reorder_hubs_transform;
+mod script_transform;
 mod section_directory_transform;
 mod section_mix_transform;
+mod server_key_transform;
 mod supplement_hub_transform;
+mod unwatched_filter;
+mod user_state_transform;
 mod utils;
 
 pub use collection_style_transform::CollectionStyleTransform;
 pub use exclude_watched_transform::ExcludeWatchedTransform;
 pub use hide_in_progress_transform::HideInProgressTransform;
 pub use hub_key_transform::HubKeyTransform;
-pub use hub_mix_transform::HubMixTransform;
+pub use hub_mix_transform::{merge_server_hubs, HubMixTransform};
+pub use hub_rules_transform::HubRulesTransform;
 pub use hub_style_transform::HubStyleTransform;
 pub use media_style_transform::MediaStyleTransform;
+pub use plugin_transform::PluginTransform;
 pub use reorder_hubs_transform::ReorderHubsTransform;
+pub use script_transform::{ScriptHook, ScriptTransform};
 pub use section_directory_transform::SectionDirectoryTransform;
 pub use section_mix_transform::SectionMixTransform;
+pub use server_key_transform::ServerKeyTransform;
 pub use supplement_hub_transform::SupplementHubTransform;
+pub use unwatched_filter::UnwatchedFilter;
+pub use user_state_transform::UserStateTransform;
 pub use utils::filter::Filter;
 pub use utils::transform::Transform;
 pub use utils::transform_builder::TransformBuilder;
@@ -0,0 +1,284 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::config::Plugins;
+use crate::models::{MediaContainer, MetaData};
+use crate::plex::client::PlexClient;
+use crate::plex::models::PlexContext;
+use crate::plugins::{
+    Plugin, FILTER_MEDIACONTAINER, FILTER_METADATA, TRANSFORM_MEDIACONTAINER,
+    TRANSFORM_METADATA,
+};
+
+use super::{Filter, ScriptHook, Transform};
+
+/// Runs the hooks of a WebAssembly plugin, see `plugins`. When a hook fails,
+/// the error is logged and what it got is left as it was.
+#[derive(Debug)]
+pub struct PluginTransform {
+    pub plugin: Arc<Plugin>,
+    pub hook: ScriptHook,
+}
+
+impl PluginTransform {
+    pub fn is_transform(&self) -> bool {
+        self.plugin.has_hook(TRANSFORM_MEDIACONTAINER)
+            || self.plugin.has_hook(TRANSFORM_METADATA)
+    }
+
+    pub fn is_filter(&self) -> bool {
+        self.plugin.has_hook(FILTER_MEDIACONTAINER)
+            || self.plugin.has_hook(FILTER_METADATA)
+    }
+
+    // Calls a transform hook, returning what the plugin changed `value` to.
+    async fn transform<T>(
+        &self,
+        hook: &'static str,
+        name: &str,
+        value: &T,
+        plex_client: &PlexClient,
+        options: &PlexContext,
+    ) -> Option<T>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+    {
+        let input = self.input(name, value, options)?;
+        let output = self
+            .call(plex_client, move |plugin, limits| {
+                plugin.transform(hook, &input, limits)
+            })
+            .await
+            .and_then(|output| {
+                output
+                    .map(|json| serde_json::from_slice::<T>(&json))
+                    .transpose()
+                    .map_err(Into::into)
+            });
+
+        output.unwrap_or_else(|e| {
+            tracing::error!(plugin = %self.plugin.name, hook, error = %e, "Plugin failed");
+            None
+        })
+    }
+
+    // Calls a filter hook, keeping `value` when the plugin fails.
+    async fn filter<T: Serialize>(
+        &self,
+        hook: &'static str,
+        name: &str,
+        value: &T,
+        plex_client: &PlexClient,
+        options: &PlexContext,
+    ) -> bool {
+        let Some(input) = self.input(name, value, options) else {
+            return true;
+        };
+        self.call(plex_client, move |plugin, limits| {
+            plugin.filter(hook, &input, limits)
+        })
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!(plugin = %self.plugin.name, hook, error = %e, "Plugin failed");
+            true
+        })
+    }
+
+    fn input<T: Serialize>(
+        &self,
+        name: &str,
+        value: &T,
+        options: &PlexContext,
+    ) -> Option<Value> {
+        let mut input = json!({
+            "route": self.hook.name(),
+            "context": options.without_secrets(),
+        });
+        input[name] = serde_json::to_value(value)
+            .inspect_err(|e| {
+                tracing::error!(plugin = %self.plugin.name, error = %e, "Failed to encode plugin input");
+            })
+            .ok()?;
+        Some(input)
+    }
+
+    // Plugins run on a thread of their own, as a call can take a while.
+    async fn call<T, F>(&self, plex_client: &PlexClient, call: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Plugin, &Plugins) -> Result<T> + Send + 'static,
+    {
+        let plugin = self.plugin.clone();
+        let limits = Plugins {
+            directory: None,
+            ..plex_client.config.plugins
+        };
+        tokio::task::spawn_blocking(move || call(&plugin, &limits)).await?
+    }
+}
+
+#[async_trait]
+impl Transform for PluginTransform {
+    async fn transform_metadata(
+        &self,
+        item: &mut MetaData,
+        plex_client: &PlexClient,
+        options: &PlexContext,
+    ) -> Result<()> {
+        if !self.plugin.has_hook(TRANSFORM_METADATA) {
+            return Ok(());
+        }
+
+        if let Some(mut transformed) = self
+            .transform(TRANSFORM_METADATA, "metadata", &*item, plex_client, options)
+            .await
+        {
+            // The element isn't part of the JSON, so a hub would come back as
+            // `Metadata`. Children that were kept as JSON went out as lists
+            // and come back decoded, tagged by their list.
+            transformed.element = item.element.clone();
+            *item = transformed;
+        }
+
+        Ok(())
+    }
+
+    async fn transform_mediacontainer(
+        &self,
+        container: &mut MediaContainer,
+        plex_client: &PlexClient,
+        options: &PlexContext,
+    ) -> Result<()> {
+        if !self.plugin.has_hook(TRANSFORM_MEDIACONTAINER) {
+            return Ok(());
+        }
+
+        if let Some(transformed) = self
+            .transform(
+                TRANSFORM_MEDIACONTAINER,
+                "media_container",
+                &*container,
+                plex_client,
+                options,
+            )
+            .await
+        {
+            *container = transformed;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Filter for PluginTransform {
+    async fn filter_metadata(
+        &self,
+        item: &mut MetaData,
+        plex_client: &PlexClient,
+        options: &PlexContext,
+    ) -> bool {
+        if !self.plugin.has_hook(FILTER_METADATA) {
+            return true;
+        }
+
+        self.filter(FILTER_METADATA, "metadata", &*item, plex_client, options)
+            .await
+    }
+
+    async fn filter_mediacontainer(
+        &self,
+        container: &MediaContainer,
+        plex_client: &PlexClient,
+        options: &PlexContext,
+    ) -> Result<bool> {
+        if !self.plugin.has_hook(FILTER_MEDIACONTAINER) {
+            return Ok(true);
+        }
+
+        Ok(self
+            .filter(
+                FILTER_MEDIACONTAINER,
+                "media_container",
+                container,
+                plex_client,
+                options,
+            )
+            .await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::plex::testing;
+    use crate::transforms::{HubRulesTransform, TransformBuilder};
+
+    use super::*;
+
+    // Turns every item it gets into the same hub.
+    const REPLACE_HUB: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{\"hubIdentifier\":\"home.movies.recent.1\",\"title\":\"From plugin\",\"size\":1,\"Metadata\":[{\"ratingKey\":\"9\",\"title\":\"Nine\"}]}")
+          (func (export "replex_abi_version") (result i32) i32.const 1)
+          (func (export "replex_alloc") (param i32) (result i32) i32.const 1024)
+          (func (export "replex_transform_metadata") (param i32 i32) (result i64)
+            i64.const 117))
+    "#;
+
+    #[tokio::test]
+    async fn hubs_stay_hubs() {
+        let config = testing::config(json!({
+            "hub_rules": [{ "match": { "title": "^From plugin$" }, "title": "Ruled" }],
+        }));
+        let plex_client = testing::client(config, |_| {
+            testing::response(
+                200,
+                json!({ "MediaContainer": {
+                    "size": 2,
+                    "Hub": [
+                        { "hubIdentifier": "home.continue", "title": "Continue", "Metadata": [] },
+                        { "hubIdentifier": "home.ondeck", "title": "On Deck", "Metadata": [] },
+                    ],
+                } }),
+            )
+        });
+        let res = plex_client.get("/hubs").await.unwrap();
+        let mut container = MediaContainer::from_reqwest_response_keeping_children(res)
+            .await
+            .unwrap();
+        let options = PlexContext::default();
+
+        TransformBuilder::new(&plex_client, &options)
+            .with_transform(PluginTransform {
+                plugin: Arc::new(Plugin::from_wat("replace_hub", REPLACE_HUB)),
+                hook: ScriptHook::PromotedHubs,
+            })
+            .apply_to(&mut container)
+            .await
+            .unwrap();
+        // Like the steps after `merge_servers`, which only apply to hubs.
+        TransformBuilder::new(&plex_client, &options)
+            .with_transform(HubRulesTransform)
+            .apply_to(&mut container)
+            .await
+            .unwrap();
+
+        assert!(container.is_hub());
+        let json = serde_json::to_value(&container).unwrap();
+        let hubs = json["Hub"].as_array().unwrap();
+        assert_eq!(hubs.len(), 2);
+        for hub in hubs {
+            assert_eq!(hub["title"], "Ruled");
+            assert_eq!(hub["Metadata"][0]["title"], "Nine");
+        }
+        assert!(json.get("Metadata").is_none());
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::config::PipelineStep;
+use crate::models::Style;
+
+use super::*;
+
+/// Name of the step that marks where the responses of the other `servers`
+/// are merged in, see `split_at_merge`.
+pub const MERGE_SERVERS: &str = "merge_servers";
+
+/// Names of the transforms and filters that pipelines can use.
+pub const STEPS: [&str; 17] = [
+    "section_directory",
+    "hide_in_progress",
+    "exclude_watched",
+    "supplement_hub",
+    "hub_mix",
+    "reorder_hubs",
+    "hub_style",
+    "hub_key",
+    "section_mix",
+    "collection_style",
+    "media_style",
+    "unwatched",
+    "hub_rules",
+    "plugins",
+    "script",
+    "user_state",
+    MERGE_SERVERS,
+];
+
+/// Values of the request that some steps are made from.
+#[derive(Debug, Clone, Default)]
+pub struct StepContext {
+    pub collection_ids: Vec<i64>,
+    pub offset: i32,
+    pub limit: i32,
+    pub is_hub: bool,
+    pub style: Style,
+    // Route of the pipeline, for the steps that depend on it. `None` for
+    // routes without plugins and scripts.
+    pub hook: Option<ScriptHook>,
+}
+
+/// A step of a pipeline, made from its config.
+pub enum Step {
+    Transform(Arc<dyn Transform + Send + Sync>),
+    Filter(Arc<dyn Filter + Send + Sync>),
+    // The plugins in `plugins.directory`, which are transforms, filters or
+    // both.
+    Plugins(ScriptHook),
+    // Handled by the route, see `split_at_merge`.
+    MergeServers,
+}
+
+/// A pipeline split at its `merge_servers` step.
+pub struct Split<'a> {
+    /// Steps for the response of each server.
+    pub before: &'a [PipelineStep],
+    /// Whether the responses of the other servers are merged in.
+    pub merge: bool,
+    /// Steps for the merged response.
+    pub after: &'a [PipelineStep],
+}
+
+/// Splits `steps` at the `merge_servers` step. Without one, all steps run
+/// before the merge.
+pub fn split_at_merge(steps: &[PipelineStep]) -> Split<'_> {
+    match steps.iter().position(|step| step.name == MERGE_SERVERS) {
+        Some(index) => Split {
+            before: &steps[..index],
+            merge: steps[index].enabled,
+            after: &steps[index + 1..],
+        },
+        None => Split {
+            before: steps,
+            merge: true,
+            after: &[],
+        },
+    }
+}
+
+/// Makes the transform or filter of `step`.
+pub fn build(step: &PipelineStep, context: &StepContext) -> Result<Step> {
+    let mut params = Params::new(&step.params);
+
+    let built = match step.name.as_str() {
+        "section_directory" => transform(SectionDirectoryTransform),
+        "hide_in_progress" => transform(HideInProgressTransform),
+        "exclude_watched" => transform(ExcludeWatchedTransform),
+        "supplement_hub" => transform(SupplementHubTransform),
+        "hub_mix" => transform(HubMixTransform),
+        "reorder_hubs" => transform(ReorderHubsTransform),
+        "hub_style" => transform(HubStyleTransform {
+            is_home: params.get("is_home")?.unwrap_or(false),
+        }),
+        "hub_key" => transform(HubKeyTransform),
+        "section_mix" => transform(SectionMixTransform {
+            collection_ids: context.collection_ids.clone(),
+            offset: context.offset,
+            limit: params.get("limit")?.unwrap_or(context.limit),
+        }),
+        "collection_style" => transform(CollectionStyleTransform {
+            collection_ids: context.collection_ids.clone(),
+            is_hub: context.is_hub,
+        }),
+        "media_style" => transform(MediaStyleTransform {
+            style: params.get("style")?.unwrap_or_else(|| context.style.clone()),
+        }),
+        "unwatched" => filter(UnwatchedFilter),
+        "hub_rules" => transform(HubRulesTransform),
+        "plugins" => Step::Plugins(hook(context, &step.name)?),
+        "script" => transform(ScriptTransform {
+            hook: hook(context, &step.name)?,
+        }),
+        "user_state" => transform(UserStateTransform),
+        MERGE_SERVERS => {
+            hook(context, &step.name)?;
+            Step::MergeServers
+        }
+        name => bail!(
+            "unknown transform \"{}\", expected one of {}",
+            name,
+            STEPS.join(", ")
+        ),
+    };
+
+    params.check_used()?;
+    Ok(built)
+}
+
+fn hook(context: &StepContext, name: &str) -> Result<ScriptHook> {
+    context.hook.ok_or_else(|| {
+        anyhow!(
+            "\"{}\" only works in promoted_hubs, section_hubs and collection_children",
+            name
+        )
+    })
+}
+
+fn transform(transform: impl Transform + 'static) -> Step {
+    Step::Transform(Arc::new(transform))
+}
+
+fn filter(filter: impl Filter + 'static) -> Step {
+    Step::Filter(Arc::new(filter))
+}
+
+// Parameters of a step, keeping track of the ones that were read so
+// misspelled ones are reported.
+struct Params<'a> {
+    values: &'a Map<String, Value>,
+    used: Vec<&'static str>,
+}
+
+impl<'a> Params<'a> {
+    fn new(values: &'a Map<String, Value>) -> Self {
+        Self {
+            values,
+            used: Vec::new(),
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&mut self, name: &'static str) -> Result<Option<T>> {
+        self.used.push(name);
+        self.values
+            .get(name)
+            .map(|value| {
+                serde_json::from_value(value.clone())
+                    .map_err(|e| anyhow!("invalid `{}`: {}", name, e))
+            })
+            .transpose()
+    }
+
+    fn check_used(&self) -> Result<()> {
+        match self.values.keys().find(|key| !self.used.contains(&key.as_str())) {
+            Some(key) => bail!("unknown parameter `{}`", key),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::plex::testing;
+
+    use super::*;
+
+    fn context(route: &str) -> StepContext {
+        StepContext {
+            hook: ScriptHook::from_name(route),
+            ..StepContext::default()
+        }
+    }
+
+    #[test]
+    fn default_pipelines_build() {
+        let config = testing::config(json!({}));
+
+        for (route, steps) in config.pipelines.routes() {
+            for step in steps {
+                if let Err(e) = build(step, &context(route)) {
+                    panic!("{} of {}: {}", step.name, route, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_step_is_known() {
+        for name in STEPS {
+            let step = PipelineStep::named(name);
+            assert!(build(&step, &context("promoted_hubs")).is_ok(), "{}", name);
+        }
+
+        let error = build(&PipelineStep::named("hub_styl"), &context("promoted_hubs"));
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn params_are_checked() {
+        let step = PipelineStep::named("hub_style").with_param("is_home", "yes");
+        let error = build(&step, &StepContext::default()).err().unwrap();
+        assert!(error.to_string().starts_with("invalid `is_home`"), "{}", error);
+
+        let step = PipelineStep::named("hub_key").with_param("is_home", true);
+        let error = build(&step, &StepContext::default()).err().unwrap();
+        assert_eq!(error.to_string(), "unknown parameter `is_home`");
+    }
+
+    #[test]
+    fn pipelines_split_at_merge_servers() {
+        let steps = [
+            PipelineStep::named("hub_key"),
+            PipelineStep::named(MERGE_SERVERS),
+            PipelineStep::named("hub_rules"),
+        ];
+
+        let split = split_at_merge(&steps);
+        assert_eq!(split.before, &steps[..1]);
+        assert!(split.merge);
+        assert_eq!(split.after, &steps[2..]);
+
+        let split = split_at_merge(&steps[..1]);
+        assert_eq!(split.before, &steps[..1]);
+        assert!(split.merge && split.after.is_empty());
+    }
+}
@@ -1,7 +1,6 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use crate::config::Config;
 use crate::models::{MediaContainer, MetaData};
 use crate::plex::client::PlexClient;
 use crate::plex::models::PlexContext;
@@ -16,54 +15,60 @@ impl Transform for ReorderHubsTransform {
     async fn transform_mediacontainer(
         &self,
         item: &mut MediaContainer,
-        _plex_client: &PlexClient,
+        plex_client: &PlexClient,
         _options: &PlexContext,
     ) -> Result<()> {
-        let config = Config::load();
+        let config = &plex_client.config;
 
-        let mut reordered_hubs: Vec<MetaData> = Vec::new();
+        let mut hubs = item.take_children();
+        let mut reordered_hubs: Vec<MetaData> = Vec::with_capacity(hubs.len());
 
         // Move in_progress and next_up hubs to the top if they exist
         if config.better_on_deck.enabled {
             if let Some(in_progress) = &config.better_on_deck.in_progress {
-                move_to_top(
-                    in_progress,
-                    item.children_mut(),
-                    &mut reordered_hubs,
-                );
+                move_to_top(in_progress, &mut hubs, &mut reordered_hubs);
             }
             if let Some(next_up) = &config.better_on_deck.next_up {
-                move_to_top(next_up, item.children_mut(), &mut reordered_hubs);
+                move_to_top(next_up, &mut hubs, &mut reordered_hubs);
             }
         }
 
         // Move other priority hubs to the top
         if let Some(priority_titles) = &config.priority_hubs {
             for title in priority_titles {
-                move_to_top(title, item.children_mut(), &mut reordered_hubs);
+                move_to_top(title, &mut hubs, &mut reordered_hubs);
             }
         }
 
         // Add remaining hubs
-        for hub in item.children_mut() {
-            if !reordered_hubs.contains(hub) {
-                reordered_hubs.push(hub.clone());
-            }
-        }
+        reordered_hubs.append(&mut hubs);
 
         item.set_children(reordered_hubs);
         Ok(())
     }
+
+    async fn needs_children(
+        &self,
+        _hub: &MetaData,
+        _hubs: &[MetaData],
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        false
+    }
 }
 
 fn move_to_top(
     title: &str,
-    hubs: &[MetaData],
+    hubs: &mut Vec<MetaData>,
     reordered_hubs: &mut Vec<MetaData>,
 ) {
-    for hub in hubs.iter().filter(|h| h.title == title).cloned() {
-        if !reordered_hubs.contains(&hub) {
-            reordered_hubs.push(hub);
+    let mut i = 0;
+    while i < hubs.len() {
+        if hubs[i].title == title {
+            reordered_hubs.push(hubs.remove(i));
+        } else {
+            i += 1;
         }
     }
 }
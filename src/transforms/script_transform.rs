@@ -0,0 +1,106 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::config::Scripts;
+use crate::models::{MediaContainer, MetaData};
+use crate::plex::client::PlexClient;
+use crate::plex::models::PlexContext;
+use crate::scripting;
+
+use super::Transform;
+
+/// Routes that have a script in `scripts`, and that plugins run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptHook {
+    PromotedHubs,
+    SectionHubs,
+    CollectionChildren,
+}
+
+impl ScriptHook {
+    /// Name of the route, as used in `scripts` and told to plugins.
+    pub fn name(self) -> &'static str {
+        match self {
+            ScriptHook::PromotedHubs => "promoted_hubs",
+            ScriptHook::SectionHubs => "section_hubs",
+            ScriptHook::CollectionChildren => "collection_children",
+        }
+    }
+
+    /// The route called `name`, see `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            ScriptHook::PromotedHubs,
+            ScriptHook::SectionHubs,
+            ScriptHook::CollectionChildren,
+        ]
+        .into_iter()
+        .find(|hook| hook.name() == name)
+    }
+
+    pub fn script(self, scripts: &Scripts) -> Option<&str> {
+        match self {
+            ScriptHook::PromotedHubs => scripts.promoted_hubs.as_deref(),
+            ScriptHook::SectionHubs => scripts.section_hubs.as_deref(),
+            ScriptHook::CollectionChildren => scripts.collection_children.as_deref(),
+        }
+    }
+}
+
+/// Runs the script of a route on the whole container. The script sees the
+/// container and the `PlexContext`, without its secrets, the way they are
+/// written as JSON, and changes `media_container` in place:
+///
+/// ```rhai
+/// media_container.Hub.retain(|hub| hub.title != "Recently Released");
+/// if context.platform == "Android" { media_container.Hub.truncate(5); }
+/// ```
+///
+/// When the script fails, the container is left as it was.
+#[derive(Debug)]
+pub struct ScriptTransform {
+    pub hook: ScriptHook,
+}
+
+#[async_trait]
+impl Transform for ScriptTransform {
+    async fn transform_mediacontainer(
+        &self,
+        container: &mut MediaContainer,
+        plex_client: &PlexClient,
+        options: &PlexContext,
+    ) -> Result<()> {
+        let scripts = &plex_client.config.scripts;
+        let Some(script) = self.hook.script(scripts) else {
+            return Ok(());
+        };
+
+        let result = async {
+            let value = serde_json::to_value(&*container)?;
+            let context = serde_json::to_value(options.without_secrets())?;
+            let value = scripting::run(script, value, context, scripts).await?;
+            anyhow::Ok(serde_json::from_value::<MediaContainer>(value)?)
+        }
+        .await;
+
+        match result {
+            Ok(transformed) => *container = transformed,
+            Err(e) => {
+                tracing::error!(script = %script, error = %e, "Script failed");
+            }
+        }
+
+        Ok(())
+    }
+
+    // The script sees all children, but only if there is one.
+    async fn needs_children(
+        &self,
+        _hub: &MetaData,
+        _hubs: &[MetaData],
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        self.hook.script(&plex_client.config.scripts).is_some()
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use crate::models::MetaData;
+use crate::models::{ChildKind, MetaData};
 use crate::plex::client::PlexClient;
 use crate::plex::models::PlexContext;
 
@@ -16,14 +16,27 @@ impl Transform for SectionDirectoryTransform {
         &self, item: &mut MetaData, _plex_client: &PlexClient,
         _options: &PlexContext,
     ) -> Result<()> {
-        if !item.is_collection_hub() || item.directory.is_empty() {
+        if !item.is_collection_hub() {
             return Ok(());
         }
 
-        let childs = item.metadata.clone();
-        item.directory = vec![];
-        item.video = childs;
+        // Some sections return a directory instead of video. We dont want that
+        for child in item.iter_children_mut() {
+            if child.element == ChildKind::Directory {
+                child.element = ChildKind::Video;
+            }
+        }
 
         return Ok(());
     }
+
+    async fn needs_children(
+        &self,
+        hub: &MetaData,
+        _hubs: &[MetaData],
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        hub.is_collection_hub()
+    }
 }
@@ -4,6 +4,7 @@ use itertools::Itertools;
 
 use crate::models::{MediaContainer, MetaData};
 use crate::plex::client::PlexClient;
+use crate::plex::error::PlexError;
 use crate::plex::models::PlexContext;
 use crate::plex::traits::{Collection, CollectionChildren};
 use crate::transforms::Transform;
@@ -33,16 +34,18 @@ impl Transform for SectionMixTransform {
 
         // These are (or should be anyway) the same for all collections
         let first_id = self.collection_ids[0];
-        let mut collection = match Collection::get(plex_client, first_id).await {
+        let collection = match Collection::get(plex_client, first_id).await {
             Ok(c) => c,
             Err(e) => {
                 tracing::error!(error = %e, "Failed to get collection");
-                return Err(e);
+                return Err(e.into());
             }
         };
-        let exclude_watched = collection.exclude_watched();
+        let exclude_watched = collection.exclude_watched(&plex_client.config);
         let children = collection.children();
-        let collection_title = children.first().unwrap().title.clone();
+        let Some(collection_title) = children.first().map(|c| c.title.clone()) else {
+            return Err(PlexError::NotFound(format!("Collection {}", first_id)).into());
+        };
 
         // Get all children for each collection
         for &id in &self.collection_ids {
@@ -61,17 +64,17 @@ impl Transform for SectionMixTransform {
             if !total_children.is_empty() {
                 total_children = total_children
                     .into_iter()
-                    .interleave(children.children())
+                    .interleave(children.take_children())
                     .collect()
             } else {
-                total_children.append(&mut children.children());
+                total_children.append(children.children_mut());
             }
         }
 
         container.offset = Some(self.offset);
         container.size = Some(self.limit as i64);
         container.total_size = Some(total_size);
-        container.metadata = total_children;
+        *container.children_mut() = total_children;
         container
             .better_on_deck(&collection_title, plex_client)
             .await;
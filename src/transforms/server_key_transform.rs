@@ -0,0 +1,78 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::models::{MediaContainer, MetaData};
+use crate::plex::client::PlexClient;
+use crate::plex::models::PlexContext;
+use crate::plex::upstream::SERVER_PREFIX;
+
+use super::Transform;
+
+/// Rewrites the keys and images of items from one of the other `servers`,
+/// so requests for them reach that server through Replex.
+#[derive(Default, Debug)]
+pub struct ServerKeyTransform {
+    pub server: String,
+}
+
+#[async_trait]
+impl Transform for ServerKeyTransform {
+    async fn transform_mediacontainer(
+        &self,
+        container: &mut MediaContainer,
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> Result<()> {
+        let prefix = format!("{}{}", SERVER_PREFIX, self.server);
+
+        for item in container.iter_children_mut() {
+            prefix_keys(item, &prefix);
+        }
+
+        Ok(())
+    }
+}
+
+fn prefix_keys(item: &mut MetaData, prefix: &str) {
+    for key in [
+        &mut item.key,
+        &mut item.parent_key,
+        &mut item.grandparent_key,
+        &mut item.primary_extra_key,
+        &mut item.thumb,
+        &mut item.art,
+        &mut item.banner,
+        &mut item.theme,
+        &mut item.composite,
+        &mut item.parent_thumb,
+        &mut item.parent_art,
+        &mut item.grandparent_thumb,
+        &mut item.grandparent_art,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        prefix_key(key, prefix);
+    }
+
+    for image in &mut item.images {
+        prefix_key(&mut image.url, prefix);
+    }
+
+    for part in item.media.iter_mut().flat_map(|media| &mut media.parts) {
+        if let Some(key) = &mut part.key {
+            prefix_key(key, prefix);
+        }
+    }
+
+    for child in item.iter_children_mut() {
+        prefix_keys(child, prefix);
+    }
+}
+
+// Only paths on the server itself are rewritten, not URLs of other hosts.
+fn prefix_key(key: &mut String, prefix: &str) {
+    if key.starts_with('/') && !key.starts_with(SERVER_PREFIX) {
+        key.insert_str(0, prefix);
+    }
+}
@@ -1,8 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use crate::config::Config;
-use crate::models::MediaContainer;
+use crate::models::{MediaContainer, MetaData};
 use crate::plex::client::PlexClient;
 use crate::plex::models::PlexContext;
 use crate::plex::traits::CollectionChildren;
@@ -19,13 +18,11 @@ impl Transform for SupplementHubTransform {
         plex_client: &PlexClient,
         _options: &PlexContext,
     ) -> Result<()> {
-        if container.hub.is_empty() {
+        if !container.is_hub() {
             return Ok(());
         }
 
-        let config = Config::load();
-
-        for hub in &mut container.hub {
+        for hub in container.iter_children_mut() {
             if hub.size.unwrap_or_default() == 0 {
                 continue;
             }
@@ -33,28 +30,23 @@ impl Transform for SupplementHubTransform {
             let mut is_supplemented = false;
 
             // In order to manually sort the better_on_deck hubs, we need to get all the children in one go
-            if let (Some(in_progress), Some(next_up)) = (
-                &config.better_on_deck.in_progress,
-                &config.better_on_deck.next_up,
-            ) {
-                if hub.title == *in_progress || hub.title == *next_up {
-                    if let Some(id) = id_from_key(hub.key.as_ref().unwrap()) {
-                        let mut children =
-                            CollectionChildren::get(plex_client, id, None, None)
-                                .await
-                                .unwrap();
+            if is_better_on_deck(hub, plex_client) {
+                if let Some(id) = hub.key.as_deref().and_then(id_from_key) {
+                    let mut children =
+                        CollectionChildren::get(plex_client, id, None, None)
+                            .await?;
 
-                        hub.metadata = children.children();
-                        is_supplemented = true;
-                    }
+                    *hub.children_mut() = children.take_children();
+                    is_supplemented = true;
                 }
             }
 
-            // If the previous step removed items from the hub, we need to supplement it
-            if !is_supplemented {
+            // If the previous step removed items from the hub, we need to supplement it.
+            // Hubs that kept their children as they came aren't missing any.
+            if !is_supplemented && !hub.has_raw_children() {
                 if let Some(size) = hub.size {
                     if size > hub.children().len() as i32 {
-                        if let Some(id) = id_from_key(hub.key.as_ref().unwrap()) {
+                        if let Some(id) = hub.key.as_deref().and_then(id_from_key) {
                             let offset = hub.children().len() as i32;
                             let limit = size - offset;
                             let mut children = CollectionChildren::get(
@@ -65,7 +57,7 @@ impl Transform for SupplementHubTransform {
                             )
                             .await?;
 
-                            hub.metadata.append(&mut children.children())
+                            hub.children_mut().append(children.children_mut())
                         }
                     }
                 }
@@ -74,6 +66,32 @@ impl Transform for SupplementHubTransform {
 
         Ok(())
     }
+
+    async fn needs_children(
+        &self,
+        hub: &MetaData,
+        _hubs: &[MetaData],
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        is_better_on_deck(hub, plex_client)
+    }
+}
+
+// Whether `hub` is one of the better on deck hubs, whose children are
+// fetched again to sort them.
+fn is_better_on_deck(hub: &MetaData, plex_client: &PlexClient) -> bool {
+    let config = &plex_client.config;
+
+    match (
+        &config.better_on_deck.in_progress,
+        &config.better_on_deck.next_up,
+    ) {
+        (Some(in_progress), Some(next_up)) => {
+            hub.title == *in_progress || hub.title == *next_up
+        }
+        _ => false,
+    }
 }
 
 pub fn id_from_key(key: &str) -> Option<i64> {
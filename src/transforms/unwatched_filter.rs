@@ -0,0 +1,23 @@
+use async_trait::async_trait;
+
+use crate::models::MetaData;
+use crate::plex::client::PlexClient;
+use crate::plex::models::PlexContext;
+
+use super::Filter;
+
+/// Removes watched items, see `MetaData::is_watched`.
+#[derive(Default, Debug)]
+pub struct UnwatchedFilter;
+
+#[async_trait]
+impl Filter for UnwatchedFilter {
+    async fn filter_metadata(
+        &self,
+        item: &mut MetaData,
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        !item.is_watched()
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::config::Config;
+use crate::models::MetaData;
+use crate::plex::client::PlexClient;
+use crate::plex::models::PlexContext;
+
+use super::Transform;
+
+/// Removes the watch state of items for `disable_user_state`, and their
+/// episode counts for `disable_leaf_count`. Items of hubs are included, and
+/// are left as JSON when they weren't decoded.
+///
+/// Runs after everything else, since watched items are found by these
+/// fields, see `MetaData::is_watched`.
+#[derive(Default, Debug)]
+pub struct UserStateTransform;
+
+impl UserStateTransform {
+    /// Whether `config` has anything for this transform to remove.
+    pub fn is_enabled(config: &Config) -> bool {
+        config.disable_user_state || config.disable_leaf_count
+    }
+}
+
+#[async_trait]
+impl Transform for UserStateTransform {
+    async fn transform_metadata(
+        &self,
+        item: &mut MetaData,
+        plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> Result<()> {
+        let config = &plex_client.config;
+        if !Self::is_enabled(config) {
+            return Ok(());
+        }
+
+        strip(item, config);
+        if item.has_raw_children() {
+            item.raw_children.remove_fields(&raw_fields(config))?;
+        } else if item.is_hub() {
+            for child in item.children_mut() {
+                strip(child, config);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Children that are still JSON have the fields removed as they are.
+    async fn needs_children(
+        &self,
+        _hub: &MetaData,
+        _hubs: &[MetaData],
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        false
+    }
+}
+
+// Names of the fields that `strip` removes, as Plex writes them in JSON.
+fn raw_fields(config: &Config) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if config.disable_user_state {
+        fields.extend(["userState", "viewCount", "viewOffset"]);
+    }
+    if config.disable_leaf_count {
+        fields.extend(["leafCount", "viewedLeafCount"]);
+    }
+    fields
+}
+
+fn strip(item: &mut MetaData, config: &Config) {
+    if config.disable_user_state {
+        item.user_state = None;
+        item.view_count = None;
+        item.view_offset = None;
+    }
+    if config.disable_leaf_count {
+        item.leaf_count = None;
+        item.viewed_leaf_count = None;
+    }
+}
@@ -23,4 +23,17 @@ pub trait Transform: Send + Sync {
         // Default implementation that does nothing and just returns Ok(())
         Ok(())
     }
+
+    /// Whether the transform reads or changes the children of `hub`, one of
+    /// `hubs`. Children that no transform needs are passed on without being
+    /// decoded, so only say no for hubs whose children are left alone.
+    async fn needs_children(
+        &self,
+        _hub: &MetaData,
+        _hubs: &[MetaData],
+        _plex_client: &PlexClient,
+        _options: &PlexContext,
+    ) -> bool {
+        true
+    }
 }
@@ -1,11 +1,16 @@
 use anyhow::Result;
-use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
 use std::sync::Arc;
 
-use crate::models::MediaContainer;
+use crate::config::PipelineStep;
+use crate::models::{MediaContainer, MetaData};
 use crate::plex::client::PlexClient;
 use crate::plex::models::PlexContext;
-use crate::transforms::{Filter, Transform};
+use crate::plugins;
+use crate::transforms::registry::{self, Step, StepContext};
+use crate::transforms::{
+    Filter, PluginTransform, ScriptHook, ServerKeyTransform, Transform,
+};
 
 #[derive(Clone)]
 pub struct TransformBuilder<'a> {
@@ -39,7 +44,118 @@ impl<'a> TransformBuilder<'a> {
         self
     }
 
+    /// Adds the enabled steps of a pipeline in `pipelines`, in order. Steps
+    /// that can't be made are logged and left out, and `merge_servers` is up
+    /// to the route, see `registry::split_at_merge`.
+    pub fn with_pipeline(
+        mut self, steps: &[PipelineStep], context: &StepContext,
+    ) -> Self {
+        for step in steps.iter().filter(|step| step.enabled) {
+            match registry::build(step, context) {
+                Ok(Step::Transform(transform)) => self.transforms.push(transform),
+                Ok(Step::Filter(filter)) => self.filters.push(filter),
+                Ok(Step::Plugins(hook)) => self = self.with_plugins(hook),
+                Ok(Step::MergeServers) => {}
+                Err(e) => {
+                    tracing::error!(step = %step.name, error = %e, "Failed to add pipeline step");
+                }
+            }
+        }
+        self
+    }
+
+    /// Adds the plugins in `plugins.directory`, as transforms and filters
+    /// depending on the hooks they have, for the route of `hook`.
+    pub fn with_plugins(mut self, hook: ScriptHook) -> Self {
+        let Some(directory) = &self.plex_client.config.plugins.directory else {
+            return self;
+        };
+
+        for plugin in plugins::loaded(directory) {
+            let transform = Arc::new(PluginTransform { plugin, hook });
+            if transform.is_transform() {
+                self.transforms.push(transform.clone());
+            }
+            if transform.is_filter() {
+                self.filters.push(transform);
+            }
+        }
+
+        self
+    }
+
     pub async fn apply_to(&self, container: &mut MediaContainer) -> Result<()> {
+        self.decode_needed_children(container).await?;
+
+        let result = self.apply_transforms(container).await;
+
+        // Items of the other servers have to lead back to them, also when a
+        // transform failed halfway.
+        if let Some(server) = &self.plex_client.server {
+            ServerKeyTransform {
+                server: server.clone(),
+            }
+            .transform_mediacontainer(container, self.plex_client, self.options)
+            .await?;
+        }
+
+        result
+    }
+
+    // Decodes the children of the hubs that are kept as JSON, when one of the
+    // transforms needs them. Filters and the keys of the other servers need
+    // all of them. Hubs are checked a few at a time, as transforms may call
+    // Plex to tell.
+    async fn decode_needed_children(
+        &self,
+        container: &mut MediaContainer,
+    ) -> Result<()> {
+        let needs_all =
+            !self.filters.is_empty() || self.plex_client.server.is_some();
+
+        let concurrency = self.plex_client.config.http.concurrency.max(1);
+        let hubs = container.children();
+        let checks: Vec<_> = hubs
+            .iter()
+            .map(|hub| self.needs_children(hub, hubs, needs_all))
+            .collect();
+        let needed: Vec<bool> = stream::iter(checks)
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        for (i, needed) in needed.into_iter().enumerate() {
+            if needed {
+                container.children_mut()[i].decode_children()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn needs_children(&self, hub: &MetaData, hubs: &[MetaData], needs_all: bool) -> bool {
+        if !hub.has_raw_children() {
+            return false;
+        }
+        if needs_all {
+            return true;
+        }
+
+        for transform in &self.transforms {
+            if transform
+                .needs_children(hub, hubs, self.plex_client, self.options)
+                .await
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn apply_transforms(
+        &self,
+        container: &mut MediaContainer,
+    ) -> Result<()> {
         // Apply transformations to the whole container, maintaining async context.
         for transform in &self.transforms {
             transform
@@ -51,37 +167,206 @@ impl<'a> TransformBuilder<'a> {
                 .await?;
         }
 
-        // Apply transformations and filters for each metadata item within the container
-        let mut i = 0;
-        while i < container.children_mut().len() {
-            let item = &mut container.children_mut()[i];
-
-            // Apply metadata-level transformations
-            for transform in &self.transforms {
-                transform
-                    .transform_metadata(item, self.plex_client, self.options)
-                    .await?;
+        // Containers that a filter rejects are passed on without children.
+        for filter in &self.filters {
+            if !filter
+                .filter_mediacontainer(container, self.plex_client, self.options)
+                .await?
+            {
+                container.set_children(Vec::new());
+                return Ok(());
             }
+        }
 
-            // Determine if item should be included after filters are applied
-            let include = self.filters.iter().all(|filter| {
-                block_on(filter.filter_metadata(
-                    item,
-                    self.plex_client,
-                    self.options,
-                ))
-            });
+        // Apply transformations and filters for each metadata item within the
+        // container, a few items at a time as they may call Plex. Items keep
+        // their order, and items that failed are kept as they are.
+        let concurrency = self.plex_client.config.http.concurrency.max(1);
+        let results: Vec<(MetaData, Result<bool>)> =
+            stream::iter(container.take_children())
+                .map(|mut item| async move {
+                    let include = self.process_metadata(&mut item).await;
+                    (item, include)
+                })
+                .buffered(concurrency)
+                .collect()
+                .await;
 
-            if !include {
-                container.children_mut().remove(i);
-            } else {
-                i += 1;
+        let mut error = None;
+        let mut kept = Vec::with_capacity(results.len());
+        for (item, include) in results {
+            match include {
+                Ok(false) => {}
+                Ok(true) => kept.push(item),
+                Err(e) => {
+                    error.get_or_insert(e);
+                    kept.push(item);
+                }
             }
         }
 
         // Update the container size based on the filtered and transformed children
-        container.size = Some(container.children().len() as i64);
+        container.set_children(kept);
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
 
+    // Applies the metadata-level transformations and then the filters to one
+    // item. Returns whether the item should be kept.
+    async fn process_metadata(&self, item: &mut MetaData) -> Result<bool> {
+        self.transform_metadata(item).await?;
+
+        for filter in &self.filters {
+            if !filter
+                .filter_metadata(item, self.plex_client, self.options)
+                .await
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Applies the metadata-level transformations to one item.
+    async fn transform_metadata(&self, item: &mut MetaData) -> Result<()> {
+        for transform in &self.transforms {
+            transform
+                .transform_metadata(item, self.plex_client, self.options)
+                .await?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use crate::plex::testing;
+
+    use super::*;
+
+    // Marks every item, the first ones last, and fails on `fail`.
+    struct Delayed {
+        fail: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl Transform for Delayed {
+        async fn transform_metadata(
+            &self,
+            item: &mut MetaData,
+            _plex_client: &PlexClient,
+            _options: &PlexContext,
+        ) -> Result<()> {
+            let index: u64 = item.rating_key.as_deref().unwrap_or_default().parse()?;
+            tokio::time::sleep(Duration::from_millis(50 - index * 10)).await;
+
+            if self.fail == Some(item.title.as_str()) {
+                return Err(anyhow!("{} failed", item.title));
+            }
+            item.title = format!("{} done", item.title);
+            Ok(())
+        }
+    }
+
+    struct RejectContainer;
+
+    #[async_trait]
+    impl Filter for RejectContainer {
+        async fn filter_mediacontainer(
+            &self,
+            _item: &MediaContainer,
+            _plex_client: &PlexClient,
+            _options: &PlexContext,
+        ) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    fn container() -> MediaContainer {
+        serde_json::from_value(json!({
+            "size": 4,
+            "Metadata": [
+                { "ratingKey": "1", "title": "One" },
+                { "ratingKey": "2", "title": "Two" },
+                { "ratingKey": "3", "title": "Three" },
+                { "ratingKey": "4", "title": "Four" },
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn plex_client() -> PlexClient {
+        let config = testing::config(json!({ "http": { "concurrency": 4 } }));
+        testing::client(config, |_| testing::response(404, json!({})))
+    }
+
+    fn titles(container: &MediaContainer) -> Vec<&str> {
+        container
+            .children()
+            .iter()
+            .map(|item| item.title.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn items_keep_their_order() {
+        let plex_client = plex_client();
+        let options = PlexContext::default();
+        let mut container = container();
+
+        TransformBuilder::new(&plex_client, &options)
+            .with_transform(Delayed { fail: None })
+            .apply_to(&mut container)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            titles(&container),
+            ["One done", "Two done", "Three done", "Four done"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_containers_lose_their_children() {
+        let plex_client = plex_client();
+        let options = PlexContext::default();
+        let mut container = container();
+
+        TransformBuilder::new(&plex_client, &options)
+            .with_transform(Delayed { fail: None })
+            .with_filter(RejectContainer)
+            .apply_to(&mut container)
+            .await
+            .unwrap();
+
+        assert!(container.children().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_items_are_kept() {
+        let plex_client = plex_client();
+        let options = PlexContext::default();
+        let mut container = container();
+
+        let result = TransformBuilder::new(&plex_client, &options)
+            .with_transform(Delayed { fail: Some("Two") })
+            .apply_to(&mut container)
+            .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "Two failed");
+        assert_eq!(
+            titles(&container),
+            ["One done", "Two", "Three done", "Four done"]
+        );
+    }
+}
@@ -4,7 +4,6 @@ use anyhow::Result;
 use futures::future::join_all;
 use mime::Mime;
 use multimap::MultiMap;
-// use reqwest_retry::{default_on_request_failure, Retryable, RetryableStrategy};
 use salvo::http::HeaderValue;
 use url::Url;
 use yaserde::ser::to_string as to_xml_str;
@@ -19,32 +18,17 @@ use crate::models::{ContentType, DisplayField, DisplayImage, MediaContainer, Met
 use crate::plex::client::PlexClient;
 use crate::plex::traits::MetaDataChildren;
 
-// struct Retry401;
-// impl RetryableStrategy for Retry401 {
-//     fn handle(
-//         &self,
-//         res: &std::result::Result<reqwest::Response, reqwest_middleware::Error>,
-//     ) -> Option<Retryable> {
-//         match res {
-//             Ok(success) if success.status() == 401 => Some(Retryable::Transient),
-//             Ok(_success) => None,
-//             // otherwise do not retry a successful request
-//             Err(error) => default_on_request_failure(error),
-//         }
-//     }
-// }
-
 async fn get_last_viewed_at(plex_client: &PlexClient, initial_rating_key: &str) -> Option<i64> {
     let mut queue = VecDeque::from(vec![initial_rating_key.to_string()]);
 
     while let Some(rating_key) = queue.pop_front() {
-        let mut children = match MetaDataChildren::get(plex_client, &rating_key).await {
+        let children = match MetaDataChildren::get(plex_client, &rating_key).await {
             Ok(data) => data,
             Err(_) => continue,
         };
 
         // Iterate backwards over the children
-        for child in children.children().iter().rev() {
+        for child in children.iter_children().rev() {
             if let Some(last_viewed_at) = child.last_viewed_at {
                 // Early exit since we found a recent watched date
                 return Some(last_viewed_at);
@@ -113,14 +97,14 @@ pub fn get_collection_id_from_hub(hub: &MetaData) -> i64 {
         .clone()
         .unwrap()
         .split('.')
-        .last()
+        .next_back()
         .unwrap()
         .parse()
         .unwrap()
 }
 
 pub fn url_from_request(req: &SalvoRequest) -> Url {
-    let config = Config::load();
+    let config = Config::for_request(req);
     let path_and_query = req
         .uri()
         .path_and_query()